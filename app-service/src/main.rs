@@ -1,22 +1,109 @@
-use std::env;
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use askama::Template;
 use axum::{
-    http::StatusCode,
-    response::{Html, IntoResponse},
-    routing::get,
+    async_trait,
+    extract::{FromRequestParts, Query, State},
+    http::{request::Parts, StatusCode},
+    response::{Html, IntoResponse, Redirect},
+    routing::{get, post},
     Json, Router,
 };
-use axum_extra::extract::CookieJar;
-use serde::Serialize;
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
 use tower_http::services::ServeDir;
 
+// Holds the state and PKCE verifier of a login in progress
+const LOGIN_COOKIE: &str = "oauth_login";
+const ACCESS_TOKEN_COOKIE: &str = "access_token";
+// Tokens with made up `kid`s must not make this service hammer the auth
+// service, so its keys are fetched at most this often
+const JWKS_MIN_FETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct AppState {
+    jwks: Arc<RwLock<JwksCache>>,
+    oauth_client: Arc<OAuthClient>,
+}
+
+// Public keys published by the auth service, fetched on first use
+#[derive(Default)]
+struct JwksCache {
+    jwks: Option<JwkSet>,
+    fetched_at: Option<Instant>,
+}
+
+impl JwksCache {
+    // Fetch when there are no keys yet, or when asked to look for a new `kid`
+    fn needs_fetch(&self, refresh: bool) -> bool {
+        let recently_fetched = self
+            .fetched_at
+            .is_some_and(|fetched_at| fetched_at.elapsed() < JWKS_MIN_FETCH_INTERVAL);
+
+        !recently_fetched && (refresh || self.jwks.is_none())
+    }
+}
+
+// This service's registration with the auth service, which users log in
+// through with the OAuth 2.0 authorization code flow
+struct OAuthClient {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+}
+
+impl OAuthClient {
+    fn from_env() -> Self {
+        let app_url = match env::var("APP_SERVICE_URL") {
+            Ok(app_url) if !app_url.is_empty() => app_url,
+            _ => "http://localhost:8000".to_owned(),
+        };
+
+        Self {
+            client_id: required_env("OAUTH_CLIENT_ID"),
+            client_secret: required_env("OAUTH_CLIENT_SECRET"),
+            redirect_uri: format!("{}/callback", app_url),
+        }
+    }
+}
+
+// Compose passes unset variables as empty strings, so those count as unset
+fn required_env(name: &str) -> String {
+    match env::var(name) {
+        Ok(value) if !value.is_empty() => value,
+        _ => panic!("{} must be set.", name),
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    let state = AppState {
+        jwks: Arc::default(),
+        oauth_client: Arc::new(OAuthClient::from_env()),
+    };
+
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route("/login", get(login))
+        .route("/callback", get(callback))
+        .route("/logout", post(logout))
+        .route("/protected", get(protected))
+        .route("/admin", get(admin))
+        .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
@@ -26,59 +113,380 @@ async fn main() {
 
 #[derive(Template)]
 #[template(path = "index.html")]
-struct IndexTemplate {
-    login_link: String,
-    logout_link: String,
-}
+struct IndexTemplate;
 
 async fn root() -> impl IntoResponse {
-    let mut address = env::var("AUTH_SERVICE_IP").unwrap_or("localhost".to_owned());
-    if address.is_empty() {
-        address = "localhost".to_owned();
+    Html(IndexTemplate.render().unwrap())
+}
+
+// Send the user to the auth service to log in. The state and PKCE verifier
+// stay in a short-lived cookie, so the callback can check it belongs to a
+// login this browser started.
+async fn login(State(state): State<AppState>, jar: CookieJar) -> impl IntoResponse {
+    let login_state = random_string();
+    let code_verifier = random_string();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    let mut url = Url::parse(&format!("http://{}:3000/authorize", auth_service_address())).unwrap();
+    url.query_pairs_mut().extend_pairs([
+        ("response_type", "code"),
+        ("client_id", &state.oauth_client.client_id),
+        ("redirect_uri", &state.oauth_client.redirect_uri),
+        ("code_challenge", &code_challenge),
+        ("code_challenge_method", "S256"),
+        ("state", &login_state),
+    ]);
+
+    let cookie = Cookie::build((LOGIN_COOKIE, format!("{}:{}", login_state, code_verifier)))
+        .path("/")
+        .http_only(true)
+        // Lax, so the cookie comes back with the redirect from the auth service
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(10))
+        .build();
+
+    (jar.add(cookie), Redirect::to(url.as_str()))
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+// The auth service sends users back here with an authorization code, which
+// is exchanged for an access token with this service's secret
+async fn callback(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Query(params): Query<CallbackParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let login = jar
+        .get(LOGIN_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let jar = jar.remove(Cookie::build(LOGIN_COOKIE).path("/"));
+
+    let (login_state, code_verifier) = login.split_once(':').ok_or(StatusCode::BAD_REQUEST)?;
+    if params.state.as_deref() != Some(login_state) {
+        return Err(StatusCode::BAD_REQUEST);
     }
-    let login_link = format!("http://{}:3000", address);
-    let logout_link = format!("http://{}:3000/logout", address);
 
-    let template = IndexTemplate {
-        login_link,
-        logout_link,
+    // No code means the user denied access
+    let Some(code) = params.code else {
+        return Ok((jar, Redirect::to("/")));
     };
-    Html(template.render().unwrap())
+
+    let token = exchange_code(&state.oauth_client, &code, code_verifier).await?;
+    let cookie = Cookie::build((ACCESS_TOKEN_COOKIE, token.access_token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(token.expires_in))
+        .build();
+
+    Ok((jar.add(cookie), Redirect::to("/")))
+}
+
+async fn exchange_code(
+    oauth_client: &OAuthClient,
+    code: &str,
+    code_verifier: &str,
+) -> Result<TokenResponse, StatusCode> {
+    let url = format!("http://{}:3000/token", auth_service_host_name());
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .basic_auth(&oauth_client.client_id, Some(&oauth_client.client_secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &oauth_client.redirect_uri),
+            ("code_verifier", code_verifier),
+        ])
+        .send()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match response.status() {
+        reqwest::StatusCode::OK => response
+            .json::<TokenResponse>()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+        // The code has expired or was already used
+        reqwest::StatusCode::BAD_REQUEST => Err(StatusCode::UNAUTHORIZED),
+        _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+// Access tokens can't be revoked, so this only forgets the token. The user
+// stays logged in to the auth service itself.
+async fn logout(jar: CookieJar) -> impl IntoResponse {
+    jar.remove(Cookie::build(ACCESS_TOKEN_COOKIE).path("/"))
+}
+
+// 32 random bytes, base64url encoded, for the state and PKCE verifier
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Where browsers reach the auth service
+fn auth_service_address() -> String {
+    match env::var("AUTH_SERVICE_IP") {
+        Ok(address) if !address.is_empty() => address,
+        _ => "localhost".to_owned(),
+    }
+}
+
+// Where this service reaches the auth service
+fn auth_service_host_name() -> String {
+    env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned())
+}
+
+async fn protected(_user: AuthenticatedUser) -> impl IntoResponse {
+    Json(ProtectedRouteResponse {
+        img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+struct AdminTemplate {
+    email: String,
+}
+
+async fn admin(VerifiedUser(user): VerifiedUser) -> Result<impl IntoResponse, StatusCode> {
+    user.require_role("admin")?;
+
+    let template = AdminTemplate { email: user.email };
+    Ok(Html(template.render().unwrap()))
+}
+
+// The user a valid auth token belongs to. Routes that take this extractor
+// respond with 401 to requests without a valid token. A revoked token is
+// accepted until it expires; see `VerifiedUser`.
+#[derive(Debug, Deserialize)]
+struct AuthenticatedUser {
+    email: String,
+    roles: Vec<String>,
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
-        Some(cookie) => cookie,
-        None => {
-            return StatusCode::UNAUTHORIZED.into_response();
+// An `AuthenticatedUser` whose token the auth service has also confirmed is
+// not revoked. Only the auth service knows which tokens were revoked before
+// they expire: banned on logout, ended by a password change or reset, or
+// belonging to a deleted account.
+struct VerifiedUser(AuthenticatedUser);
+
+// The claims of a verified access token that make up an `AuthenticatedUser`
+#[derive(Deserialize)]
+struct AccessTokenClaims {
+    sub: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+impl From<AccessTokenClaims> for AuthenticatedUser {
+    fn from(claims: AccessTokenClaims) -> Self {
+        Self {
+            email: claims.sub,
+            roles: claims.roles,
         }
-    };
+    }
+}
+
+impl AuthenticatedUser {
+    // Responds with 403 unless the user has `role`
+    fn require_role(&self, role: &str) -> Result<(), StatusCode> {
+        if self.roles.iter().any(|r| r == role) {
+            Ok(())
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        authenticate(parts, state, false).await
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for VerifiedUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        authenticate(parts, state, true).await.map(VerifiedUser)
+    }
+}
+
+// Check the request's access token against the auth service's published
+// keys, and with the auth service itself if `check_revoked`
+async fn authenticate(
+    parts: &Parts,
+    state: &AppState,
+    check_revoked: bool,
+) -> Result<AuthenticatedUser, StatusCode> {
+    let token = access_token(parts)?;
+
+    let auth_hostname = auth_service_host_name();
+    // Access tokens are minted for the client they were issued to
+    let audience = &state.oauth_client.client_id;
+
+    let kid = jsonwebtoken::decode_header(&token)
+        .ok()
+        .and_then(|header| header.kid);
 
+    let mut jwks = get_jwks(state, &auth_hostname, false)
+        .await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // An unknown `kid` means the auth service has rotated its signing key,
+    // or the token is made up. Without the key the token is rejected.
+    if let Some(kid) = &kid {
+        if !jwks.keys.is_empty() && jwks.find(kid).is_none() {
+            jwks = get_jwks(state, &auth_hostname, true)
+                .await
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+        }
+    }
+
+    // Forged and expired tokens are turned away here without asking the auth
+    // service. Revoked tokens are only caught by asking it.
+    if !jwks.keys.is_empty() {
+        let issuer = env::var("AUTH_SERVICE_ISSUER").unwrap_or("auth-service".to_owned());
+        let claims = verify_token_locally(&token, kid.as_deref(), &jwks, &issuer, audience)
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        if !check_revoked {
+            return Ok(claims.into());
+        }
+    }
+
+    // The auth service publishes no keys when it signs tokens with a shared
+    // secret, so then every token goes to it
+    verify_token_remotely(&token, audience, &auth_hostname)
+        .await?
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+fn access_token(parts: &Parts) -> Result<String, StatusCode> {
+    CookieJar::from_headers(&parts.headers)
+        .get(ACCESS_TOKEN_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+// The cached keys, fetched first if needed. A failed fetch keeps the keys
+// fetched before, if any.
+async fn get_jwks(state: &AppState, auth_hostname: &str, refresh: bool) -> Option<JwkSet> {
+    {
+        let cache = state.jwks.read().await;
+        if !cache.needs_fetch(refresh) {
+            return cache.jwks.clone();
+        }
+    }
+
+    let mut cache = state.jwks.write().await;
+    // Another request may have fetched the keys while this one waited
+    if cache.needs_fetch(refresh) {
+        cache.fetched_at = Some(Instant::now());
+        if let Ok(jwks) = fetch_jwks(auth_hostname).await {
+            cache.jwks = Some(jwks);
+        }
+    }
+
+    cache.jwks.clone()
+}
+
+async fn fetch_jwks(auth_hostname: &str) -> Result<JwkSet, reqwest::Error> {
+    let url = format!("http://{}:3000/.well-known/jwks.json", auth_hostname);
+
+    // Requests wait for the fetch, so it must not hang
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// Check the token's signature and claims against the published keys,
+// returning its claims if it is valid
+fn verify_token_locally(
+    token: &str,
+    kid: Option<&str>,
+    jwks: &JwkSet,
+    issuer: &str,
+    audience: &str,
+) -> Option<AccessTokenClaims> {
+    let jwk = kid.and_then(|kid| jwks.find(kid))?;
+
+    let algorithm = jwk
+        .common
+        .key_algorithm
+        .and_then(|key_algorithm| key_algorithm.to_string().parse::<Algorithm>().ok())?;
+
+    let mut validation = Validation::new(algorithm);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    validation.validate_nbf = true;
+
+    let decoding_key = DecodingKey::from_jwk(jwk).ok()?;
+    jsonwebtoken::decode::<AccessTokenClaims>(token, &decoding_key, &validation)
+        .ok()
+        .map(|token_data| token_data.claims)
+}
+
+async fn verify_token_remotely(
+    token: &str,
+    audience: &str,
+    auth_hostname: &str,
+) -> Result<Option<AuthenticatedUser>, StatusCode> {
     let api_client = reqwest::Client::builder().build().unwrap();
 
     let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
+        "token": token,
+        "audience": audience,
     });
 
-    let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
     let response = match api_client.post(&url).json(&verify_token_body).send().await {
         Ok(response) => response,
         Err(_) => {
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
     match response.status() {
-        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
-        reqwest::StatusCode::OK => Json(ProtectedRouteResponse {
-            img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
-        })
-        .into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => Ok(None),
+        reqwest::StatusCode::OK => response
+            .json::<AuthenticatedUser>()
+            .await
+            .map(Some)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+        _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
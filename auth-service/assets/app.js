@@ -6,6 +6,78 @@ const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
 
+const forgotPasswordSection = document.getElementById("forgot-password-section");
+const resetPasswordSection = document.getElementById("reset-password-section");
+const forgotPasswordLink = document.getElementById("forgot-password-link");
+const forgotPasswordLoginLink = document.getElementById("forgot-password-login-link");
+
+const totpSection = document.getElementById("totp-section");
+const totpLoginLink = document.getElementById("totp-login-link");
+
+const consentSection = document.getElementById("consent-section");
+
+function showSection(section) {
+    for (const s of [loginSection, twoFASection, signupSection, forgotPasswordSection, resetPasswordSection, totpSection, consentSection]) {
+        s.style.display = s === section ? "block" : "none";
+    }
+}
+
+function showError(alertElement, response) {
+    response.json().then(data => {
+        let error_msg = data.error;
+        if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+            alertElement.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+            alertElement.style.display = "block";
+        } else {
+            alertElement.style.display = "none";
+        }
+    });
+}
+
+// Recovery codes are only ever shown once, when 2FA is turned on
+function recoveryCodesMessage(recoveryCodes) {
+    if (recoveryCodes === undefined) {
+        return "";
+    }
+    return "\n\nSave these recovery codes. Each one can be used once instead of a 2FA code:\n" + recoveryCodes.join("\n");
+}
+
+// Password reset emails link here with the token in the query string
+const resetToken = new URLSearchParams(window.location.search).get("reset_token");
+if (resetToken !== null) {
+    showSection(resetPasswordSection);
+}
+
+// OAuth clients send users here through /authorize, with the original
+// request in the query string, to log in and allow the client
+let authorizeQuery = new URLSearchParams(window.location.search).get("authorize");
+
+// Users logging in for a client go back to /authorize, which sends them on
+// to the client or asks them to allow it first
+function loggedIn() {
+    if (authorizeQuery !== null) {
+        window.location.assign("/authorize?" + authorizeQuery);
+        return;
+    }
+    alert("You have successfully logged in.");
+    showSection(totpSection);
+}
+
+totpLoginLink.addEventListener("click", (e) => {
+    e.preventDefault();
+    showSection(loginSection);
+});
+
+forgotPasswordLink.addEventListener("click", (e) => {
+    e.preventDefault();
+    showSection(forgotPasswordSection);
+});
+
+forgotPasswordLoginLink.addEventListener("click", (e) => {
+    e.preventDefault();
+    showSection(loginSection);
+});
+
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
@@ -53,6 +125,9 @@ loginButton.addEventListener("click", (e) => {
             TwoFAForm.email.value = email;
             response.json().then(data => {
                 TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+                document.getElementById("2fa-hint").textContent = data.twoFactorMethod === "totp"
+                    ? "Enter the code from your authenticator app."
+                    : "Enter the code we emailed you.";
             });
 
             loginForm.email.value = "";
@@ -66,7 +141,7 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
+            loggedIn();
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -104,7 +179,9 @@ signupButton.addEventListener("click", (e) => {
             signupForm.password.value = "";
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
+            response.json().then(data => {
+                alert("You have successfully created a user." + recoveryCodesMessage(data.recoveryCodes));
+            });
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -145,10 +222,7 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
-            loginSection.style.display = "block";
-            twoFASection.style.display = "none";
-            signupSection.style.display = "none";
+            loggedIn();
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -161,4 +235,321 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+const forgotPasswordForm = document.getElementById("forgot-password-form");
+const forgotPasswordButton = document.getElementById("forgot-password-form-submit");
+const forgotPasswordErrAlert = document.getElementById("forgot-password-err-alert");
+
+forgotPasswordButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = forgotPasswordForm.email.value;
+
+    fetch('/forgot-password', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.ok) {
+            forgotPasswordForm.email.value = "";
+            forgotPasswordErrAlert.style.display = "none";
+            alert("If the address belongs to an account, a reset link has been sent.");
+            showSection(loginSection);
+        } else {
+            showError(forgotPasswordErrAlert, response);
+        }
+    });
+});
+
+const resetPasswordForm = document.getElementById("reset-password-form");
+const resetPasswordButton = document.getElementById("reset-password-form-submit");
+const resetPasswordErrAlert = document.getElementById("reset-password-err-alert");
+
+resetPasswordButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const password = resetPasswordForm.password.value;
+
+    fetch('/reset-password', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: resetToken, password }),
+    }).then(response => {
+        if (response.ok) {
+            resetPasswordForm.password.value = "";
+            resetPasswordErrAlert.style.display = "none";
+            alert("Your password has been reset.");
+            // Drop the used token from the address bar
+            window.history.replaceState(null, "", "/");
+            showSection(loginSection);
+        } else {
+            showError(resetPasswordErrAlert, response);
+        }
+    });
+});
+
+const totpEnrollForm = document.getElementById("totp-enroll-form");
+const totpEnrollButton = document.getElementById("totp-enroll-form-submit");
+const totpConfirmForm = document.getElementById("totp-confirm-form");
+const totpConfirmButton = document.getElementById("totp-confirm-form-submit");
+const totpErrAlert = document.getElementById("totp-err-alert");
+
+totpEnrollButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const password = totpEnrollForm.password.value;
+
+    fetch('/totp/enroll', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ password }),
+    }).then(response => {
+        if (response.ok) {
+            response.json().then(data => {
+                document.getElementById("totp-qr-code").src = `data:image/png;base64,${data.qrCode}`;
+                document.getElementById("totp-secret").textContent = data.secret;
+            });
+            totpEnrollForm.password.value = "";
+            totpErrAlert.style.display = "none";
+            totpEnrollForm.style.display = "none";
+            totpConfirmForm.style.display = "block";
+        } else {
+            showError(totpErrAlert, response);
+        }
+    });
+});
+
+totpConfirmButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const code = totpConfirmForm.code.value;
+
+    fetch('/totp/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ code }),
+    }).then(response => {
+        if (response.ok) {
+            totpConfirmForm.code.value = "";
+            totpErrAlert.style.display = "none";
+            totpConfirmForm.style.display = "none";
+            totpEnrollForm.style.display = "block";
+            response.json().then(data => {
+                alert("Your authenticator app is set up. You will need a code from it to log in." + recoveryCodesMessage(data.recoveryCodes));
+            });
+            showSection(loginSection);
+        } else {
+            showError(totpErrAlert, response);
+        }
+    });
+});
+
+// The passkey options and credentials are exchanged as JSON, with binary
+// fields base64url encoded
+const passkeyLoginButton = document.getElementById("passkey-login-submit");
+
+passkeyLoginButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    fetch('/passkey/login/start', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(async response => {
+        if (!response.ok) {
+            showError(loginErrAlter, response);
+            return;
+        }
+
+        const data = await response.json();
+        const publicKey = PublicKeyCredential.parseRequestOptionsFromJSON(data.publicKey);
+        const credential = await navigator.credentials.get({ publicKey });
+
+        const finishResponse = await fetch('/passkey/login/finish', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ challengeId: data.challengeId, credential: credential.toJSON() }),
+        });
+        if (finishResponse.ok) {
+            loginForm.email.value = "";
+            loginForm.password.value = "";
+            loginErrAlter.style.display = "none";
+            loggedIn();
+        } else {
+            showError(loginErrAlter, finishResponse);
+        }
+    }).catch(error => {
+        // The user cancelled, or the browser does not support passkeys
+        loginErrAlter.innerHTML = `<span><strong>Error: </strong>${error.message}</span>`;
+        loginErrAlter.style.display = "block";
+    });
+});
+
+const magicLinkButton = document.getElementById("magic-link-submit");
+
+magicLinkButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    fetch('/login/magic-link', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.ok) {
+            // /verify-2fa needs the address again if the user has 2FA
+            localStorage.setItem("magicLinkEmail", email);
+            // The link opens a new page, which needs the client's request too
+            if (authorizeQuery !== null) {
+                localStorage.setItem("magicLinkAuthorize", authorizeQuery);
+            }
+            loginErrAlter.style.display = "none";
+            alert("If the address belongs to an account, a login link has been sent.");
+        } else {
+            showError(loginErrAlter, response);
+        }
+    });
+});
+
+// Login links land here with the token in the query string. It is only
+// used once the page has loaded, so link scanners cannot use it up.
+const magicToken = new URLSearchParams(window.location.search).get("magic_token");
+if (magicToken !== null) {
+    // Keeps the used token out of the address bar and history
+    history.replaceState(null, "", window.location.pathname);
+    authorizeQuery = localStorage.getItem("magicLinkAuthorize");
+    localStorage.removeItem("magicLinkAuthorize");
+
+    fetch('/login/magic-link/verify', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: magicToken }),
+    }).then(response => {
+        if (response.status === 206) {
+            // The link may have been opened in another browser
+            TwoFAForm.email.value = localStorage.getItem("magicLinkEmail") ?? prompt("Enter your email address");
+            response.json().then(data => {
+                TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+                document.getElementById("2fa-hint").textContent = data.twoFactorMethod === "totp"
+                    ? "Enter the code from your authenticator app."
+                    : "Enter the code we emailed you.";
+            });
+            showSection(twoFASection);
+        } else if (response.status === 200) {
+            loggedIn();
+        } else {
+            showError(loginErrAlter, response);
+        }
+        localStorage.removeItem("magicLinkEmail");
+    });
+}
+
+const passkeyRegisterForm = document.getElementById("passkey-register-form");
+const passkeyRegisterButton = document.getElementById("passkey-register-form-submit");
+const passkeyErrAlert = document.getElementById("passkey-err-alert");
+
+passkeyRegisterButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const password = passkeyRegisterForm.password.value;
+
+    fetch('/passkey/register/start', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ password }),
+    }).then(async response => {
+        if (!response.ok) {
+            showError(passkeyErrAlert, response);
+            return;
+        }
+
+        const data = await response.json();
+        const publicKey = PublicKeyCredential.parseCreationOptionsFromJSON(data.publicKey);
+        const credential = await navigator.credentials.create({ publicKey });
+
+        const finishResponse = await fetch('/passkey/register/finish', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ challengeId: data.challengeId, credential: credential.toJSON() }),
+        });
+        if (finishResponse.ok) {
+            passkeyRegisterForm.password.value = "";
+            passkeyErrAlert.style.display = "none";
+            alert("Your passkey is set up. You can use it to log in without your password.");
+        } else {
+            showError(passkeyErrAlert, finishResponse);
+        }
+    }).catch(error => {
+        passkeyErrAlert.innerHTML = `<span><strong>Error: </strong>${error.message}</span>`;
+        passkeyErrAlert.style.display = "block";
+    });
+});
+
+const consentErrAlert = document.getElementById("consent-err-alert");
+
+// Magic link logins go back to /authorize once the link has been used
+if (authorizeQuery !== null && magicToken === null) {
+    fetch('/authorize/consent?' + authorizeQuery).then(response => {
+        if (response.ok) {
+            response.json().then(data => {
+                // Client names are chosen by whoever registered the client
+                document.getElementById("consent-client-name").textContent = data.clientName;
+            });
+            showSection(consentSection);
+        } else if (response.status !== 401) {
+            // Not logged in yet is the only error the login form can fix
+            showError(loginErrAlter, response);
+        }
+    });
+}
+
+function decideConsent(approved) {
+    const params = Object.fromEntries(new URLSearchParams(authorizeQuery));
+
+    fetch('/authorize/consent', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ ...params, approved }),
+    }).then(response => {
+        if (response.ok) {
+            response.json().then(data => window.location.assign(data.redirectUri));
+        } else {
+            showError(consentErrAlert, response);
+        }
+    });
+}
+
+document.getElementById("consent-allow").addEventListener("click", (e) => {
+    e.preventDefault();
+    decideConsent(true);
+});
+
+document.getElementById("consent-deny").addEventListener("click", (e) => {
+    e.preventDefault();
+    decideConsent(false);
+});
@@ -0,0 +1,8 @@
+-- Signing keys added by /admin/rotate-signing-key, see `domain::JwtKeyStore`.
+-- Private keys are PKCS#8 PEM, or base64url secrets for HS256.
+CREATE TABLE IF NOT EXISTS jwt_keys (
+    kid TEXT NOT NULL PRIMARY KEY,
+    algorithm TEXT NOT NULL,
+    private_key TEXT NOT NULL,
+    activates_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Space separated list of roles, see `domain::role`
+ALTER TABLE users ADD COLUMN roles TEXT NOT NULL DEFAULT 'user';
@@ -0,0 +1,3 @@
+-- Stamped into every token the user is issued. Incremented to end all of the
+-- user's sessions, see `utils::auth::validate_token`.
+ALTER TABLE users ADD COLUMN session_version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Unix timestamp of a soft deletion, see `UserStore::soft_delete_user`
+ALTER TABLE users ADD COLUMN deleted_at BIGINT;
@@ -0,0 +1,3 @@
+-- Base32 authenticator app secrets, see `UserStore::confirm_totp_secret`
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_pending_secret TEXT;
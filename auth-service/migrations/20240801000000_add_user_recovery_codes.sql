@@ -0,0 +1,2 @@
+-- Space separated hashes of unused 2FA recovery codes, see `domain::recovery_code`
+ALTER TABLE users ADD COLUMN recovery_codes TEXT NOT NULL DEFAULT '';
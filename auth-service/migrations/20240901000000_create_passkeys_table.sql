@@ -0,0 +1,9 @@
+-- Passkeys serialized as JSON by webauthn-rs, see `domain::PasskeyStore`.
+-- Credential IDs are base64url encoded.
+CREATE TABLE IF NOT EXISTS passkeys (
+    credential_id TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL REFERENCES users (email) ON DELETE CASCADE,
+    passkey TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS passkeys_email ON passkeys (email);
@@ -0,0 +1,16 @@
+-- Applications that log users in through /authorize, see
+-- `domain::OAuthClientStore`. Redirect URIs are a JSON array. Secrets are
+-- stored as base64url SHA-256 hashes, and are null for public clients.
+CREATE TABLE IF NOT EXISTS oauth_clients (
+    client_id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    redirect_uris TEXT NOT NULL,
+    secret_hash TEXT
+);
+
+-- Users who have allowed a client to log them in
+CREATE TABLE IF NOT EXISTS oauth_consents (
+    client_id TEXT NOT NULL REFERENCES oauth_clients (client_id) ON DELETE CASCADE,
+    email TEXT NOT NULL REFERENCES users (email) ON DELETE CASCADE,
+    PRIMARY KEY (client_id, email)
+);
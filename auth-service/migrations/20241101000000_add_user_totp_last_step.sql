@@ -0,0 +1,2 @@
+-- Time step of the last accepted authenticator app code, see `UserStore::use_totp_step`
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
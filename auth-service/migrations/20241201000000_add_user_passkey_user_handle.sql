@@ -0,0 +1,2 @@
+-- Random WebAuthn user handle, see `UserStore::passkey_user_handle`
+ALTER TABLE users ADD COLUMN passkey_user_handle TEXT;
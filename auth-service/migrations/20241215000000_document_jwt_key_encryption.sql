@@ -0,0 +1,5 @@
+-- Private keys in `jwt_keys` are not stored as the plain PEM or secret
+-- described in 20240315000000_create_jwt_keys_table.sql. Each one is
+-- encrypted with AES-256-GCM under `jwt.key_encryption_key`, with its kid as
+-- associated data, and stored as base64url(nonce || ciphertext). The column
+-- itself is unchanged.
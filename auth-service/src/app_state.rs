@@ -1,19 +1,87 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::domain::UserStore;
+use webauthn_rs::Webauthn;
+
+use crate::domain::{
+    AuthorizationCodeStore, BannedTokenStore, EmailClient, EmailTokenStore, JwtKeyStore,
+    OAuthClientStore, PasskeyChallengeStore, PasskeyStore, RateLimitStore, RefreshTokenStore,
+    TwoFACodeStore, UserStore,
+};
+use crate::settings::Settings;
+use crate::utils::jwt_keys::JwtKeyring;
 
 // Using a type alias to improve readability!
 pub type UserStoreType = Arc<RwLock<dyn UserStore>>;
+pub type PasskeyStoreType = Arc<RwLock<dyn PasskeyStore>>;
+pub type OAuthClientStoreType = Arc<RwLock<dyn OAuthClientStore>>;
+pub type JwtKeyStoreType = Arc<RwLock<dyn JwtKeyStore>>;
+pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore>>;
+pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore>>;
+pub type RateLimitStoreType = Arc<RwLock<dyn RateLimitStore>>;
+pub type EmailTokenStoreType = Arc<RwLock<dyn EmailTokenStore>>;
+pub type PasskeyChallengeStoreType = Arc<RwLock<dyn PasskeyChallengeStore>>;
+pub type AuthorizationCodeStoreType = Arc<RwLock<dyn AuthorizationCodeStore>>;
+pub type EmailClientType = Arc<dyn EmailClient>;
+pub type JwtKeyringType = Arc<RwLock<JwtKeyring>>;
+pub type WebauthnType = Arc<Webauthn>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub oauth_client_store: OAuthClientStoreType,
+    pub jwt_key_store: JwtKeyStoreType,
+    pub banned_token_store: BannedTokenStoreType,
+    pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub rate_limit_store: RateLimitStoreType,
+    pub email_token_store: EmailTokenStoreType,
+    pub passkey_challenge_store: PasskeyChallengeStoreType,
+    pub authorization_code_store: AuthorizationCodeStoreType,
+    pub email_client: EmailClientType,
+    pub jwt_keyring: JwtKeyringType,
+    pub webauthn: WebauthnType,
+    pub settings: Arc<Settings>,
 }
 
 impl AppState {
-    pub fn new(user_store: UserStoreType) -> Self {
-        Self { user_store }
+    // Every store is a separate argument so each can be swapped on its own
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        user_store: UserStoreType,
+        passkey_store: PasskeyStoreType,
+        oauth_client_store: OAuthClientStoreType,
+        jwt_key_store: JwtKeyStoreType,
+        banned_token_store: BannedTokenStoreType,
+        two_fa_code_store: TwoFACodeStoreType,
+        refresh_token_store: RefreshTokenStoreType,
+        rate_limit_store: RateLimitStoreType,
+        email_token_store: EmailTokenStoreType,
+        passkey_challenge_store: PasskeyChallengeStoreType,
+        authorization_code_store: AuthorizationCodeStoreType,
+        email_client: EmailClientType,
+        jwt_keyring: JwtKeyringType,
+        webauthn: WebauthnType,
+        settings: Arc<Settings>,
+    ) -> Self {
+        Self {
+            user_store,
+            passkey_store,
+            oauth_client_store,
+            jwt_key_store,
+            banned_token_store,
+            two_fa_code_store,
+            refresh_token_store,
+            rate_limit_store,
+            email_token_store,
+            passkey_challenge_store,
+            authorization_code_store,
+            email_client,
+            jwt_keyring,
+            webauthn,
+            settings,
+        }
     }
 }
-
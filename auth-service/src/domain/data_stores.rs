@@ -1,10 +1,101 @@
-use super::{Email, Password, User};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+    AuthenticationResult, Passkey, PasskeyAuthentication, PasskeyRegistration,
+};
+
+use super::{
+    ClientId, CodeChallenge, Email, OAuthClient, OpaqueToken, OpaqueTokenKind, Password,
+    PasswordHash, PasswordHashError, RecoveryCodeHash, RedirectUri, TotpSecret, User,
+};
 
 #[async_trait::async_trait]
 pub trait UserStore: Send + Sync {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+    async fn validate_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError> {
+        verify_user_password(self.get_user(email).await, password).await
+    }
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError>;
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError>;
+    // An authenticator app secret waiting to be confirmed, replacing any
+    // previous one. The user's confirmed secret is left as it is.
+    async fn set_pending_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: TotpSecret,
+    ) -> Result<(), UserStoreError>;
+    async fn get_pending_totp_secret(
+        &self,
+        email: &Email,
+    ) -> Result<Option<TotpSecret>, UserStoreError>;
+    // Make `secret` the user's confirmed secret and require 2FA. Codes from the
+    // old secret no longer count towards `use_totp_step`. Fails with
+    // `UserNotFound` unless `secret` is still the pending one.
+    async fn confirm_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<(), UserStoreError>;
+    // Replace the user's recovery codes with a new batch
+    async fn set_recovery_codes(
+        &mut self,
+        email: &Email,
+        recovery_codes: Vec<RecoveryCodeHash>,
+    ) -> Result<(), UserStoreError>;
+    // Remove one of the user's recovery codes, so each can only be used once.
+    // Fails with `InvalidCredentials` if the user has no such code.
+    async fn use_recovery_code(
+        &mut self,
+        email: &Email,
+        recovery_code: &RecoveryCodeHash,
+    ) -> Result<(), UserStoreError>;
+    // Record that an authenticator app code for `time_step` was accepted, so
+    // it can't be used again. Fails with `InvalidCredentials` if a code for
+    // this or a later step was accepted before.
+    async fn use_totp_step(&mut self, email: &Email, time_step: i64) -> Result<(), UserStoreError>;
+    // The WebAuthn user handle of the user's passkeys. A random one is
+    // assigned the first time it is asked for and kept from then on.
+    async fn passkey_user_handle(&mut self, email: &Email) -> Result<Uuid, UserStoreError>;
+    // End all of the user's sessions, so tokens issued to them so far are
+    // rejected
+    async fn increment_session_version(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    // Replace every field of the user with the same email, except the session
+    // version, which only `increment_session_version` changes
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    // Up to `limit` users ordered by email, skipping the first `offset`
+    async fn list_users(&self, offset: u64, limit: u64) -> Result<Vec<User>, UserStoreError>;
+    // Hide the user from every other method, while keeping their email
+    // taken, until `purge_deleted_users` removes them for good
+    async fn soft_delete_user(
+        &mut self,
+        email: &Email,
+        deleted_at: i64,
+    ) -> Result<(), UserStoreError>;
+    // Emails of users soft deleted at or before `deleted_before`, so their
+    // data in other stores can be removed before they are purged
+    async fn deleted_users(&self, deleted_before: i64) -> Result<Vec<Email>, UserStoreError>;
+    // Remove users soft deleted at or before `deleted_before`, returning
+    // their emails
+    async fn purge_deleted_users(
+        &mut self,
+        deleted_before: i64,
+    ) -> Result<Vec<Email>, UserStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -15,3 +106,533 @@ pub enum UserStoreError {
     UnexpectedError,
 }
 
+// Check `password` against a user from `UserStore::get_user`. Unknown users
+// are checked against a dummy hash, so response times do not reveal which
+// accounts exist.
+pub async fn verify_user_password(
+    user: Result<User, UserStoreError>,
+    password: &Password,
+) -> Result<(), UserStoreError> {
+    let password_hash = match user {
+        Ok(user) => Some(user.password_hash),
+        Err(UserStoreError::UserNotFound) => None,
+        Err(e) => return Err(e),
+    };
+
+    let result = match password_hash.clone() {
+        Some(password_hash) => password_hash.verify(password).await,
+        None => match PasswordHash::dummy().await {
+            Ok(dummy) => dummy.verify(password).await,
+            Err(_) => return Err(UserStoreError::UnexpectedError),
+        },
+    };
+
+    match (password_hash, result) {
+        (None, _) => Err(UserStoreError::UserNotFound),
+        (Some(_), Ok(())) => Ok(()),
+        (Some(_), Err(PasswordHashError::IncorrectPassword)) => {
+            Err(UserStoreError::InvalidCredentials)
+        }
+        (Some(_), Err(_)) => Err(UserStoreError::UnexpectedError),
+    }
+}
+
+// Tokens are banned by their `jti` claim rather than by the whole token string
+#[async_trait::async_trait]
+pub trait BannedTokenStore: Send + Sync {
+    async fn add_token(&mut self, token_id: String) -> Result<(), BannedTokenStoreError>;
+    async fn contains_token(&self, token_id: &str) -> Result<bool, BannedTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BannedTokenStoreError {
+    UnexpectedError,
+}
+
+// This trait represents the interface all concrete 2FA code stores should implement
+#[async_trait::async_trait]
+pub trait TwoFACodeStore: Send + Sync {
+    async fn add_code(
+        &mut self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    // Count a wrong answer to the pending code, returning how many there have
+    // been since it was added
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError>;
+    async fn get_code(
+        &self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TwoFACodeStoreError {
+    LoginAttemptIdNotFound,
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginAttemptId(String);
+
+impl LoginAttemptId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        // Use the `parse_str` function from the `uuid` crate to ensure `id` is a valid UUID
+        let parsed_id = Uuid::parse_str(&id).map_err(|_| "Invalid login attempt id".to_owned())?;
+        Ok(Self(parsed_id.to_string()))
+    }
+}
+
+impl Default for LoginAttemptId {
+    fn default() -> Self {
+        // Use the `uuid` crate to generate a random version 4 UUID
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for LoginAttemptId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoFACode(String);
+
+impl TwoFACode {
+    pub fn parse(code: String) -> Result<Self, String> {
+        // Ensure `code` is a valid 6-digit code
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+            Ok(Self(code))
+        } else {
+            Err("Invalid 2FA code".to_owned())
+        }
+    }
+}
+
+impl Default for TwoFACode {
+    fn default() -> Self {
+        // Use the `rand` crate to generate a random 2FA code.
+        // The code should be 6 digits (ex: 834629)
+        let code = rand::thread_rng().gen_range(0..1_000_000);
+        Self(format!("{:06}", code))
+    }
+}
+
+impl AsRef<str> for TwoFACode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete refresh token stores should implement.
+// Tokens issued by rotating another token share its family, so a whole login
+// session can be revoked at once.
+#[async_trait::async_trait]
+pub trait RefreshTokenStore: Send + Sync {
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError>;
+    // Mark the token as used and return its record. Each token can only be
+    // consumed once; consuming it again returns `TokenReused`.
+    async fn consume_token(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError>;
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError>;
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenStoreError {
+    TokenNotFound,
+    TokenReused(RefreshTokenRecord),
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshTokenRecord {
+    pub email: Email,
+    pub family_id: String,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
+
+// An opaque, random refresh token
+pub type RefreshToken = OpaqueToken<RefreshTokenKind>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RefreshTokenKind {}
+
+impl OpaqueTokenKind for RefreshTokenKind {
+    const NAME: &'static str = "refresh token";
+}
+
+// Single-use, time-limited tokens sent to users by email. Each token is
+// removed when it is consumed, so a link only works once.
+#[async_trait::async_trait]
+pub trait EmailTokenStore: Send + Sync {
+    async fn add_token(
+        &mut self,
+        token: EmailToken,
+        record: EmailTokenRecord,
+    ) -> Result<(), EmailTokenStoreError>;
+    async fn consume_token(
+        &mut self,
+        token: &EmailToken,
+    ) -> Result<EmailTokenRecord, EmailTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailTokenStoreError {
+    TokenNotFound,
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+    MagicLogin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailTokenRecord {
+    pub email: Email,
+    // A token is only accepted by the route it was sent for
+    pub purpose: EmailTokenPurpose,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
+
+// An opaque, random token embedded in a link
+pub type EmailToken = OpaqueToken<EmailTokenKind>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EmailTokenKind {}
+
+impl OpaqueTokenKind for EmailTokenKind {
+    const NAME: &'static str = "email token";
+}
+
+// Passkeys users have registered, so they can sign in without a password.
+// Credential IDs are unique across all users.
+#[async_trait::async_trait]
+pub trait PasskeyStore: Send + Sync {
+    async fn add_passkey(
+        &mut self,
+        email: &Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError>;
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError>;
+    // Save the signature counter and backup state reported by a sign-in.
+    // Fails with `PasskeyNotFound` if the user has no such passkey.
+    async fn update_passkey(
+        &mut self,
+        email: &Email,
+        result: &AuthenticationResult,
+    ) -> Result<(), PasskeyStoreError>;
+    // Called before a user is purged. The SQL stores keep passkeys in a table
+    // that cascades deletes from users, so purging the user is enough.
+    async fn delete_passkeys(&mut self, _email: &Email) -> Result<(), PasskeyStoreError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasskeyStoreError {
+    PasskeyAlreadyExists,
+    PasskeyNotFound,
+    UnexpectedError,
+}
+
+// WebAuthn ceremonies waiting for the browser's response to their challenge.
+// Each challenge is removed when it is consumed, so it can only be answered once.
+#[async_trait::async_trait]
+pub trait PasskeyChallengeStore: Send + Sync {
+    async fn add_challenge(
+        &mut self,
+        challenge_id: PasskeyChallengeId,
+        record: PasskeyChallengeRecord,
+    ) -> Result<(), PasskeyChallengeStoreError>;
+    async fn consume_challenge(
+        &mut self,
+        challenge_id: &PasskeyChallengeId,
+    ) -> Result<PasskeyChallengeRecord, PasskeyChallengeStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasskeyChallengeStoreError {
+    ChallengeNotFound,
+    UnexpectedError,
+}
+
+// The state webauthn-rs needs to verify the response to a challenge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PasskeyCeremony {
+    Registration(PasskeyRegistration),
+    Authentication(PasskeyAuthentication),
+}
+
+#[derive(Debug, Clone)]
+pub struct PasskeyChallengeRecord {
+    // The user registering a passkey, or signing in with one
+    pub email: Email,
+    pub ceremony: PasskeyCeremony,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PasskeyChallengeId(String);
+
+impl PasskeyChallengeId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let parsed_id = Uuid::parse_str(&id).map_err(|_| "Invalid challenge id".to_owned())?;
+        Ok(Self(parsed_id.to_string()))
+    }
+}
+
+impl Default for PasskeyChallengeId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for PasskeyChallengeId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Applications registered to log users in through /authorize, and the users
+// who have allowed each of them to
+#[async_trait::async_trait]
+pub trait OAuthClientStore: Send + Sync {
+    async fn add_client(&mut self, client: OAuthClient) -> Result<(), OAuthClientStoreError>;
+    async fn get_client(&self, client_id: &ClientId) -> Result<OAuthClient, OAuthClientStoreError>;
+    // Consent is remembered, so users are only asked once per client
+    async fn add_consent(
+        &mut self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<(), OAuthClientStoreError>;
+    async fn has_consent(
+        &self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<bool, OAuthClientStoreError>;
+    // Called before a user is purged. The SQL stores keep consents in a table
+    // that cascades deletes from users, so purging the user is enough.
+    async fn delete_consents(&mut self, _email: &Email) -> Result<(), OAuthClientStoreError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OAuthClientStoreError {
+    ClientAlreadyExists,
+    ClientNotFound,
+    UnexpectedError,
+}
+
+// Authorization codes issued by /authorize. Like email tokens, each code is
+// removed when it is consumed, so it can only be exchanged once.
+#[async_trait::async_trait]
+pub trait AuthorizationCodeStore: Send + Sync {
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        record: AuthorizationCodeRecord,
+    ) -> Result<(), AuthorizationCodeStoreError>;
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationCodeRecord, AuthorizationCodeStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthorizationCodeStoreError {
+    CodeNotFound,
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationCodeRecord {
+    // The code can only be exchanged by this client, with the same
+    // redirect URI and the verifier for the challenge
+    pub client_id: ClientId,
+    pub redirect_uri: RedirectUri,
+    pub code_challenge: CodeChallenge,
+    // The user who logged in
+    pub email: Email,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
+
+// An opaque, random code passed to the client through the user's browser
+pub type AuthorizationCode = OpaqueToken<AuthorizationCodeKind>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AuthorizationCodeKind {}
+
+impl OpaqueTokenKind for AuthorizationCodeKind {
+    const NAME: &'static str = "authorization code";
+}
+
+// Signing keys added by rotation. Every replica loads them from here, so they
+// all sign and accept the same tokens. Keys from the settings are not stored.
+#[async_trait::async_trait]
+pub trait JwtKeyStore: Send + Sync {
+    async fn add_key(&mut self, record: JwtKeyRecord) -> Result<(), JwtKeyStoreError>;
+    // Every stored key, in the order they become active
+    async fn get_keys(&self) -> Result<Vec<JwtKeyRecord>, JwtKeyStoreError>;
+    // Remove keys that became active before `activated_before`, returning how many
+    async fn delete_keys(&mut self, activated_before: i64) -> Result<u64, JwtKeyStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JwtKeyStoreError {
+    KeyAlreadyExists,
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JwtKeyRecord {
+    pub kid: String,
+    // `alg` of the tokens the key signs: HS256, RS256 or EdDSA
+    pub algorithm: String,
+    // A PKCS#8 PEM, or the base64url encoded secret for HS256, encrypted with
+    // AES-256-GCM under the key encryption key, with `kid` as associated data
+    pub private_key: String,
+    // Unix timestamp in seconds from which the key signs new tokens
+    pub activates_at: i64,
+}
+
+// Brute-force protection state, shared between replicas: token buckets per
+// client and consecutive failed logins per account
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    // Take one request from the bucket under `key`, refilling it first
+    async fn take_token(
+        &mut self,
+        key: &str,
+        params: RateLimitParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError>;
+    // Count a login attempt on the account before its password is checked,
+    // so concurrent guesses can not all get in before it is locked. Every
+    // attempt counts as a failure until `reset_login_failures` is called.
+    // Once more than `params.max_failures` have been counted in a row, the
+    // account is locked and its attempts are refused until the lockout ends.
+    async fn record_login_attempt(
+        &mut self,
+        email: &Email,
+        params: LockoutParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError>;
+    // Forget the account's failed logins, ending any lockout
+    async fn reset_login_failures(&mut self, email: &Email) -> Result<(), RateLimitStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimitStoreError {
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitDecision {
+    Allowed,
+    Limited { retry_after_seconds: u64 },
+}
+
+// A token bucket holding up to `burst` requests, refilled at `per_minute`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitParams {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Default for RateLimitParams {
+    fn default() -> Self {
+        Self {
+            burst: 10,
+            per_minute: 30,
+        }
+    }
+}
+
+// Failures are forgotten once no login has failed for `lockout_seconds`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutParams {
+    pub max_failures: u32,
+    pub lockout_seconds: u64,
+}
+
+impl Default for LockoutParams {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            lockout_seconds: 900, // 15 minutes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_attempt_id_parse_accepts_uuid() {
+        let id = Uuid::new_v4().to_string();
+        let result = LoginAttemptId::parse(id.clone());
+        assert_eq!(result.unwrap().as_ref(), id);
+    }
+
+    #[test]
+    fn test_login_attempt_id_parse_rejects_invalid_uuid() {
+        let result = LoginAttemptId::parse("not-a-uuid".to_owned());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_login_attempt_id_default_is_valid() {
+        let id = LoginAttemptId::default();
+        assert!(LoginAttemptId::parse(id.as_ref().to_owned()).is_ok());
+    }
+
+    #[test]
+    fn test_passkey_challenge_id_parse() {
+        let id = PasskeyChallengeId::default();
+        assert!(PasskeyChallengeId::parse(id.as_ref().to_owned()).is_ok());
+        assert!(PasskeyChallengeId::parse("not-a-uuid".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_two_fa_code_parse_accepts_six_digits() {
+        let result = TwoFACode::parse("012345".to_owned());
+        assert_eq!(result.unwrap().as_ref(), "012345");
+    }
+
+    #[test]
+    fn test_two_fa_code_parse_rejects_invalid_codes() {
+        for code in ["", "12345", "1234567", "12345a", "١٢٣٤٥٦"] {
+            assert!(
+                TwoFACode::parse(code.to_owned()).is_err(),
+                "Failed for: {code}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_default_is_valid() {
+        for _ in 0..100 {
+            let code = TwoFACode::default();
+            assert!(TwoFACode::parse(code.as_ref().to_owned()).is_ok());
+        }
+    }
+}
@@ -42,7 +42,7 @@ mod tests {
     struct ValidEmail(String);
 
     impl Arbitrary for ValidEmail {
-        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let email = SafeEmail().fake_with_rng(g);
             Self(email)
         }
@@ -0,0 +1,17 @@
+use super::Email;
+
+// This trait represents the interface all concrete email clients should implement
+#[async_trait::async_trait]
+pub trait EmailClient: Send + Sync {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), EmailClientError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailClientError {
+    UnexpectedError,
+}
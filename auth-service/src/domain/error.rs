@@ -2,6 +2,40 @@ pub enum AuthAPIError {
     UserAlreadyExists,
     InvalidCredentials,
     IncorrectCredentials,
+    MissingToken,
+    InvalidToken,
+    EmailNotVerified,
+    PasswordUnchanged,
+    PasswordLoginDisabled,
+    TooManyRequests { retry_after_seconds: u64 },
     UnexpectedError,
 }
 
+// Errors from the OAuth routes, reported with the codes from RFC 6749
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OAuthError {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    AccessDenied,
+    // The user has to log in to this service first
+    LoginRequired,
+    UnexpectedError,
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant => "invalid_grant",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::AccessDenied => "access_denied",
+            OAuthError::LoginRequired => "login_required",
+            OAuthError::UnexpectedError => "server_error",
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+
+// AES-256-GCM key for secrets kept in the database: the private keys in the
+// key store and users' TOTP secrets, so that a copy of the database gives
+// neither away. Each secret is bound to what it belongs to, such as a `kid`
+// or an email, so stored secrets can't be swapped around.
+#[derive(Clone)]
+pub struct KeyEncryptionKey(Aes256Gcm);
+
+impl KeyEncryptionKey {
+    const NONCE_LEN: usize = 12;
+
+    pub fn parse(key: &str) -> Option<Self> {
+        let key = URL_SAFE_NO_PAD.decode(key).ok()?;
+        Aes256Gcm::new_from_slice(&key).ok().map(Self)
+    }
+
+    // Base64url encoded random nonce followed by the ciphertext
+    pub fn encrypt(&self, secret: &str, bound_to: &str) -> Option<String> {
+        let mut nonce = [0u8; Self::NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = Payload {
+            msg: secret.as_bytes(),
+            aad: bound_to.as_bytes(),
+        };
+        let ciphertext = self.0.encrypt(Nonce::from_slice(&nonce), payload).ok()?;
+
+        Some(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn decrypt(&self, encrypted: &str, bound_to: &str) -> Option<String> {
+        let encrypted = URL_SAFE_NO_PAD.decode(encrypted).ok()?;
+        if encrypted.len() < Self::NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = encrypted.split_at(Self::NONCE_LEN);
+
+        let payload = Payload {
+            msg: ciphertext,
+            aad: bound_to.as_bytes(),
+        };
+        let secret = self.0.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+        String::from_utf8(secret).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_are_bound_to_what_they_belong_to() {
+        let key_encryption_key =
+            KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap();
+        let encrypted = key_encryption_key.encrypt("private key", "kid").unwrap();
+        assert_eq!(
+            key_encryption_key.decrypt(&encrypted, "kid").as_deref(),
+            Some("private key")
+        );
+        // The nonce is random, so the same key never encrypts the same way twice
+        assert_ne!(
+            key_encryption_key.encrypt("private key", "kid").unwrap(),
+            encrypted
+        );
+
+        assert!(key_encryption_key.decrypt(&encrypted, "other").is_none());
+        let other_key = KeyEncryptionKey::parse(&URL_SAFE_NO_PAD.encode([1u8; 32])).unwrap();
+        assert!(other_key.decrypt(&encrypted, "kid").is_none());
+        assert!(key_encryption_key.decrypt("c2hvcnQ", "kid").is_none());
+
+        assert!(KeyEncryptionKey::parse("c2hvcnQ").is_none());
+        assert!(KeyEncryptionKey::parse("not base64!").is_none());
+    }
+}
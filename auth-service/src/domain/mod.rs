@@ -1,11 +1,42 @@
 pub mod data_stores;
 pub mod email;
+pub mod email_client;
 pub mod error;
+pub mod key_encryption_key;
+pub mod oauth_client;
+pub mod opaque_token;
 pub mod password;
+pub mod password_hash;
+pub mod recovery_code;
+pub mod role;
+pub mod totp_secret;
 pub mod user;
 
-pub use data_stores::{UserStore, UserStoreError};
+pub use data_stores::{
+    verify_user_password, AuthorizationCode, AuthorizationCodeRecord, AuthorizationCodeStore,
+    AuthorizationCodeStoreError, BannedTokenStore, BannedTokenStoreError, EmailToken,
+    EmailTokenPurpose, EmailTokenRecord, EmailTokenStore, EmailTokenStoreError, JwtKeyRecord,
+    JwtKeyStore, JwtKeyStoreError, LockoutParams, LoginAttemptId, OAuthClientStore,
+    OAuthClientStoreError, PasskeyCeremony, PasskeyChallengeId, PasskeyChallengeRecord,
+    PasskeyChallengeStore, PasskeyChallengeStoreError, PasskeyStore, PasskeyStoreError,
+    RateLimitDecision, RateLimitParams, RateLimitStore, RateLimitStoreError, RefreshToken,
+    RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError, TwoFACode, TwoFACodeStore,
+    TwoFACodeStoreError, UserStore, UserStoreError,
+};
 pub use email::Email;
-pub use error::AuthAPIError;
+pub use email_client::{EmailClient, EmailClientError};
+pub use error::{AuthAPIError, OAuthError};
+pub use key_encryption_key::KeyEncryptionKey;
+pub use oauth_client::{
+    ClientId, ClientSecret, ClientSecretHash, CodeChallenge, OAuthClient, RedirectUri,
+};
+pub use opaque_token::{OpaqueToken, OpaqueTokenKind};
 pub use password::Password;
+pub use password_hash::{PasswordHash, PasswordHashError, PasswordHashingParams};
+pub use recovery_code::{
+    format_recovery_codes, parse_recovery_codes, RecoveryCode, RecoveryCodeHash,
+    RECOVERY_CODE_COUNT,
+};
+pub use role::{format_roles, parse_roles, Role, RoleParseError};
+pub use totp_secret::TotpSecret;
 pub use user::User;
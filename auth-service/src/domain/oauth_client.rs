@@ -0,0 +1,279 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use super::{OpaqueToken, OpaqueTokenKind};
+
+// An application that logs users in through /authorize. Confidential
+// clients also authenticate with their secret at /token, public clients
+// (single page and native apps) can't keep a secret and only use PKCE.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthClient {
+    pub client_id: ClientId,
+    // Shown to users when they are asked to allow the client
+    pub name: String,
+    pub redirect_uris: Vec<RedirectUri>,
+    // None for public clients
+    pub secret_hash: Option<ClientSecretHash>,
+}
+
+impl OAuthClient {
+    // Redirect URIs must match a registered one exactly
+    pub fn redirect_uri(&self, redirect_uri: &str) -> Option<&RedirectUri> {
+        self.redirect_uris
+            .iter()
+            .find(|registered| registered.as_ref() == redirect_uri)
+    }
+
+    pub fn verify_secret(&self, secret: Option<&ClientSecret>) -> bool {
+        match (&self.secret_hash, secret) {
+            // Comparing hashes gives nothing away about the secret through timing
+            (Some(secret_hash), Some(secret)) => secret.hash() == *secret_hash,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientId(String);
+
+impl ClientId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let parsed_id = Uuid::parse_str(&id).map_err(|_| "Invalid client id".to_owned())?;
+        Ok(Self(parsed_id.to_string()))
+    }
+}
+
+impl Default for ClientId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for ClientId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// An absolute URL without a fragment, where users are sent back to the
+// client with the authorization code
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedirectUri(String);
+
+impl RedirectUri {
+    pub fn parse(uri: String) -> Result<Self, String> {
+        match Url::parse(&uri) {
+            Ok(url) if url.fragment().is_none() && !url.cannot_be_a_base() => Ok(Self(uri)),
+            _ => Err("Invalid redirect URI".to_owned()),
+        }
+    }
+
+    // The redirect URI with `params` added to its query
+    pub fn with_params(&self, params: &[(&str, &str)]) -> String {
+        let mut url = Url::parse(&self.0).expect("Redirect URIs are valid URLs");
+        url.query_pairs_mut().extend_pairs(params);
+        url.into()
+    }
+}
+
+impl AsRef<str> for RedirectUri {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Shown to the operator once, when the client is registered
+pub type ClientSecret = OpaqueToken<ClientSecretKind>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientSecretKind {}
+
+impl OpaqueTokenKind for ClientSecretKind {
+    const NAME: &'static str = "client secret";
+}
+
+impl ClientSecret {
+    // Secrets have enough entropy that a fast hash is as safe as a slow one
+    pub fn hash(&self) -> ClientSecretHash {
+        ClientSecretHash(URL_SAFE_NO_PAD.encode(Sha256::digest(self.as_ref().as_bytes())))
+    }
+}
+
+// The SHA-256 of a client secret, base64url encoded. Only these are stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientSecretHash(String);
+
+impl ClientSecretHash {
+    pub fn parse(hash: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&hash) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Self(hash)),
+            _ => Err("Invalid client secret hash".to_owned()),
+        }
+    }
+}
+
+impl AsRef<str> for ClientSecretHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// A PKCE S256 code challenge (RFC 7636): the base64url SHA-256 of a code
+// verifier that only the client knows
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeChallenge(String);
+
+impl CodeChallenge {
+    pub fn parse(challenge: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&challenge) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Self(challenge)),
+            _ => Err("Invalid code challenge".to_owned()),
+        }
+    }
+
+    pub fn verify(&self, verifier: &str) -> bool {
+        // Verifiers are 43 to 128 unreserved URI characters
+        let is_valid = (43..=128).contains(&verifier.len())
+            && verifier
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+
+        is_valid && URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == self.0
+    }
+}
+
+impl AsRef<str> for CodeChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(secret: Option<&ClientSecret>) -> OAuthClient {
+        OAuthClient {
+            client_id: ClientId::default(),
+            name: "Test".to_owned(),
+            redirect_uris: vec![
+                RedirectUri::parse("http://localhost:8000/callback".to_owned()).unwrap(),
+            ],
+            secret_hash: secret.map(ClientSecret::hash),
+        }
+    }
+
+    #[test]
+    fn test_redirect_uri_must_match_exactly() {
+        let client = client(None);
+        assert!(client
+            .redirect_uri("http://localhost:8000/callback")
+            .is_some());
+
+        for uri in [
+            "http://localhost:8000/callback/",
+            "http://localhost:8000/callback?next=/",
+            "http://localhost:8000/Callback",
+            "https://localhost:8000/callback",
+        ] {
+            assert!(
+                client.redirect_uri(uri).is_none(),
+                "Failed for input: {:?}",
+                uri
+            );
+        }
+    }
+
+    #[test]
+    fn test_redirect_uri_parse() {
+        for uri in [
+            "http://localhost:8000/callback",
+            "com.example.app:/callback",
+        ] {
+            assert!(
+                RedirectUri::parse(uri.to_owned()).is_ok(),
+                "Failed for input: {:?}",
+                uri
+            );
+        }
+        for uri in [
+            "/callback",
+            "http://localhost:8000/callback#token",
+            "mailto:a@b.c",
+            "",
+        ] {
+            assert!(
+                RedirectUri::parse(uri.to_owned()).is_err(),
+                "Failed for input: {:?}",
+                uri
+            );
+        }
+    }
+
+    #[test]
+    fn test_redirect_uri_with_params_keeps_query() {
+        let uri = RedirectUri::parse("http://localhost:8000/callback?app=1".to_owned()).unwrap();
+        assert_eq!(
+            uri.with_params(&[("code", "abc"), ("state", "a b")]),
+            "http://localhost:8000/callback?app=1&code=abc&state=a+b"
+        );
+    }
+
+    #[test]
+    fn test_verify_secret() {
+        let secret = ClientSecret::default();
+        let confidential = client(Some(&secret));
+        assert!(confidential.verify_secret(Some(&secret)));
+        assert!(!confidential.verify_secret(Some(&ClientSecret::default())));
+        assert!(!confidential.verify_secret(None));
+
+        let public = client(None);
+        assert!(public.verify_secret(None));
+        assert!(!public.verify_secret(Some(&secret)));
+    }
+
+    #[test]
+    fn test_client_secret_hash_is_valid() {
+        let secret = ClientSecret::default();
+        assert!(ClientSecretHash::parse(secret.hash().as_ref().to_owned()).is_ok());
+    }
+
+    #[test]
+    fn test_code_challenge_verify() {
+        // The example from RFC 7636, appendix B
+        let challenge =
+            CodeChallenge::parse("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned()).unwrap();
+        assert!(challenge.verify("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"));
+
+        for verifier in [
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXl",
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+            "short",
+            "",
+        ] {
+            assert!(
+                !challenge.verify(verifier),
+                "Failed for input: {:?}",
+                verifier
+            );
+        }
+    }
+
+    #[test]
+    fn test_code_challenge_parse_rejects_invalid_challenges() {
+        for challenge in [
+            "",
+            "plain-verifier",
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-c",
+        ] {
+            assert!(
+                CodeChallenge::parse(challenge.to_owned()).is_err(),
+                "Failed for input: {:?}",
+                challenge
+            );
+        }
+    }
+}
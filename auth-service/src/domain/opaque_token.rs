@@ -0,0 +1,78 @@
+use std::marker::PhantomData;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+
+// What an `OpaqueToken` is used for, named in parse errors
+pub trait OpaqueTokenKind {
+    const NAME: &'static str;
+}
+
+// A random token that only means something to the store it is kept in, such
+// as the tokens in email links. The kind keeps one sort of token from being
+// passed where another is expected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OpaqueToken<K>(String, PhantomData<K>);
+
+impl<K: OpaqueTokenKind> OpaqueToken<K> {
+    pub fn parse(token: String) -> Result<Self, String> {
+        // Default tokens are 32 random bytes, encoded as 43 base64url characters
+        let is_valid = token.len() == 43
+            && token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if is_valid {
+            Ok(Self(token, PhantomData))
+        } else {
+            Err(format!("Invalid {}", K::NAME))
+        }
+    }
+}
+
+impl<K> Default for OpaqueToken<K> {
+    fn default() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes), PhantomData)
+    }
+}
+
+impl<K> AsRef<str> for OpaqueToken<K> {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum TestToken {}
+
+    impl OpaqueTokenKind for TestToken {
+        const NAME: &'static str = "test token";
+    }
+
+    #[test]
+    fn test_default_is_valid_and_unique() {
+        let token = OpaqueToken::<TestToken>::default();
+        assert!(OpaqueToken::<TestToken>::parse(token.as_ref().to_owned()).is_ok());
+        assert_ne!(token, OpaqueToken::default());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_tokens() {
+        let too_short = "a".repeat(42);
+        let too_long = "a".repeat(44);
+        let invalid_char = format!("{}+", "a".repeat(42));
+        for token in ["", &too_short, &too_long, &invalid_char] {
+            assert_eq!(
+                OpaqueToken::<TestToken>::parse(token.to_owned()),
+                Err("Invalid test token".to_owned()),
+                "Failed for: {token}"
+            );
+        }
+    }
+}
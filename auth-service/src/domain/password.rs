@@ -41,7 +41,7 @@ mod tests {
     struct ValidPassword(String);
 
     impl Arbitrary for ValidPassword {
-        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let password = FakePassword(8..100).fake_with_rng(g);
             Self(password)
         }
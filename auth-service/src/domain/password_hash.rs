@@ -0,0 +1,230 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHasher, PasswordVerifier, Version,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use super::Password;
+
+// Argon2id cost parameters used when hashing new passwords. Existing hashes
+// carry their own parameters in the PHC string, so changing these only
+// affects passwords hashed from then on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordHashingParams {
+    pub memory_cost_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingParams {
+    // OWASP recommended minimum for Argon2id
+    fn default() -> Self {
+        Self {
+            memory_cost_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+// The hash of a random password nobody knows. Logins for unknown emails are
+// checked against it, so they take as long as logins with a wrong password.
+static DUMMY_PASSWORD_HASH: OnceCell<PasswordHash> = OnceCell::const_new();
+
+// An Argon2id password hash in PHC string format
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordHash(String);
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordHashError {
+    InvalidFormat,
+    InvalidParams,
+    IncorrectPassword,
+    UnexpectedError,
+}
+
+impl PasswordHash {
+    pub fn parse(hash: String) -> Result<PasswordHash, PasswordHashError> {
+        argon2::PasswordHash::new(&hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+        Ok(PasswordHash(hash))
+    }
+
+    // Hash the dummy password with the parameters new passwords are hashed
+    // with. Called once at startup; later calls keep the first hash.
+    pub async fn init_dummy(params: PasswordHashingParams) -> Result<(), PasswordHashError> {
+        DUMMY_PASSWORD_HASH
+            .get_or_try_init(|| Self::hash_random_password(params))
+            .await
+            .map(|_| ())
+    }
+
+    // Falls back to the default parameters if `init_dummy` was never called
+    pub async fn dummy() -> Result<PasswordHash, PasswordHashError> {
+        Self::init_dummy(PasswordHashingParams::default()).await?;
+        DUMMY_PASSWORD_HASH
+            .get()
+            .cloned()
+            .ok_or(PasswordHashError::UnexpectedError)
+    }
+
+    // The hash of a password nobody knows, for accounts that sign up without
+    // one
+    pub async fn hash_random_password(
+        params: PasswordHashingParams,
+    ) -> Result<PasswordHash, PasswordHashError> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let password = Password::parse(URL_SAFE_NO_PAD.encode(bytes))
+            .map_err(|_| PasswordHashError::UnexpectedError)?;
+        PasswordHash::hash(&password, params).await
+    }
+
+    // Hashing is CPU-heavy, so it runs on the blocking thread pool instead of
+    // stalling the async runtime
+    pub async fn hash(
+        password: &Password,
+        params: PasswordHashingParams,
+    ) -> Result<PasswordHash, PasswordHashError> {
+        let password = password.as_ref().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let params = Params::new(
+                params.memory_cost_kib,
+                params.iterations,
+                params.parallelism,
+                None,
+            )
+            .map_err(|_| PasswordHashError::InvalidParams)?;
+
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| PasswordHash(hash.to_string()))
+                .map_err(|_| PasswordHashError::UnexpectedError)
+        })
+        .await
+        .map_err(|_| PasswordHashError::UnexpectedError)?
+    }
+
+    // Verification uses the parameters stored in the hash itself and compares
+    // digests in constant time
+    pub async fn verify(&self, password: &Password) -> Result<(), PasswordHashError> {
+        let hash = self.0.clone();
+        let password = password.as_ref().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let hash =
+                argon2::PasswordHash::new(&hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .map_err(|e| match e {
+                    argon2::password_hash::Error::Password => PasswordHashError::IncorrectPassword,
+                    _ => PasswordHashError::UnexpectedError,
+                })
+        })
+        .await
+        .map_err(|_| PasswordHashError::UnexpectedError)?
+    }
+}
+
+impl AsRef<str> for PasswordHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap parameters so the tests stay fast
+    const TEST_PARAMS: PasswordHashingParams = PasswordHashingParams {
+        memory_cost_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[tokio::test]
+    async fn test_hash_produces_argon2id_phc_string() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hash = PasswordHash::hash(&password, TEST_PARAMS).await.unwrap();
+        assert!(hash.as_ref().starts_with("$argon2id$v=19$m=8,t=1,p=1$"));
+        assert_ne!(hash.as_ref(), password.as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_hash_uses_random_salt() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let first = PasswordHash::hash(&password, TEST_PARAMS).await.unwrap();
+        let second = PasswordHash::hash(&password, TEST_PARAMS).await.unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_hash_rejects_invalid_params() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let params = PasswordHashingParams {
+            iterations: 0,
+            ..TEST_PARAMS
+        };
+        let result = PasswordHash::hash(&password, params).await;
+        assert_eq!(result, Err(PasswordHashError::InvalidParams));
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        let hash = PasswordHash::hash(&password, TEST_PARAMS).await.unwrap();
+
+        assert_eq!(hash.verify(&password).await, Ok(()));
+        assert_eq!(
+            hash.verify(&wrong_password).await,
+            Err(PasswordHashError::IncorrectPassword)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hash = PasswordHash::hash(&password, TEST_PARAMS).await.unwrap();
+
+        let parsed = PasswordHash::parse(hash.as_ref().to_owned()).unwrap();
+        assert_eq!(parsed, hash);
+
+        let result = PasswordHash::parse("password123".to_owned());
+        assert_eq!(result, Err(PasswordHashError::InvalidFormat));
+    }
+
+    #[tokio::test]
+    async fn test_dummy_rejects_passwords() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let dummy = PasswordHash::hash_random_password(TEST_PARAMS)
+            .await
+            .unwrap();
+
+        assert!(dummy.as_ref().starts_with("$argon2id$v=19$m=8,t=1,p=1$"));
+        assert_eq!(
+            dummy.verify(&password).await,
+            Err(PasswordHashError::IncorrectPassword)
+        );
+
+        // The shared dummy is only hashed once
+        PasswordHash::init_dummy(TEST_PARAMS).await.unwrap();
+        let dummy = PasswordHash::dummy().await.unwrap();
+        assert_eq!(dummy, PasswordHash::dummy().await.unwrap());
+        assert_eq!(
+            PasswordHash::parse(dummy.as_ref().to_owned()),
+            Ok(dummy.clone())
+        );
+        assert_eq!(
+            dummy.verify(&password).await,
+            Err(PasswordHashError::IncorrectPassword)
+        );
+    }
+}
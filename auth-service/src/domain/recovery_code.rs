@@ -0,0 +1,148 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+// How many codes a user gets at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// Lowercase base32, so codes are easy to read out and type
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+const GROUP_LENGTH: usize = 5;
+
+// A one-time code that stands in for a 2FA code when the user has lost
+// their second factor. Formatted as two groups of five characters, 50 bits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    // Accepts codes typed in upper case or without the dash
+    pub fn parse(code: String) -> Result<Self, String> {
+        let chars: String = code
+            .trim()
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        if chars.len() != 2 * GROUP_LENGTH || !chars.bytes().all(|c| ALPHABET.contains(&c)) {
+            return Err("Invalid recovery code".to_owned());
+        }
+
+        let (first, second) = chars.split_at(GROUP_LENGTH);
+        Ok(Self(format!("{}-{}", first, second)))
+    }
+
+    pub fn generate_batch() -> Vec<RecoveryCode> {
+        (0..RECOVERY_CODE_COUNT).map(|_| Self::default()).collect()
+    }
+
+    // Codes have enough entropy that a fast hash is as safe as a slow one
+    pub fn hash(&self) -> RecoveryCodeHash {
+        RecoveryCodeHash(URL_SAFE_NO_PAD.encode(Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut group = || -> String {
+            (0..GROUP_LENGTH)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect()
+        };
+        let first = group();
+        Self(format!("{}-{}", first, group()))
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// The SHA-256 of a recovery code, base64url encoded. Only these are stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCodeHash(String);
+
+impl RecoveryCodeHash {
+    pub fn parse(hash: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&hash) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Self(hash)),
+            _ => Err("Invalid recovery code hash".to_owned()),
+        }
+    }
+}
+
+impl AsRef<str> for RecoveryCodeHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub fn format_recovery_codes(hashes: &[RecoveryCodeHash]) -> String {
+    hashes
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn parse_recovery_codes(hashes: &str) -> Result<Vec<RecoveryCodeHash>, String> {
+    hashes
+        .split_whitespace()
+        .map(|hash| RecoveryCodeHash::parse(hash.to_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_normalizes_codes() {
+        let code = RecoveryCode::default();
+        let (first, second) = code.as_ref().split_once('-').unwrap();
+
+        for input in [
+            code.as_ref().to_owned(),
+            code.as_ref().to_uppercase(),
+            format!("{}{}", first, second),
+            format!(" {} ", code.as_ref()),
+        ] {
+            assert_eq!(
+                RecoveryCode::parse(input.clone()),
+                Ok(code.clone()),
+                "Failed for input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_codes() {
+        for input in ["", "abcde-fghi", "abcde-fghijk", "abcde-fgh1j", "123456"] {
+            assert!(
+                RecoveryCode::parse(input.to_owned()).is_err(),
+                "Failed for input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_batch() {
+        let codes = RecoveryCode::generate_batch();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        let hashes: Vec<RecoveryCodeHash> = codes.iter().map(RecoveryCode::hash).collect();
+        assert!(hashes
+            .iter()
+            .enumerate()
+            .all(|(i, hash)| !hashes[i + 1..].contains(hash)));
+        assert_eq!(
+            parse_recovery_codes(&format_recovery_codes(&hashes)),
+            Ok(hashes)
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+// Roles are carried in auth tokens, so other services can authorize
+// requests without asking the auth service
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Assigned to every user at signup
+    #[default]
+    User,
+    Admin,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RoleParseError {
+    UnknownRole,
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Result<Role, RoleParseError> {
+        match role {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            _ => Err(RoleParseError::UnknownRole),
+        }
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+// Roles are stored as a space separated list, e.g. "user admin"
+pub fn format_roles(roles: &BTreeSet<Role>) -> String {
+    roles
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn parse_roles(roles: &str) -> Result<BTreeSet<Role>, RoleParseError> {
+    roles.split_whitespace().map(Role::parse).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_round_trip() {
+        let roles = BTreeSet::from([Role::Admin, Role::User]);
+        assert_eq!(format_roles(&roles), "user admin");
+        assert_eq!(parse_roles("user admin"), Ok(roles));
+        assert_eq!(parse_roles(""), Ok(BTreeSet::new()));
+    }
+
+    #[test]
+    fn test_unknown_role_is_rejected() {
+        assert_eq!(parse_roles("user owner"), Err(RoleParseError::UnknownRole));
+    }
+
+    #[test]
+    fn test_roles_serialize_in_lowercase() {
+        let json = serde_json::to_string(&BTreeSet::from([Role::Admin, Role::User])).unwrap();
+        assert_eq!(json, r#"["user","admin"]"#);
+    }
+}
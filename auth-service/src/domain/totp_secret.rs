@@ -0,0 +1,144 @@
+use chrono::Utc;
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::{Email, TwoFACode};
+
+// RFC 6238 defaults, which is what authenticator apps assume
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+// Codes from one step before or after the current one are accepted too,
+// to allow for clock drift between the server and the user's device
+const SKEW_STEPS: u8 = 1;
+// 160 bits, as recommended by RFC 4226
+const SECRET_BYTES: usize = 20;
+
+// The key shared with a user's authenticator app, base32 encoded as in
+// `otpauth://` URIs
+#[derive(Debug, Clone, PartialEq)]
+pub struct TotpSecret(String);
+
+impl TotpSecret {
+    pub fn parse(secret: String) -> Result<Self, String> {
+        let totp_secret = Self(secret);
+        totp_secret.totp(None, String::new())?;
+        Ok(totp_secret)
+    }
+
+    // The time step `code` was generated for, if it is accepted now. A code
+    // stays valid for several steps, so callers must also make sure each step
+    // is only used once, see `UserStore::use_totp_step`.
+    pub fn verify(&self, code: &TwoFACode) -> Option<i64> {
+        let mut totp = self.totp(None, String::new()).ok()?;
+        // Each step is checked on its own, to know which one matched
+        totp.skew = 0;
+
+        let step_seconds = STEP_SECONDS as i64;
+        let current_step = Utc::now().timestamp() / step_seconds;
+        let skew_steps = i64::from(SKEW_STEPS);
+        (current_step - skew_steps..=current_step + skew_steps)
+            .rev()
+            .find(|&step| totp.check(code.as_ref(), (step * step_seconds) as u64))
+    }
+
+    // The URI authenticator apps import, labelled with the issuer and email
+    pub fn otpauth_uri(&self, issuer: &str, email: &Email) -> Result<String, String> {
+        Ok(self
+            .totp(Some(issuer.to_owned()), email.as_ref().to_owned())?
+            .get_url())
+    }
+
+    // The same URI as a base64 encoded PNG QR code
+    pub fn qr_code_png(&self, issuer: &str, email: &Email) -> Result<String, String> {
+        self.totp(Some(issuer.to_owned()), email.as_ref().to_owned())?
+            .get_qr_base64()
+    }
+
+    fn totp(&self, issuer: Option<String>, account_name: String) -> Result<TOTP, String> {
+        let secret = Secret::Encoded(self.0.clone())
+            .to_bytes()
+            .map_err(|_| "Invalid TOTP secret".to_owned())?;
+
+        TOTP::new(
+            Algorithm::SHA1,
+            DIGITS,
+            SKEW_STEPS,
+            STEP_SECONDS,
+            secret,
+            issuer,
+            account_name,
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let mut secret = vec![0u8; SECRET_BYTES];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        match Secret::Raw(secret).to_encoded() {
+            Secret::Encoded(encoded) => Self(encoded),
+            Secret::Raw(_) => unreachable!(),
+        }
+    }
+}
+
+impl AsRef<str> for TotpSecret {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejects_invalid_secrets() {
+        // Not base32, and shorter than 128 bits
+        for secret in ["not base32!", "JBSWY3DPEHPK3PXP"] {
+            assert!(
+                TotpSecret::parse(secret.to_owned()).is_err(),
+                "Failed for input: {:?}",
+                secret
+            );
+        }
+
+        let secret = TotpSecret::default();
+        assert_eq!(TotpSecret::parse(secret.as_ref().to_owned()), Ok(secret));
+    }
+
+    #[test]
+    fn test_verify_tolerates_one_step_of_drift() {
+        let secret = TotpSecret::default();
+        let totp = secret.totp(None, String::new()).unwrap();
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        for offset in [0, STEP_SECONDS] {
+            for time in [now - offset, now + offset] {
+                let code = TwoFACode::parse(totp.generate(time)).unwrap();
+                assert_eq!(
+                    secret.verify(&code),
+                    Some((time / STEP_SECONDS) as i64),
+                    "Failed for input: {:?}",
+                    time
+                );
+            }
+        }
+
+        let code = TwoFACode::parse(totp.generate(now - 3 * STEP_SECONDS)).unwrap();
+        assert_eq!(secret.verify(&code), None);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let secret = TotpSecret::default();
+        let email = Email::parse("user@example.com".to_owned()).unwrap();
+
+        let uri = secret.otpauth_uri("auth-service", &email).unwrap();
+        assert!(uri.starts_with("otpauth://totp/auth-service:user%40example.com?"));
+        assert!(uri.contains(&format!("secret={}", secret.as_ref())));
+        assert!(secret.qr_code_png("auth-service", &email).is_ok());
+    }
+}
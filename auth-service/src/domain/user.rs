@@ -1,18 +1,65 @@
-use super::{Email, Password};
+use std::collections::BTreeSet;
+
+use super::{Email, PasswordHash, RecoveryCodeHash, Role, TotpSecret};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct User {
     pub email: Email,
-    pub password: Password,
+    pub password_hash: PasswordHash,
     pub requires_2fa: bool,
+    pub roles: BTreeSet<Role>,
+    // Set once the user has followed the link sent to their address
+    pub email_verified: bool,
+    // Set once the user has confirmed an authenticator app. Their 2FA codes
+    // then come from the app instead of by email.
+    pub totp_secret: Option<TotpSecret>,
+    // Hashes of the recovery codes the user has not used yet
+    pub recovery_codes: Vec<RecoveryCodeHash>,
+    // Tokens issued with an older version are no longer accepted
+    pub session_version: i64,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    // New users only have the default role, and have not verified their email yet
+    pub fn new(email: Email, password_hash: PasswordHash, requires_2fa: bool) -> Self {
         User {
             email,
-            password,
+            password_hash,
             requires_2fa,
+            roles: BTreeSet::from([Role::default()]),
+            email_verified: false,
+            totp_secret: None,
+            recovery_codes: Vec::new(),
+            session_version: 0,
         }
     }
-}
\ No newline at end of file
+
+    pub fn with_email_verified(mut self, email_verified: bool) -> Self {
+        self.email_verified = email_verified;
+        self
+    }
+
+    pub fn with_totp_secret(mut self, totp_secret: Option<TotpSecret>) -> Self {
+        self.totp_secret = totp_secret;
+        self
+    }
+
+    pub fn with_recovery_codes(mut self, recovery_codes: Vec<RecoveryCodeHash>) -> Self {
+        self.recovery_codes = recovery_codes;
+        self
+    }
+
+    pub fn with_session_version(mut self, session_version: i64) -> Self {
+        self.session_version = session_version;
+        self
+    }
+
+    pub fn with_roles(mut self, roles: BTreeSet<Role>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+}
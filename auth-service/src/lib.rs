@@ -1,22 +1,45 @@
 use axum::{
-    http::StatusCode,
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    http::{header, StatusCode},
+    middleware::{self, AddExtension},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     serve::Serve,
     Json, Router,
 };
+use redis::{aio::ConnectionManager, RedisResult};
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    PgPool, SqlitePool,
+};
 use tower_http::services::ServeDir;
 use std::error::Error;
+use std::{net::SocketAddr, str::FromStr};
 use serde::{Deserialize, Serialize};
 
 pub mod app_state;
 pub mod domain;
 pub mod services;
 pub mod routes;
+pub mod settings;
 pub mod utils;
 use app_state::AppState;
-use domain::AuthAPIError;
-use crate::routes::{signup, login, logout, verify_token, verify_2fa};
+use domain::{AuthAPIError, OAuthError, PasswordHash};
+use crate::routes::{
+    authorize, change_password, confirm_totp, delete_account, enroll_totp, finish_passkey_login,
+    finish_passkey_registration, forgot_password, get_consent, get_recovery_codes, jwks, login,
+    logout, post_consent, reauthenticate, refresh, regenerate_recovery_codes,
+    register_oauth_client, request_magic_link, resend_verification, reset_password,
+    rotate_signing_key, signup, start_passkey_login, start_passkey_registration, token, verify_2fa,
+    verify_email, verify_magic_link, verify_token,
+};
+use settings::Settings;
+use utils::{
+    account_purge::purge_deleted_accounts,
+    jwt_key_sync::{load_jwt_keys, sync_jwt_keys},
+    rate_limit::rate_limit_by_ip,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -29,6 +52,29 @@ impl IntoResponse for AuthAPIError {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
+            AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing auth token"),
+            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email address not verified"),
+            AuthAPIError::PasswordUnchanged => (
+                StatusCode::BAD_REQUEST,
+                "New password must be different from the current password",
+            ),
+            AuthAPIError::PasswordLoginDisabled => {
+                (StatusCode::FORBIDDEN, "Password login is disabled")
+            }
+            AuthAPIError::TooManyRequests {
+                retry_after_seconds,
+            } => {
+                let body = Json(ErrorResponse {
+                    error: "Too many requests".to_owned(),
+                });
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after_seconds.to_string())],
+                    body,
+                )
+                    .into_response();
+            }
             AuthAPIError::UnexpectedError => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
@@ -40,24 +86,111 @@ impl IntoResponse for AuthAPIError {
     }
 }
 
+// OAuth errors use the error codes from RFC 6749, which clients match on
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            OAuthError::InvalidRequest
+            | OAuthError::InvalidGrant
+            | OAuthError::UnsupportedGrantType
+            | OAuthError::UnsupportedResponseType => StatusCode::BAD_REQUEST,
+            OAuthError::InvalidClient | OAuthError::LoginRequired => StatusCode::UNAUTHORIZED,
+            OAuthError::AccessDenied => StatusCode::FORBIDDEN,
+            OAuthError::UnexpectedError => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(ErrorResponse {
+            error: self.code().to_owned(),
+        });
+        (status, body).into_response()
+    }
+}
+
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     pub address: String,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        let router = Router::new()
-            .nest_service("/", ServeDir::new("assets"))
+    pub async fn build(app_state: AppState, settings: &Settings) -> Result<Self, Box<dyn Error>> {
+        // Keys rotated before this process started sign from the first request
+        load_jwt_keys(&app_state.jwt_keyring, &app_state.jwt_key_store)
+            .await
+            .map_err(|e| format!("Failed to load signing keys: {:?}", e))?;
+        tokio::spawn(sync_jwt_keys(
+            app_state.jwt_keyring.clone(),
+            app_state.jwt_key_store.clone(),
+            settings.jwt.key_sync_interval_seconds,
+        ));
+
+        // Logins for unknown emails must take as long as with the configured
+        // hashing parameters
+        PasswordHash::init_dummy(settings.password_hashing)
+            .await
+            .map_err(|e| format!("Failed to hash the dummy password: {:?}", e))?;
+
+        tokio::spawn(purge_deleted_accounts(
+            app_state.user_store.clone(),
+            app_state.passkey_store.clone(),
+            app_state.oauth_client_store.clone(),
+            settings.account_deletion,
+        ));
+
+        // Routes that accept passwords or codes, or send emails, are rate
+        // limited per client IP
+        let rate_limited = Router::new()
             .route("/signup", post(signup))
             .route("/login", post(login))
+            .route("/login/magic-link", post(request_magic_link))
+            .route("/login/magic-link/verify", post(verify_magic_link))
             .route("/verify-2fa", post(verify_2fa))
+            .route("/resend-verification", post(resend_verification))
+            .route("/forgot-password", post(forgot_password))
+            .route("/reset-password", post(reset_password))
+            .route("/change-password", post(change_password))
+            .route("/delete-account", post(delete_account))
+            .route("/reauthenticate", post(reauthenticate))
+            .route("/totp/enroll", post(enroll_totp))
+            .route("/totp/confirm", post(confirm_totp))
+            .route(
+                "/recovery-codes",
+                get(get_recovery_codes).post(regenerate_recovery_codes),
+            )
+            .route("/passkey/register/start", post(start_passkey_registration))
+            .route(
+                "/passkey/register/finish",
+                post(finish_passkey_registration),
+            )
+            .route("/passkey/login/start", post(start_passkey_login))
+            .route("/passkey/login/finish", post(finish_passkey_login))
+            .route("/authorize/consent", get(get_consent).post(post_consent))
+            .route("/token", post(token))
+            .route_layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                rate_limit_by_ip,
+            ));
+
+        let router = Router::new()
+            .nest_service("/", ServeDir::new("assets"))
+            .merge(rate_limited)
             .route("/logout", post(logout))
+            .route("/refresh", post(refresh))
             .route("/verify-token", post(verify_token))
+            .route("/verify-email", get(verify_email))
+            .route("/.well-known/jwks.json", get(jwks))
+            .route("/authorize", get(authorize))
+            .route("/admin/rotate-signing-key", post(rotate_signing_key))
+            .route("/admin/oauth-clients", post(register_oauth_client))
             .with_state(app_state);
-        let listener = tokio::net::TcpListener::bind(address).await?;
+        let listener = tokio::net::TcpListener::bind(&settings.address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Client addresses are needed for rate limiting
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
         Ok(Self { server, address })
     }
 
@@ -66,3 +199,46 @@ impl Application {
         self.server.await
     }
 }
+
+pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+    // Create a new PostgreSQL connection pool
+    PgPoolOptions::new().max_connections(5).connect(url).await
+}
+
+// Accepts file-backed URLs such as `sqlite://auth.db` as well as `sqlite::memory:`
+pub async fn get_sqlite_pool(url: &str) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+
+    if url.contains(":memory:") {
+        // An in-memory database disappears with its last connection, so
+        // keep exactly one connection open for the lifetime of the pool
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+    } else {
+        SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+    }
+}
+
+// The connection manager reconnects automatically and can be cloned cheaply
+// to share one connection between stores
+pub async fn get_redis_connection(url: &str) -> RedisResult<ConnectionManager> {
+    let client = redis::Client::open(url)?;
+    ConnectionManager::new(client).await
+}
+
+// Apply any pending migrations from the `migrations` directory.
+// The schema is shared between the Postgres and SQLite stores.
+pub async fn run_postgres_migrations(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!().run(pool).await
+}
+
+pub async fn run_sqlite_migrations(pool: &SqlitePool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!().run(pool).await
+}
@@ -1,17 +1,223 @@
-use auth_service::Application;
-use auth_service::app_state::AppState;
-use auth_service::services::HashmapUserStore;
+use auth_service::app_state::{
+    AppState, AuthorizationCodeStoreType, BannedTokenStoreType, EmailClientType,
+    EmailTokenStoreType, JwtKeyStoreType, OAuthClientStoreType, PasskeyChallengeStoreType,
+    PasskeyStoreType, RateLimitStoreType, RefreshTokenStoreType, TwoFACodeStoreType, UserStoreType,
+};
+use auth_service::domain::{Email, KeyEncryptionKey};
+use auth_service::services::{
+    FileOutboxEmailClient, HashmapAuthorizationCodeStore, HashmapEmailTokenStore,
+    HashmapJwtKeyStore, HashmapOAuthClientStore, HashmapPasskeyChallengeStore, HashmapPasskeyStore,
+    HashmapRateLimitStore, HashmapRefreshTokenStore, HashmapTwoFACodeStore, HashmapUserStore,
+    HashsetBannedTokenStore, LogEmailClient, PostgresJwtKeyStore, PostgresOAuthClientStore,
+    PostgresPasskeyStore, PostgresUserStore, RedisAuthorizationCodeStore, RedisBannedTokenStore,
+    RedisEmailTokenStore, RedisPasskeyChallengeStore, RedisRateLimitStore, RedisRefreshTokenStore,
+    RedisTwoFACodeStore, SqliteJwtKeyStore, SqliteOAuthClientStore, SqlitePasskeyStore,
+    SqliteUserStore,
+};
+use auth_service::settings::Settings;
+use auth_service::utils::{jwt_keys::JwtKeyring, webauthn::build_webauthn};
+use auth_service::{
+    get_postgres_pool, get_redis_connection, get_sqlite_pool, run_postgres_migrations,
+    run_sqlite_migrations, Application,
+};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 #[tokio::main]
 async fn main() {
-    let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-    let app_state = AppState::new(user_store);
+    let settings = match Settings::load() {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let app = Application::build(app_state, "0.0.0.0:3000")
+    let jwt_keyring = match JwtKeyring::from_settings(&settings.jwt) {
+        Ok(jwt_keyring) => Arc::new(RwLock::new(jwt_keyring)),
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // The relying party was checked when the settings were validated
+    let webauthn = Arc::new(build_webauthn(&settings).expect("Invalid WebAuthn settings"));
+
+    let (user_store, passkey_store, oauth_client_store, jwt_key_store) =
+        configure_user_stores(&settings).await;
+    let (
+        banned_token_store,
+        two_fa_code_store,
+        refresh_token_store,
+        rate_limit_store,
+        email_token_store,
+        passkey_challenge_store,
+        authorization_code_store,
+    ) = configure_token_stores(&settings).await;
+    let email_client = configure_email_client(&settings);
+    let app_state = AppState::new(
+        user_store,
+        passkey_store,
+        oauth_client_store,
+        jwt_key_store,
+        banned_token_store,
+        two_fa_code_store,
+        refresh_token_store,
+        rate_limit_store,
+        email_token_store,
+        passkey_challenge_store,
+        authorization_code_store,
+        email_client,
+        jwt_keyring,
+        webauthn,
+        settings.clone(),
+    );
+
+    let app = Application::build(app_state, &settings)
         .await
         .expect("Failed to build app");
 
     app.run().await.expect("Failed to run app");
 }
+
+// `sqlite:` URLs select the SQLite stores, any other URL is treated as Postgres.
+// Passkeys and OAuth consents live next to the users they belong to, and
+// rotated signing keys are kept there too so every replica can load them.
+async fn configure_user_stores(
+    settings: &Settings,
+) -> (
+    UserStoreType,
+    PasskeyStoreType,
+    OAuthClientStoreType,
+    JwtKeyStoreType,
+) {
+    // Required with a database
+    let key_encryption_key = || {
+        settings
+            .jwt
+            .key_encryption_key
+            .as_deref()
+            .and_then(KeyEncryptionKey::parse)
+            .expect("Invalid key encryption key")
+    };
+
+    match settings.database_url.as_ref() {
+        Some(url) if url.starts_with("sqlite:") => {
+            let pool = get_sqlite_pool(url)
+                .await
+                .expect("Failed to create SQLite connection pool!");
+
+            run_sqlite_migrations(&pool)
+                .await
+                .expect("Failed to run migrations");
+
+            (
+                Arc::new(RwLock::new(SqliteUserStore::new(
+                    pool.clone(),
+                    key_encryption_key(),
+                ))),
+                Arc::new(RwLock::new(SqlitePasskeyStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqliteOAuthClientStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqliteJwtKeyStore::new(pool))),
+            )
+        }
+        Some(url) => {
+            let pool = get_postgres_pool(url)
+                .await
+                .expect("Failed to create Postgres connection pool!");
+
+            run_postgres_migrations(&pool)
+                .await
+                .expect("Failed to run migrations");
+
+            (
+                Arc::new(RwLock::new(PostgresUserStore::new(
+                    pool.clone(),
+                    key_encryption_key(),
+                ))),
+                Arc::new(RwLock::new(PostgresPasskeyStore::new(pool.clone()))),
+                Arc::new(RwLock::new(PostgresOAuthClientStore::new(pool.clone()))),
+                Arc::new(RwLock::new(PostgresJwtKeyStore::new(pool))),
+            )
+        }
+        None => (
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashmapPasskeyStore::default())),
+            Arc::new(RwLock::new(HashmapOAuthClientStore::default())),
+            Arc::new(RwLock::new(HashmapJwtKeyStore::default())),
+        ),
+    }
+}
+
+// Replicas behind a load balancer must share these stores through Redis
+async fn configure_token_stores(
+    settings: &Settings,
+) -> (
+    BannedTokenStoreType,
+    TwoFACodeStoreType,
+    RefreshTokenStoreType,
+    RateLimitStoreType,
+    EmailTokenStoreType,
+    PasskeyChallengeStoreType,
+    AuthorizationCodeStoreType,
+) {
+    match settings.redis_url.as_ref() {
+        Some(url) => {
+            let conn = get_redis_connection(url)
+                .await
+                .expect("Failed to connect to Redis");
+
+            (
+                // Banned IDs must outlive the token, including the leeway
+                Arc::new(RwLock::new(RedisBannedTokenStore::new(
+                    conn.clone(),
+                    settings.jwt.token_ttl_seconds + settings.jwt.leeway_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisTwoFACodeStore::new(
+                    conn.clone(),
+                    settings.two_fa_code_ttl_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisRefreshTokenStore::new(
+                    conn.clone(),
+                    settings.jwt.refresh_token_ttl_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisRateLimitStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisEmailTokenStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisPasskeyChallengeStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(conn))),
+            )
+        }
+        None => (
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::new(
+                settings.two_fa_code_ttl_seconds,
+            ))),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapRateLimitStore::default())),
+            Arc::new(RwLock::new(HashmapEmailTokenStore::default())),
+            Arc::new(RwLock::new(HashmapPasskeyChallengeStore::default())),
+            Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default())),
+        ),
+    }
+}
+
+fn configure_email_client(settings: &Settings) -> EmailClientType {
+    match settings.email.outbox_dir.as_ref() {
+        Some(outbox_dir) => {
+            // The sender address was checked when the settings were validated
+            let sender = Email::parse(settings.email.sender.clone()).expect("Invalid sender email");
+            Arc::new(FileOutboxEmailClient::new(outbox_dir, sender))
+        }
+        // Logged emails would put login and password reset links into the
+        // logs, so only debug builds print them, and only when asked to
+        None if settings.email.log_only && cfg!(debug_assertions) => Arc::new(LogEmailClient),
+        None => {
+            eprintln!(
+                "Invalid configuration: no way to send emails. Set EMAIL_OUTBOX_DIR, or \
+                 EMAIL_LOG_ONLY=true in a debug build."
+            );
+            std::process::exit(1);
+        }
+    }
+}
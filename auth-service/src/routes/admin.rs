@@ -0,0 +1,169 @@
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, ClientId, ClientSecret, OAuthClient, RedirectUri},
+    utils::{jwt_key_sync::load_jwt_keys, jwt_keys::JwtKey},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateSigningKeyResponse {
+    pub kid: String,
+    // Unix timestamp in seconds from which the key signs new tokens
+    #[serde(rename = "activatesAt")]
+    pub activates_at: i64,
+}
+
+// Add a new signing key to the key store, which every replica loads it from.
+// The key is published straight away, but only signs tokens one key sync
+// interval later, once every replica has loaded it. The key it replaces is
+// then retired, and keeps verifying the tokens it signed until they expire.
+pub async fn rotate_signing_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let sync_interval_seconds = i64::try_from(state.settings.jwt.key_sync_interval_seconds)
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    // A rotation right after another still replaces the key it added
+    let latest_activates_at = state
+        .jwt_key_store
+        .read()
+        .await
+        .get_keys()
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?
+        .last()
+        .map(|record| record.activates_at);
+    let activates_at = latest_activates_at
+        .map_or(i64::MIN, |latest| latest + 1)
+        .max(Utc::now().timestamp() + sync_interval_seconds);
+
+    let (algorithm, key_encryption_key) = {
+        let keyring = state.jwt_keyring.read().await;
+        (
+            keyring.active_algorithm(),
+            keyring.key_encryption_key().cloned(),
+        )
+    };
+    // Settings validation requires it along with the admin token
+    let key_encryption_key = key_encryption_key.ok_or(AuthAPIError::UnexpectedError)?;
+
+    // RSA keys take a while to generate
+    let record = tokio::task::spawn_blocking(move || {
+        JwtKey::generate_record(algorithm, activates_at, &key_encryption_key)
+    })
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)?
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let kid = record.kid.clone();
+
+    state
+        .jwt_key_store
+        .write()
+        .await
+        .add_key(record)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Other replicas pick the key up at their next sync
+    load_jwt_keys(&state.jwt_keyring, &state.jwt_key_store)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(Json(RotateSigningKeyResponse { kid, activates_at }))
+}
+
+#[derive(Deserialize)]
+pub struct RegisterOAuthClientRequest {
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+    // Public clients get no secret and rely on PKCE alone
+    #[serde(default)]
+    pub public: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterOAuthClientResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    // Only returned here, the store keeps a hash
+    #[serde(rename = "clientSecret", skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+// Register an application that can log users in through /authorize
+pub async fn register_oauth_client(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RegisterOAuthClientRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+
+    let name = request.name.trim().to_owned();
+    if name.is_empty() || request.redirect_uris.is_empty() {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let redirect_uris = request
+        .redirect_uris
+        .into_iter()
+        .map(RedirectUri::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let client_secret = (!request.public).then(ClientSecret::default);
+    let client = OAuthClient {
+        client_id: ClientId::default(),
+        name,
+        redirect_uris,
+        secret_hash: client_secret.as_ref().map(ClientSecret::hash),
+    };
+    let client_id = client.client_id.as_ref().to_owned();
+
+    state
+        .oauth_client_store
+        .write()
+        .await
+        .add_client(client)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let response = Json(RegisterOAuthClientResponse {
+        client_id,
+        client_secret: client_secret.map(|secret| secret.as_ref().to_owned()),
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+// Admin routes expect `Authorization: Bearer <admin_api_token>`
+fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    match &state.settings.admin_api_token {
+        Some(admin_api_token) if constant_time_eq(token.as_bytes(), admin_api_token.as_bytes()) => {
+            Ok(())
+        }
+        _ => Err(AuthAPIError::InvalidToken),
+    }
+}
+
+// Compare without returning early, so response times do not reveal how much
+// of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
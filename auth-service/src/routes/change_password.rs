@@ -0,0 +1,177 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, PasswordHash, PasswordHashError},
+    utils::{
+        auth::{
+            generate_auth_cookie, generate_refresh_cookie, new_refresh_family_id, validate_token,
+        },
+        reauthentication::{verify_reauthentication, Reauthentication},
+    },
+};
+
+// With password login turned off, the change is confirmed with a code from
+// /reauthenticate instead of the current password
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Option<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let token = match jar.get(&state.settings.jwt.cookie_name) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let claims = match validate_token(
+        &token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let email = match Email::parse(claims.sub) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let new_password = match Password::parse(request.new_password) {
+        Ok(password) => password,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // A stolen cookie must not allow guessing the password more freely than
+    // /login does, so the same lockout applies
+    let reauthentication = Reauthentication {
+        password: request.current_password,
+        login_attempt_id: request.login_attempt_id,
+        two_fa_code: request.two_fa_code,
+    };
+    if let Err(e) = verify_reauthentication(&state, &email, reauthentication).await {
+        return (jar, Err(e));
+    }
+
+    // Compared with the stored hash, since a change confirmed with a code
+    // has no current password to compare with. Only done once the user has
+    // proven who they are, so it can't be used to guess the password.
+    let current_password_hash = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user.password_hash,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+    match current_password_hash.verify(&new_password).await {
+        Ok(()) => return (jar, Err(AuthAPIError::PasswordUnchanged)),
+        Err(PasswordHashError::IncorrectPassword) => {}
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+
+    let password_hash =
+        match PasswordHash::hash(&new_password, state.settings.password_hashing).await {
+            Ok(password_hash) => password_hash,
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        };
+
+    let user = {
+        let mut user_store = state.user_store.write().await;
+
+        if user_store
+            .update_password(&email, password_hash)
+            .await
+            .is_err()
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError));
+        }
+
+        // Access tokens of every session, this one included, stop working
+        if user_store.increment_session_version(&email).await.is_err() {
+            return (jar, Err(AuthAPIError::UnexpectedError));
+        }
+
+        match user_store.get_user(&email).await {
+            Ok(user) => user,
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
+    };
+
+    // Other sessions can't refresh their way back in either. This one gets
+    // new tokens below.
+    if state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_user_tokens(&email)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    if state
+        .banned_token_store
+        .write()
+        .await
+        .add_token(claims.jti)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    // The current session continues as a new refresh token family
+    let family_id = new_refresh_family_id();
+
+    let auth_cookie = match generate_auth_cookie(
+        &user,
+        &family_id,
+        &state.settings.jwt,
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let refresh_cookie = match generate_refresh_cookie(
+        &email,
+        &family_id,
+        state.refresh_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+    let response = Json(ChangePasswordResponse {
+        message: "Password updated".to_owned(),
+    });
+
+    (updated_jar, Ok((StatusCode::OK, response)))
+}
@@ -0,0 +1,194 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId, Password, TwoFACode, User},
+    utils::{
+        auth::validate_token,
+        rate_limit::verify_password_with_lockout,
+        two_fa::{start_2fa_challenge, verify_2fa_code, TwoFactorProof},
+    },
+};
+
+use super::TwoFactorAuthResponse;
+
+// Users confirm with their password. Users with 2FA then confirm again with
+// a 2FA code, sending it with the login attempt ID from the response. With
+// password login turned off, users send no password and confirm with a code
+// straight away.
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum DeleteAccountResponse {
+    Deleted,
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+pub async fn delete_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<DeleteAccountRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let cookie_name = state.settings.jwt.cookie_name.clone();
+    let token = match jar.get(&cookie_name) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let claims = match validate_token(
+        &token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let email = match Email::parse(claims.sub) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let password_login_enabled = state.settings.password_login_enabled;
+
+    match (
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+    ) {
+        (Some(_), None, None) if !password_login_enabled => {
+            return (jar, Err(AuthAPIError::PasswordLoginDisabled));
+        }
+        (None, None, None) if !password_login_enabled => {
+            return start_2fa(&user, &state, jar).await;
+        }
+        (Some(password), None, None) => {
+            let password = match Password::parse(password) {
+                Ok(password) => password,
+                Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+            };
+
+            if let Err(e) = verify_password_with_lockout(&state, &email, &password).await {
+                return (jar, Err(e));
+            }
+
+            if user.requires_2fa {
+                return start_2fa(&user, &state, jar).await;
+            }
+        }
+        (None, Some(login_attempt_id), Some(two_fa_code))
+            if user.requires_2fa || !password_login_enabled =>
+        {
+            let login_attempt_id = match LoginAttemptId::parse(login_attempt_id) {
+                Ok(login_attempt_id) => login_attempt_id,
+                Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+            };
+
+            let proof = match TwoFactorProof::parse(two_fa_code) {
+                Ok(proof) => proof,
+                Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+            };
+
+            if let Err(e) = verify_2fa_code(&state, &user, login_attempt_id, proof).await {
+                return (jar, Err(e));
+            }
+        }
+        _ => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    }
+
+    // The account is only hidden for now, see `purge_deleted_accounts`
+    if state
+        .user_store
+        .write()
+        .await
+        .soft_delete_user(&email, Utc::now().timestamp())
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    // Revoke every token the user still has. Auth tokens of other sessions
+    // fail /verify-token from now on, since the user no longer exists.
+    if state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_user_tokens(&email)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    if state
+        .banned_token_store
+        .write()
+        .await
+        .add_token(claims.jti)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    let jar = jar
+        .remove(Cookie::build(cookie_name).path("/"))
+        .remove(Cookie::build(state.settings.jwt.refresh_cookie_name.clone()).path("/refresh"));
+
+    (
+        jar,
+        Ok((StatusCode::OK, Json(DeleteAccountResponse::Deleted))),
+    )
+}
+
+async fn start_2fa(
+    user: &User,
+    state: &AppState,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<DeleteAccountResponse>), AuthAPIError>,
+) {
+    let content = |code: &TwoFACode| {
+        format!(
+            "Your code to confirm the deletion of your account: {}",
+            code.as_ref()
+        )
+    };
+    let (login_attempt_id, two_factor_method) =
+        match start_2fa_challenge(state, user, "Confirm account deletion", content).await {
+            Ok(challenge) => challenge,
+            Err(e) => return (jar, Err(e)),
+        };
+
+    let response = Json(DeleteAccountResponse::TwoFactorAuth(
+        TwoFactorAuthResponse {
+            message: "2FA required".to_owned(),
+            login_attempt_id: login_attempt_id.as_ref().to_owned(),
+            two_factor_method,
+        },
+    ));
+
+    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+}
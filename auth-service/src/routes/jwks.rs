@@ -0,0 +1,8 @@
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::app_state::AppState;
+
+// Public keys for verifying auth tokens without calling /verify-token
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.jwt_keyring.read().await.jwks())
+}
@@ -1,11 +1,15 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password},
-    utils::auth::generate_auth_cookie,
+    domain::{AuthAPIError, Email, Password, User},
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie, new_refresh_family_id},
+        rate_limit::verify_password_with_lockout,
+        two_fa::{start_2fa_challenge, TwoFactorMethod},
+    },
 };
 
 #[derive(Deserialize)]
@@ -14,11 +18,36 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+// The login route can return 2 possible success responses.
+// This enum models each response!
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    RegularAuth,
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+// If a user requires 2FA, this JSON body should be returned!
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorAuthResponse {
+    pub message: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+    // Tells the client whether to ask for an emailed or an authenticator app code
+    #[serde(rename = "twoFactorMethod")]
+    pub two_factor_method: TwoFactorMethod,
+}
+
 pub async fn login(
     State(state): State<AppState>,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Such deployments log in with emailed links or passkeys instead
+    if !state.settings.password_login_enabled {
+        return (jar, Err(AuthAPIError::PasswordLoginDisabled));
+    }
+
     // Parse and validate email
     let email = match Email::parse(request.email) {
         Ok(email) => email,
@@ -31,32 +60,95 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let user_store = &state.user_store.read().await;
-
     // Validate user credentials
-    if user_store
-        .validate_user(&email, &password)
-        .await
-        .is_err()
-    {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    if let Err(e) = verify_password_with_lockout(&state, &email, &password).await {
+        return (jar, Err(e));
     }
 
     // Get user
-    let _user = match user_store.get_user(&email).await {
+    let user = match state.user_store.read().await.get_user(&email).await {
         Ok(user) => user,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
+    // Only checked after the password, so this does not reveal which
+    // addresses have an account
+    if state.settings.email_verification.required && !user.email_verified {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    // Handle request based on user's 2FA configuration
+    match user.requires_2fa {
+        true => handle_2fa(&user, &state, jar).await,
+        false => handle_no_2fa(&user, &state, jar).await,
+    }
+}
+
+pub(crate) async fn handle_2fa(
+    user: &User,
+    state: &AppState,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    // /verify-2fa checks the code later
+    let (login_attempt_id, two_factor_method) =
+        match start_2fa_challenge(state, user, "2FA Code", |code| code.as_ref().to_owned()).await {
+            Ok(challenge) => challenge,
+            Err(e) => return (jar, Err(e)),
+        };
+
+    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+        message: "2FA required".to_owned(),
+        login_attempt_id: login_attempt_id.as_ref().to_owned(),
+        two_factor_method,
+    }));
+
+    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+}
+
+pub(crate) async fn handle_no_2fa(
+    user: &User,
+    state: &AppState,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    // Start a new refresh token family for this login
+    let family_id = new_refresh_family_id();
+
     // Call the generate_auth_cookie function defined in the auth module.
     // If the function call fails return AuthAPIError::UnexpectedError.
-    let auth_cookie = match generate_auth_cookie(&email) {
+    let auth_cookie = match generate_auth_cookie(
+        user,
+        &family_id,
+        &state.settings.jwt,
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
         Ok(cookie) => cookie,
         Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
     };
 
-    let updated_jar = jar.add(auth_cookie);
+    let refresh_cookie = match generate_refresh_cookie(
+        &user.email,
+        &family_id,
+        state.refresh_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
 
-    (updated_jar, Ok(StatusCode::OK.into_response()))
-}
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
 
+    (
+        updated_jar,
+        Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))),
+    )
+}
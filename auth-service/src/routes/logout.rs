@@ -1,6 +1,66 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
 
-pub async fn logout() -> impl IntoResponse {
-    StatusCode::OK.into_response()
-}
+use crate::{app_state::AppState, domain::AuthAPIError, utils::auth::validate_token};
+
+pub async fn logout(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Retrieve JWT cookie from the `CookieJar`
+    let cookie_name = state.settings.jwt.cookie_name.clone();
+    let cookie = match jar.get(&cookie_name) {
+        Some(cookie) => cookie,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let token = cookie.value().to_owned();
+
+    // Validate JWT token, rejecting tokens that were already banned
+    let claims = match validate_token(
+        &token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
 
+    // Ban the token ID so the token can no longer be used before it expires
+    if state
+        .banned_token_store
+        .write()
+        .await
+        .add_token(claims.jti)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    // Revoke this session's refresh tokens so it cannot be resumed. The
+    // user's sessions on other devices stay logged in.
+    if let Some(family_id) = &claims.sid {
+        if state
+            .refresh_token_store
+            .write()
+            .await
+            .revoke_family(family_id)
+            .await
+            .is_err()
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError));
+        }
+    }
+
+    // Remove JWT and refresh token cookies from the `CookieJar`
+    let jar = jar
+        .remove(Cookie::build(cookie_name).path("/"))
+        .remove(Cookie::build(state.settings.jwt.refresh_cookie_name.clone()).path("/refresh"));
+
+    (jar, Ok(StatusCode::OK))
+}
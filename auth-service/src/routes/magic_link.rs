@@ -0,0 +1,99 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, EmailTokenPurpose, UserStoreError},
+    routes::login::{handle_2fa, handle_no_2fa},
+    utils::{
+        email_links::{consume_email_token, send_email_link_to_account},
+        rate_limit::rate_limit_emails_to,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyMagicLinkRequest {
+    pub token: String,
+}
+
+// Always answers the same way, so the response does not reveal which
+// addresses have an account
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    Json(request): Json<MagicLinkRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    rate_limit_emails_to(
+        &state,
+        "magic-link",
+        &email,
+        state.settings.magic_link.request_limit,
+    )
+    .await?;
+
+    send_email_link_to_account(
+        &state,
+        email,
+        EmailTokenPurpose::MagicLogin,
+        state.settings.magic_link.token_ttl_seconds,
+    );
+
+    let response = Json(MagicLinkResponse {
+        message: "If the address belongs to an account, a login link has been sent".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+// The link stands in for the password, so users with 2FA are still asked
+// for a code, just as after /login
+pub async fn verify_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<VerifyMagicLinkRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email =
+        match consume_email_token(&state, request.token, EmailTokenPurpose::MagicLogin).await {
+            Ok(email) => email,
+            Err(e) => return (jar, Err(e)),
+        };
+
+    let mut user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    // Opening the link proves the user can read mail sent to the address
+    if !user.email_verified {
+        if let Err(e) = state
+            .user_store
+            .write()
+            .await
+            .mark_email_verified(&email)
+            .await
+        {
+            let error = match e {
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                _ => AuthAPIError::UnexpectedError,
+            };
+            return (jar, Err(error));
+        }
+        user.email_verified = true;
+    }
+
+    match user.requires_2fa {
+        true => handle_2fa(&user, &state, jar).await,
+        false => handle_no_2fa(&user, &state, jar).await,
+    }
+}
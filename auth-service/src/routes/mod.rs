@@ -1,11 +1,37 @@
+mod admin;
+mod change_password;
+mod delete_account;
+mod jwks;
 mod login;
 mod logout;
+mod magic_link;
+mod oauth;
+mod passkey;
+mod reauthenticate;
+mod recovery_codes;
+mod refresh;
+mod reset_password;
 pub mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
 
+pub use admin::*;
+pub use change_password::*;
+pub use delete_account::*;
+pub use jwks::*;
 pub use login::*;
 pub use logout::*;
+pub use magic_link::*;
+pub use oauth::*;
+pub use passkey::*;
+pub use reauthenticate::*;
+pub use recovery_codes::*;
+pub use refresh::*;
+pub use reset_password::*;
 pub use signup::*;
+pub use totp::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
@@ -0,0 +1,402 @@
+use axum::{
+    extract::{Query, RawQuery, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Redirect},
+    Form, Json,
+};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthorizationCode, AuthorizationCodeRecord, AuthorizationCodeStoreError, ClientId,
+        ClientSecret, CodeChallenge, Email, OAuthClient, OAuthClientStoreError, OAuthError,
+        RedirectUri,
+    },
+    utils::auth::{authenticated_email, generate_access_token},
+};
+
+// The query of an authorization request (RFC 6749 section 4.1.1)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizeParams {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub response_type: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    // Returned to the client untouched
+    pub state: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsentResponse {
+    #[serde(rename = "clientName")]
+    pub client_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConsentRequest {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    pub approved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsentDecisionResponse {
+    // Where the UI sends the user next, with either a code or an error
+    #[serde(rename = "redirectUri")]
+    pub redirect_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    // Clients can authenticate with these instead of HTTP Basic auth
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+}
+
+// Send the user back to the client with an authorization code. Users who are
+// not logged in, or have not allowed the client yet, go through the UI first,
+// which comes back here once they have.
+pub async fn authorize(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    RawQuery(query): RawQuery,
+    Query(params): Query<AuthorizeParams>,
+) -> Result<Redirect, OAuthError> {
+    let (client, redirect_uri) = check_client(&state, &params).await?;
+
+    let code_challenge = match check_request(&params) {
+        Ok(code_challenge) => code_challenge,
+        Err(e) => return Ok(Redirect::to(&error_redirect(&redirect_uri, e, &params))),
+    };
+
+    let Ok(email) = authenticated_email(&state, &jar).await else {
+        return Ok(Redirect::to(&ui_url(query)));
+    };
+
+    let has_consent = state
+        .oauth_client_store
+        .read()
+        .await
+        .has_consent(&client.client_id, &email)
+        .await
+        .map_err(|_| OAuthError::UnexpectedError)?;
+
+    if !has_consent {
+        return Ok(Redirect::to(&ui_url(query)));
+    }
+
+    let uri = issue_code(
+        &state,
+        &client,
+        redirect_uri,
+        code_challenge,
+        email,
+        &params,
+    )
+    .await?;
+    Ok(Redirect::to(&uri))
+}
+
+// Tells the UI which client is asking for consent
+pub async fn get_consent(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Query(params): Query<AuthorizeParams>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let (client, _) = check_client(&state, &params).await?;
+    check_request(&params)?;
+
+    authenticated_email(&state, &jar)
+        .await
+        .map_err(|_| OAuthError::LoginRequired)?;
+
+    Ok(Json(ConsentResponse {
+        client_name: client.name,
+    }))
+}
+
+// Record the user's decision. Consent is remembered, so the user is not asked
+// again the next time the client sends them here.
+pub async fn post_consent(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConsentRequest>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let params = request.params;
+    let (client, redirect_uri) = check_client(&state, &params).await?;
+    let code_challenge = check_request(&params)?;
+
+    let email = authenticated_email(&state, &jar)
+        .await
+        .map_err(|_| OAuthError::LoginRequired)?;
+
+    if !request.approved {
+        return Ok(Json(ConsentDecisionResponse {
+            redirect_uri: error_redirect(&redirect_uri, OAuthError::AccessDenied, &params),
+        }));
+    }
+
+    state
+        .oauth_client_store
+        .write()
+        .await
+        .add_consent(&client.client_id, &email)
+        .await
+        .map_err(|_| OAuthError::UnexpectedError)?;
+
+    let redirect_uri = issue_code(
+        &state,
+        &client,
+        redirect_uri,
+        code_challenge,
+        email,
+        &params,
+    )
+    .await?;
+
+    Ok(Json(ConsentDecisionResponse { redirect_uri }))
+}
+
+// Exchange an authorization code for an access token. Only the client the
+// code was issued to can exchange it, and only with the PKCE verifier.
+pub async fn token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let client = authenticate_client(&state, &headers, &request).await?;
+
+    match request.grant_type.as_deref() {
+        Some("authorization_code") => {}
+        Some(_) => return Err(OAuthError::UnsupportedGrantType),
+        None => return Err(OAuthError::InvalidRequest),
+    }
+
+    let code = request.code.ok_or(OAuthError::InvalidRequest)?;
+    let code = AuthorizationCode::parse(code).map_err(|_| OAuthError::InvalidGrant)?;
+
+    // The code is used up even if the checks below fail, so an intercepted
+    // code can only be tried once
+    let record = state
+        .authorization_code_store
+        .write()
+        .await
+        .consume_code(&code)
+        .await
+        .map_err(|e| match e {
+            AuthorizationCodeStoreError::CodeNotFound => OAuthError::InvalidGrant,
+            AuthorizationCodeStoreError::UnexpectedError => OAuthError::UnexpectedError,
+        })?;
+
+    let is_valid = record.client_id == client.client_id
+        && record.expires_at >= Utc::now().timestamp()
+        && request.redirect_uri.as_deref() == Some(record.redirect_uri.as_ref())
+        && request
+            .code_verifier
+            .is_some_and(|verifier| record.code_challenge.verify(&verifier));
+
+    if !is_valid {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    // The user may have deleted their account since logging in
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(&record.email)
+        .await
+        .map_err(|_| OAuthError::InvalidGrant)?;
+
+    let access_token = generate_access_token(
+        &user,
+        &client.client_id,
+        &state.settings.jwt,
+        &*state.jwt_keyring.read().await,
+    )
+    .map_err(|_| OAuthError::UnexpectedError)?;
+
+    let response = Json(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_owned(),
+        expires_in: state.settings.jwt.token_ttl_seconds,
+    });
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], response))
+}
+
+// Until the client and redirect URI are known to be right, errors are shown
+// to the user instead of being sent to the redirect URI, so /authorize can't
+// be used to send users to arbitrary sites
+async fn check_client(
+    state: &AppState,
+    params: &AuthorizeParams,
+) -> Result<(OAuthClient, RedirectUri), OAuthError> {
+    let client = get_client(state, params.client_id.clone()).await?;
+
+    let redirect_uri = client
+        .redirect_uri(&params.redirect_uri)
+        .cloned()
+        .ok_or(OAuthError::InvalidRequest)?;
+
+    Ok((client, redirect_uri))
+}
+
+// Only the authorization code flow with PKCE is supported. The `plain`
+// challenge method would give the verifier away, so only S256 is accepted.
+fn check_request(params: &AuthorizeParams) -> Result<CodeChallenge, OAuthError> {
+    match params.response_type.as_deref() {
+        Some("code") => {}
+        Some(_) => return Err(OAuthError::UnsupportedResponseType),
+        None => return Err(OAuthError::InvalidRequest),
+    }
+
+    if params.code_challenge_method.as_deref() != Some("S256") {
+        return Err(OAuthError::InvalidRequest);
+    }
+
+    params
+        .code_challenge
+        .clone()
+        .and_then(|code_challenge| CodeChallenge::parse(code_challenge).ok())
+        .ok_or(OAuthError::InvalidRequest)
+}
+
+// Confidential clients authenticate with HTTP Basic auth or with their
+// credentials in the body, public clients only send their id
+async fn authenticate_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &TokenRequest,
+) -> Result<OAuthClient, OAuthError> {
+    let basic_credentials = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .map(|credentials| {
+            STANDARD
+                .decode(credentials)
+                .ok()
+                .and_then(|credentials| String::from_utf8(credentials).ok())
+                .and_then(|credentials| {
+                    let (id, secret) = credentials.split_once(':')?;
+                    Some((id.to_owned(), Some(secret.to_owned())))
+                })
+                .ok_or(OAuthError::InvalidClient)
+        })
+        .transpose()?;
+
+    let (client_id, secret) = match basic_credentials {
+        Some(credentials) => credentials,
+        None => (
+            request.client_id.clone().ok_or(OAuthError::InvalidClient)?,
+            request.client_secret.clone(),
+        ),
+    };
+
+    let secret = secret
+        .map(ClientSecret::parse)
+        .transpose()
+        .map_err(|_| OAuthError::InvalidClient)?;
+    let client = get_client(state, client_id).await?;
+
+    if !client.verify_secret(secret.as_ref()) {
+        return Err(OAuthError::InvalidClient);
+    }
+
+    Ok(client)
+}
+
+async fn get_client(state: &AppState, client_id: String) -> Result<OAuthClient, OAuthError> {
+    let client_id = ClientId::parse(client_id).map_err(|_| OAuthError::InvalidClient)?;
+
+    state
+        .oauth_client_store
+        .read()
+        .await
+        .get_client(&client_id)
+        .await
+        .map_err(|e| match e {
+            OAuthClientStoreError::ClientNotFound => OAuthError::InvalidClient,
+            _ => OAuthError::UnexpectedError,
+        })
+}
+
+// Save a new code for the client, and return where to send the user with it
+async fn issue_code(
+    state: &AppState,
+    client: &OAuthClient,
+    redirect_uri: RedirectUri,
+    code_challenge: CodeChallenge,
+    email: Email,
+    params: &AuthorizeParams,
+) -> Result<String, OAuthError> {
+    let code = AuthorizationCode::default();
+    let record = AuthorizationCodeRecord {
+        client_id: client.client_id.clone(),
+        redirect_uri: redirect_uri.clone(),
+        code_challenge,
+        email,
+        expires_at: Utc::now().timestamp()
+            + state.settings.oauth.authorization_code_ttl_seconds as i64,
+    };
+
+    state
+        .authorization_code_store
+        .write()
+        .await
+        .add_code(code.clone(), record)
+        .await
+        .map_err(|_| OAuthError::UnexpectedError)?;
+
+    Ok(redirect_with(
+        &redirect_uri,
+        ("code", code.as_ref()),
+        params,
+    ))
+}
+
+fn error_redirect(
+    redirect_uri: &RedirectUri,
+    error: OAuthError,
+    params: &AuthorizeParams,
+) -> String {
+    redirect_with(redirect_uri, ("error", error.code()), params)
+}
+
+fn redirect_with(
+    redirect_uri: &RedirectUri,
+    param: (&str, &str),
+    params: &AuthorizeParams,
+) -> String {
+    match &params.state {
+        Some(state) => redirect_uri.with_params(&[param, ("state", state)]),
+        None => redirect_uri.with_params(&[param]),
+    }
+}
+
+// The UI keeps the original request, and sends the user back to /authorize
+// with it
+fn ui_url(query: Option<String>) -> String {
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("authorize", &query.unwrap_or_default())
+        .finish();
+    format!("/?{}", query)
+}
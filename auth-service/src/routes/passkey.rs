@@ -0,0 +1,356 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, PasskeyCeremony, PasskeyChallengeId, PasskeyChallengeRecord,
+        PasskeyChallengeStoreError, PasskeyStoreError, UserStoreError,
+    },
+    utils::{
+        auth::{
+            authenticated_email, generate_auth_cookie, generate_refresh_cookie,
+            new_refresh_family_id,
+        },
+        reauthentication::{verify_reauthentication, Reauthentication},
+        webauthn::fake_login_options,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct StartPasskeyRegistrationRequest {
+    #[serde(flatten)]
+    pub reauthentication: Reauthentication,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartPasskeyRegistrationResponse {
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    // Options for `navigator.credentials.create()`, under `publicKey`
+    #[serde(flatten)]
+    pub options: CreationChallengeResponse,
+}
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyRegistrationRequest {
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct FinishPasskeyRegistrationResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct StartPasskeyLoginRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartPasskeyLoginResponse {
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    // Options for `navigator.credentials.get()`, under `publicKey`
+    #[serde(flatten)]
+    pub options: RequestChallengeResponse,
+}
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyLoginRequest {
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    pub credential: PublicKeyCredential,
+}
+
+// Challenge the logged in user's authenticator to create a new passkey
+pub async fn start_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<StartPasskeyRegistrationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    // A passkey signs in without the password, so a stolen cookie alone
+    // must not be enough to add one
+    verify_reauthentication(&state, &email, request.reauthentication).await?;
+
+    // Stops the browser from registering an authenticator twice
+    let exclude_credentials = state
+        .passkey_store
+        .read()
+        .await
+        .get_passkeys(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+
+    // Authenticators keep one passkey per user handle on each site, so
+    // registering the same authenticator again replaces the old passkey
+    let user_handle = state
+        .user_store
+        .write()
+        .await
+        .passkey_user_handle(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let (options, registration) = state
+        .webauthn
+        .start_passkey_registration(
+            user_handle,
+            email.as_ref(),
+            email.as_ref(),
+            Some(exclude_credentials),
+        )
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let challenge_id =
+        add_challenge(&state, email, PasskeyCeremony::Registration(registration)).await?;
+
+    let response = Json(StartPasskeyRegistrationResponse {
+        challenge_id: challenge_id.as_ref().to_owned(),
+        options,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Verify the new passkey created by the authenticator and save it
+pub async fn finish_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<FinishPasskeyRegistrationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    let record = consume_challenge(&state, request.challenge_id).await?;
+
+    // The challenge must have been issued to this user, for a registration
+    let registration = match record.ceremony {
+        PasskeyCeremony::Registration(registration) if record.email == email => registration,
+        _ => return Err(AuthAPIError::IncorrectCredentials),
+    };
+
+    let passkey = state
+        .webauthn
+        .finish_passkey_registration(&request.credential, &registration)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    state
+        .passkey_store
+        .write()
+        .await
+        .add_passkey(&email, passkey)
+        .await
+        .map_err(|e| match e {
+            PasskeyStoreError::PasskeyAlreadyExists => AuthAPIError::InvalidCredentials,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    let response = Json(FinishPasskeyRegistrationResponse {
+        message: "Passkey registered".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Challenge the user to sign in with one of their passkeys. Addresses
+// without passkeys are challenged with made-up ones, so like /login, this
+// doesn't tell whether the address has an account.
+pub async fn start_passkey_login(
+    State(state): State<AppState>,
+    Json(request): Json<StartPasskeyLoginRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Deleted users are not found here, although their passkeys are only
+    // removed once they are purged
+    let user = state.user_store.read().await.get_user(&email).await;
+
+    let passkeys = match user {
+        Ok(_) => state
+            .passkey_store
+            .read()
+            .await
+            .get_passkeys(&email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?,
+        Err(UserStoreError::UserNotFound) => Vec::new(),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    if passkeys.is_empty() {
+        let options = fake_login_options(&state.settings, &email)
+            .map_err(|_| AuthAPIError::UnexpectedError)?
+            .ok_or(AuthAPIError::IncorrectCredentials)?;
+
+        // The challenge is not stored, so it can never be answered
+        let response = Json(StartPasskeyLoginResponse {
+            challenge_id: PasskeyChallengeId::default().as_ref().to_owned(),
+            options,
+        });
+
+        return Ok((StatusCode::OK, response));
+    }
+
+    let (options, authentication) = state
+        .webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let challenge_id = add_challenge(
+        &state,
+        email,
+        PasskeyCeremony::Authentication(authentication),
+    )
+    .await?;
+
+    let response = Json(StartPasskeyLoginResponse {
+        challenge_id: challenge_id.as_ref().to_owned(),
+        options,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Verify the authenticator's signature and log the user in. The passkey
+// already proves possession and user verification, so 2FA is not asked for.
+pub async fn finish_passkey_login(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<FinishPasskeyLoginRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let record = match consume_challenge(&state, request.challenge_id).await {
+        Ok(record) => record,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let authentication = match record.ceremony {
+        PasskeyCeremony::Authentication(authentication) => authentication,
+        PasskeyCeremony::Registration(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    let result = match state
+        .webauthn
+        .finish_passkey_authentication(&request.credential, &authentication)
+    {
+        Ok(result) => result,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    let user = match state.user_store.read().await.get_user(&record.email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    if state.settings.email_verification.required && !user.email_verified {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    // Keeps the signature counter current, so a cloned authenticator can
+    // be detected
+    if let Err(e) = state
+        .passkey_store
+        .write()
+        .await
+        .update_passkey(&user.email, &result)
+        .await
+    {
+        let error = match e {
+            PasskeyStoreError::PasskeyNotFound => AuthAPIError::IncorrectCredentials,
+            _ => AuthAPIError::UnexpectedError,
+        };
+        return (jar, Err(error));
+    }
+
+    // Start a new refresh token family for this login
+    let family_id = new_refresh_family_id();
+
+    let auth_cookie = match generate_auth_cookie(
+        &user,
+        &family_id,
+        &state.settings.jwt,
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let refresh_cookie = match generate_refresh_cookie(
+        &user.email,
+        &family_id,
+        state.refresh_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    (updated_jar, Ok(StatusCode::OK))
+}
+
+async fn add_challenge(
+    state: &AppState,
+    email: Email,
+    ceremony: PasskeyCeremony,
+) -> Result<PasskeyChallengeId, AuthAPIError> {
+    let challenge_id = PasskeyChallengeId::default();
+    let record = PasskeyChallengeRecord {
+        email,
+        ceremony,
+        expires_at: Utc::now().timestamp() + state.settings.webauthn.challenge_ttl_seconds as i64,
+    };
+
+    state
+        .passkey_challenge_store
+        .write()
+        .await
+        .add_challenge(challenge_id.clone(), record)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(challenge_id)
+}
+
+// Each challenge can only be answered once, before it expires
+async fn consume_challenge(
+    state: &AppState,
+    challenge_id: String,
+) -> Result<PasskeyChallengeRecord, AuthAPIError> {
+    let challenge_id =
+        PasskeyChallengeId::parse(challenge_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let record = state
+        .passkey_challenge_store
+        .write()
+        .await
+        .consume_challenge(&challenge_id)
+        .await
+        .map_err(|e| match e {
+            PasskeyChallengeStoreError::ChallengeNotFound => AuthAPIError::IncorrectCredentials,
+            PasskeyChallengeStoreError::UnexpectedError => AuthAPIError::UnexpectedError,
+        })?;
+
+    if record.expires_at < Utc::now().timestamp() {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    Ok(record)
+}
@@ -0,0 +1,53 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, TwoFACode, UserStoreError},
+    utils::{auth::authenticated_email, two_fa::start_2fa_challenge},
+};
+
+use super::TwoFactorAuthResponse;
+
+// Challenge the logged in user to confirm a sensitive change without their
+// password, for deployments with password login turned off. Users with an
+// authenticator app answer with a code from it, everyone else is emailed one.
+pub async fn reauthenticate(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Otherwise the password confirms changes, as it always has
+    if state.settings.password_login_enabled {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let email = authenticated_email(&state, &jar).await?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    let content = |code: &TwoFACode| {
+        format!(
+            "Your code to confirm the change to your account: {}",
+            code.as_ref()
+        )
+    };
+    let (login_attempt_id, two_factor_method) =
+        start_2fa_challenge(&state, &user, "Confirm the change to your account", content).await?;
+
+    let response = Json(TwoFactorAuthResponse {
+        message: "Confirmation required".to_owned(),
+        login_attempt_id: login_attempt_id.as_ref().to_owned(),
+        two_factor_method,
+    });
+
+    Ok((StatusCode::OK, response))
+}
@@ -0,0 +1,96 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RecoveryCode, UserStoreError},
+    utils::{
+        auth::authenticated_email,
+        reauthentication::{verify_reauthentication, Reauthentication},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct RegenerateRecoveryCodesRequest {
+    #[serde(flatten)]
+    pub reauthentication: Reauthentication,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct RecoveryCodesResponse {
+    // Only returned when a new batch is generated, since only hashes are kept
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
+    pub remaining: usize,
+}
+
+pub async fn get_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    let response = Json(RecoveryCodesResponse {
+        recovery_codes: None,
+        remaining: user.recovery_codes.len(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Replace the user's recovery codes, so any they had stop working
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<RegenerateRecoveryCodesRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    // Recovery codes get past 2FA, so a stolen cookie alone must not be
+    // enough to get new ones
+    verify_reauthentication(&state, &email, request.reauthentication).await?;
+
+    let recovery_codes = RecoveryCode::generate_batch();
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_recovery_codes(
+            &email,
+            recovery_codes.iter().map(RecoveryCode::hash).collect(),
+        )
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    let response = Json(RecoveryCodesResponse {
+        remaining: recovery_codes.len(),
+        recovery_codes: Some(
+            recovery_codes
+                .iter()
+                .map(|code| code.as_ref().to_owned())
+                .collect(),
+        ),
+    });
+
+    Ok((StatusCode::OK, response))
+}
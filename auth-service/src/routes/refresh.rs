@@ -0,0 +1,91 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RefreshToken, RefreshTokenStoreError},
+    utils::auth::{generate_auth_cookie, generate_refresh_cookie},
+};
+
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Retrieve refresh token cookie from the `CookieJar`
+    let token = match jar.get(&state.settings.jwt.refresh_cookie_name) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let token = match RefreshToken::parse(token) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let record = {
+        let mut refresh_token_store = state.refresh_token_store.write().await;
+
+        match refresh_token_store.consume_token(&token).await {
+            Ok(record) => record,
+            // A token that was already rotated is being replayed, so it has
+            // probably been stolen. Revoke every token issued from the same login.
+            Err(RefreshTokenStoreError::TokenReused(record)) => {
+                if refresh_token_store
+                    .revoke_family(&record.family_id)
+                    .await
+                    .is_err()
+                {
+                    return (jar, Err(AuthAPIError::UnexpectedError));
+                }
+                return (jar, Err(AuthAPIError::InvalidToken));
+            }
+            Err(RefreshTokenStoreError::TokenNotFound) => {
+                return (jar, Err(AuthAPIError::InvalidToken))
+            }
+            Err(RefreshTokenStoreError::UnexpectedError) => {
+                return (jar, Err(AuthAPIError::UnexpectedError))
+            }
+        }
+    };
+
+    if record.expires_at <= Utc::now().timestamp() {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    }
+
+    // The user may have been removed since the token was issued. Their
+    // roles are read again, so role changes apply from the next refresh.
+    let user = match state.user_store.read().await.get_user(&record.email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let auth_cookie = match generate_auth_cookie(
+        &user,
+        &record.family_id,
+        &state.settings.jwt,
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    // Rotate the refresh token, keeping it in the same family
+    let refresh_cookie = match generate_refresh_cookie(
+        &record.email,
+        &record.family_id,
+        state.refresh_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    (updated_jar, Ok(StatusCode::OK))
+}
@@ -0,0 +1,113 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, EmailTokenPurpose, Password, PasswordHash},
+    utils::{
+        email_links::{consume_email_token, send_email_link_to_account},
+        rate_limit::rate_limit_emails_to,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ResetPasswordResponse {
+    pub message: String,
+}
+
+// Always answers the same way, so the response does not reveal which
+// addresses have an account
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(request): Json<ForgotPasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    rate_limit_emails_to(
+        &state,
+        "forgot-password",
+        &email,
+        state.settings.password_reset.request_limit,
+    )
+    .await?;
+
+    send_email_link_to_account(
+        &state,
+        email,
+        EmailTokenPurpose::ResetPassword,
+        state.settings.password_reset.token_ttl_seconds,
+    );
+
+    let response = Json(ResetPasswordResponse {
+        message: "If the address belongs to an account, a reset link has been sent".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Checked before the token is used up, so a rejected password does not
+    // cost the user their link
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let email =
+        consume_email_token(&state, request.token, EmailTokenPurpose::ResetPassword).await?;
+
+    let password_hash = PasswordHash::hash(&password, state.settings.password_hashing)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .update_password(&email, password_hash)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Sessions started with the old password end, their access tokens are no
+    // longer accepted and they can not be refreshed anymore
+    state
+        .user_store
+        .write()
+        .await
+        .increment_session_version(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_user_tokens(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // The lockout protects the old password, the new one starts afresh
+    state
+        .rate_limit_store
+        .write()
+        .await
+        .reset_login_failures(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let response = Json(ResetPasswordResponse {
+        message: "Password updated".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
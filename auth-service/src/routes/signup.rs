@@ -3,13 +3,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password, User, UserStoreError},
+    domain::{AuthAPIError, Email, Password, PasswordHash, RecoveryCode, User, UserStoreError},
 };
 
+use super::send_verification_email;
+
+// The password can be left out when password login is turned off
 #[derive(Deserialize)]
 pub struct SignupRequest {
     pub email: String,
-    pub password: String,
+    pub password: Option<String>,
     #[serde(rename = "requires2FA")]
     pub requires_2fa: bool,
 }
@@ -17,6 +20,13 @@ pub struct SignupRequest {
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct SignupResponse {
     pub message: String,
+    // Only shown once, for users who signed up with 2FA
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
 }
 
 pub async fn signup(
@@ -26,22 +36,52 @@ pub async fn signup(
     // Parse and validate email
     let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    // Parse and validate password
-    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    // Only the hash of the password is ever stored. Users who sign up
+    // without one can set it later through /forgot-password.
+    let password_hashing = state.settings.password_hashing;
+    let password_hash = match request.password {
+        Some(password) => {
+            let password =
+                Password::parse(password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            PasswordHash::hash(&password, password_hashing).await
+        }
+        None if !state.settings.password_login_enabled => {
+            PasswordHash::hash_random_password(password_hashing).await
+        }
+        None => return Err(AuthAPIError::InvalidCredentials),
+    }
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    // Users with 2FA get recovery codes in case they lose their second factor
+    let recovery_codes = request.requires_2fa.then(RecoveryCode::generate_batch);
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .flatten()
+        .map(RecoveryCode::hash)
+        .collect();
 
-    let mut user_store = state.user_store.write().await;
+    let user = User::new(email.clone(), password_hash, request.requires_2fa)
+        .with_recovery_codes(recovery_code_hashes);
 
     // Handle the result from add_user
-    match user_store.add_user(user).await {
-        Ok(_) => {
-            let response = Json(SignupResponse {
-                message: "User created successfully!".to_string(),
-            });
-            Ok((StatusCode::CREATED, response))
-        }
-        Err(UserStoreError::UserAlreadyExists) => Err(AuthAPIError::UserAlreadyExists),
-        Err(_) => Err(AuthAPIError::UnexpectedError),
+    match state.user_store.write().await.add_user(user).await {
+        Ok(_) => {}
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
     }
-}
\ No newline at end of file
+
+    // New accounts start unverified, the link in this email verifies them.
+    // Without it the account could not be used, so it is removed again and
+    // the address can sign up once more.
+    if let Err(error) = send_verification_email(&state, &email).await {
+        let _ = state.user_store.write().await.delete_user(&email).await;
+        return Err(error);
+    }
+
+    let response = Json(SignupResponse {
+        message: "User created successfully!".to_string(),
+        recovery_codes: recovery_codes
+            .map(|codes| codes.iter().map(|code| code.as_ref().to_owned()).collect()),
+    });
+    Ok((StatusCode::CREATED, response))
+}
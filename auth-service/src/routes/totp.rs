@@ -0,0 +1,157 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RecoveryCode, TotpSecret, TwoFACode, UserStoreError},
+    utils::{
+        auth::authenticated_email,
+        reauthentication::{verify_reauthentication, Reauthentication},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct EnrollTotpRequest {
+    #[serde(flatten)]
+    pub reauthentication: Reauthentication,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct EnrollTotpResponse {
+    // For entering the secret by hand when the QR code can't be scanned
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+    // The otpauth URI as a base64 encoded PNG
+    #[serde(rename = "qrCode")]
+    pub qr_code: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ConfirmTotpResponse {
+    pub message: String,
+    // Only shown once, when confirming turns 2FA on
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+// Start setting up an authenticator app. Until the new secret is confirmed,
+// the user keeps logging in as before.
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<EnrollTotpRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    // Confirming replaces the user's second factor, so a stolen cookie alone
+    // must not be enough
+    verify_reauthentication(&state, &email, request.reauthentication).await?;
+
+    let secret = TotpSecret::default();
+    let issuer = &state.settings.totp_issuer;
+    let otpauth_uri = secret
+        .otpauth_uri(issuer, &email)
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let qr_code = secret
+        .qr_code_png(issuer, &email)
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_pending_totp_secret(&email, secret.clone())
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    let response = Json(EnrollTotpResponse {
+        secret: secret.as_ref().to_owned(),
+        otpauth_uri,
+        qr_code,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Proves the user's app produces valid codes, then requires them at login
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &jar).await?;
+
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let mut user_store = state.user_store.write().await;
+
+    let user = match user_store.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    let secret = match user_store.get_pending_totp_secret(&email).await {
+        Ok(Some(secret)) => secret,
+        // Nothing to confirm without calling /totp/enroll first
+        Ok(None) => return Err(AuthAPIError::InvalidCredentials),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    let Some(time_step) = secret.verify(&code) else {
+        return Err(AuthAPIError::IncorrectCredentials);
+    };
+
+    user_store
+        .confirm_totp_secret(&email, &secret)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    // The code just shown to confirm the app can't also be used to log in
+    user_store
+        .use_totp_step(&email, time_step)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Users who already had 2FA keep the recovery codes they have
+    let recovery_codes = match user.requires_2fa {
+        true => None,
+        false => {
+            let recovery_codes = RecoveryCode::generate_batch();
+            user_store
+                .set_recovery_codes(
+                    &email,
+                    recovery_codes.iter().map(RecoveryCode::hash).collect(),
+                )
+                .await
+                .map_err(|_| AuthAPIError::UnexpectedError)?;
+            Some(
+                recovery_codes
+                    .iter()
+                    .map(|code| code.as_ref().to_owned())
+                    .collect(),
+            )
+        }
+    };
+
+    let response = Json(ConfirmTotpResponse {
+        message: "Authenticator app enabled".to_owned(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
@@ -1,6 +1,86 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
 
-pub async fn verify_2fa() -> impl IntoResponse {
-    StatusCode::OK.into_response()
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId},
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie, new_refresh_family_id},
+        two_fa::{verify_2fa_code, TwoFactorProof},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct Verify2FARequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: String,
 }
 
+pub async fn verify_2fa(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<Verify2FARequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Parse and validate the request fields
+    let email = match Email::parse(request.email) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
+        Ok(login_attempt_id) => login_attempt_id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // Either the 2FA code or one of the user's recovery codes
+    let proof = match TwoFactorProof::parse(request.two_fa_code) {
+        Ok(proof) => proof,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // The user's roles go into the auth token, and their authenticator app
+    // secret decides how the code is checked
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    if let Err(e) = verify_2fa_code(&state, &user, login_attempt_id, proof).await {
+        return (jar, Err(e));
+    }
+
+    // Start a new refresh token family for this login
+    let family_id = new_refresh_family_id();
+
+    let auth_cookie = match generate_auth_cookie(
+        &user,
+        &family_id,
+        &state.settings.jwt,
+        state.jwt_keyring.clone(),
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let refresh_cookie = match generate_refresh_cookie(
+        &email,
+        &family_id,
+        state.refresh_token_store.clone(),
+        &state.settings.jwt,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    (updated_jar, Ok(StatusCode::OK))
+}
@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, EmailTokenPurpose},
+    utils::{
+        email_links::{consume_email_token, send_email_link, send_email_link_to_account},
+        rate_limit::rate_limit_emails_to,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
+
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // The token is used up first, so a link can never be used twice, even
+    // if the rest of this request fails
+    let email = consume_email_token(&state, query.token, EmailTokenPurpose::VerifyEmail).await?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .mark_email_verified(&email)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let response = Json(VerifyEmailResponse {
+        message: "Email address verified".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+// Always answers the same way, so the response does not reveal which
+// addresses have an unverified account
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    rate_limit_emails_to(
+        &state,
+        "resend-verification",
+        &email,
+        state.settings.email_verification.resend_limit,
+    )
+    .await?;
+
+    send_email_link_to_account(
+        &state,
+        email,
+        EmailTokenPurpose::VerifyEmail,
+        state.settings.email_verification.token_ttl_seconds,
+    );
+
+    let response = Json(VerifyEmailResponse {
+        message: "If the address belongs to an unverified account, a new link has been sent"
+            .to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+pub(crate) async fn send_verification_email(
+    state: &AppState,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    send_email_link(
+        state,
+        email,
+        EmailTokenPurpose::VerifyEmail,
+        state.settings.email_verification.token_ttl_seconds,
+    )
+    .await
+}
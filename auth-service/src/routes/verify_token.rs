@@ -1,6 +1,48 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::collections::BTreeSet;
 
-pub async fn verify_token() -> impl IntoResponse {
-    StatusCode::OK.into_response()
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Role},
+    utils::auth::validate_token,
+};
+
+#[derive(Deserialize)]
+pub struct VerifyTokenRequest {
+    pub token: String,
+    // Service asking, which must be in the token's `aud` claim
+    pub audience: Option<String>,
 }
 
+pub async fn verify_token(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Expired, tampered, malformed, banned and revoked tokens are all rejected
+    // the same way
+    let claims = validate_token(
+        &request.token,
+        request.audience.as_deref(),
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.jwt_keyring.clone(),
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let response = Json(VerifyTokenResponse {
+        email: claims.sub,
+        roles: claims.roles,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Who the token belongs to, so callers can authorize the request
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyTokenResponse {
+    pub email: String,
+    pub roles: BTreeSet<Role>,
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::domain::{Email, EmailClient, EmailClientError};
+
+// Writes every outgoing email to `outbox_dir` as an RFC 5322 `.eml` file,
+// so mail can be read with any mail client during local development
+pub struct FileOutboxEmailClient {
+    outbox_dir: PathBuf,
+    sender: Email,
+}
+
+impl FileOutboxEmailClient {
+    pub fn new(outbox_dir: impl Into<PathBuf>, sender: Email) -> Self {
+        Self {
+            outbox_dir: outbox_dir.into(),
+            sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for FileOutboxEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), EmailClientError> {
+        tokio::fs::create_dir_all(&self.outbox_dir)
+            .await
+            .map_err(|_| EmailClientError::UnexpectedError)?;
+
+        let id = Uuid::new_v4();
+        let message = format_message(&self.sender, recipient, subject, content, &id);
+
+        // Prefix with the timestamp so a directory listing is in send order
+        let file_name = format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"), id);
+
+        tokio::fs::write(self.outbox_dir.join(file_name), message)
+            .await
+            .map_err(|_| EmailClientError::UnexpectedError)
+    }
+}
+
+fn format_message(
+    sender: &Email,
+    recipient: &Email,
+    subject: &str,
+    content: &str,
+    id: &Uuid,
+) -> String {
+    let domain = sender.as_ref().rsplit('@').next().unwrap_or("localhost");
+
+    let headers = [
+        format!("From: {}", sender.as_ref()),
+        format!("To: {}", recipient.as_ref()),
+        format!("Subject: {}", encode_header(subject)),
+        format!("Date: {}", Utc::now().to_rfc2822()),
+        format!("Message-ID: <{}@{}>", id, domain),
+        "MIME-Version: 1.0".to_owned(),
+        "Content-Type: text/plain; charset=utf-8".to_owned(),
+        "Content-Transfer-Encoding: 8bit".to_owned(),
+    ];
+
+    // RFC 5322 requires CRLF line endings throughout the message
+    let body = content.replace("\r\n", "\n").replace('\n', "\r\n");
+
+    format!("{}\r\n\r\n{}\r\n", headers.join("\r\n"), body)
+}
+
+// Header values must be single-line ASCII; anything else is sent as an
+// RFC 2047 encoded word
+fn encode_header(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?utf-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender() -> Email {
+        Email::parse("no-reply@example.com".to_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_format_message_headers_and_body() {
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+        let id = Uuid::new_v4();
+        let message = format_message(&sender(), &recipient, "Hello", "line 1\nline 2", &id);
+
+        let (headers, body) = message.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("From: no-reply@example.com\r\n"));
+        assert!(headers.contains("To: test@example.com\r\n"));
+        assert!(headers.contains("Subject: Hello\r\n"));
+        assert!(headers.contains(&format!("Message-ID: <{}@example.com>", id)));
+        assert_eq!(body, "line 1\r\nline 2\r\n");
+    }
+
+    #[test]
+    fn test_encode_header() {
+        assert_eq!(encode_header("Your code"), "Your code");
+        assert_eq!(encode_header("Bad\r\nBcc: x"), "Bad  Bcc: x");
+        assert_eq!(encode_header("Café"), "=?utf-8?B?Q2Fmw6k=?=");
+    }
+
+    #[tokio::test]
+    async fn test_send_email_writes_eml_file() {
+        let outbox_dir = std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+        let client = FileOutboxEmailClient::new(&outbox_dir, sender());
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+
+        client
+            .send_email(&recipient, "Subject", "Content")
+            .await
+            .unwrap();
+
+        let mut entries = std::fs::read_dir(&outbox_dir).unwrap();
+        let path = entries.next().unwrap().unwrap().path();
+        assert!(entries.next().is_none());
+        assert_eq!(path.extension().unwrap(), "eml");
+
+        let message = std::fs::read_to_string(&path).unwrap();
+        assert!(message.contains("To: test@example.com\r\n"));
+        assert!(message.ends_with("\r\n\r\nContent\r\n"));
+
+        std::fs::remove_dir_all(outbox_dir).unwrap();
+    }
+}
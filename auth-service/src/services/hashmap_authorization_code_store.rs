@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    AuthorizationCode, AuthorizationCodeRecord, AuthorizationCodeStore, AuthorizationCodeStoreError,
+};
+
+#[derive(Default)]
+pub struct HashmapAuthorizationCodeStore {
+    codes: HashMap<AuthorizationCode, AuthorizationCodeRecord>,
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for HashmapAuthorizationCodeStore {
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        record: AuthorizationCodeRecord,
+    ) -> Result<(), AuthorizationCodeStoreError> {
+        self.codes.insert(code, record);
+        Ok(())
+    }
+
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationCodeRecord, AuthorizationCodeStoreError> {
+        self.codes
+            .remove(code)
+            .ok_or(AuthorizationCodeStoreError::CodeNotFound)
+    }
+}
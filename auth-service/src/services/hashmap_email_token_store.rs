@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::domain::{EmailToken, EmailTokenRecord, EmailTokenStore, EmailTokenStoreError};
+
+#[derive(Default)]
+pub struct HashmapEmailTokenStore {
+    tokens: HashMap<EmailToken, EmailTokenRecord>,
+}
+
+#[async_trait::async_trait]
+impl EmailTokenStore for HashmapEmailTokenStore {
+    async fn add_token(
+        &mut self,
+        token: EmailToken,
+        record: EmailTokenRecord,
+    ) -> Result<(), EmailTokenStoreError> {
+        self.tokens.insert(token, record);
+        Ok(())
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &EmailToken,
+    ) -> Result<EmailTokenRecord, EmailTokenStoreError> {
+        self.tokens
+            .remove(token)
+            .ok_or(EmailTokenStoreError::TokenNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Email, EmailTokenPurpose};
+
+    #[tokio::test]
+    async fn test_consume_token_only_once() {
+        let mut store = HashmapEmailTokenStore::default();
+        let token = EmailToken::default();
+        let record = EmailTokenRecord {
+            email: Email::parse("test@example.com".to_owned()).unwrap(),
+            purpose: EmailTokenPurpose::VerifyEmail,
+            expires_at: 0,
+        };
+
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Err(EmailTokenStoreError::TokenNotFound));
+
+        store
+            .add_token(token.clone(), record.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.consume_token(&token).await, Ok(record));
+
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Err(EmailTokenStoreError::TokenNotFound));
+    }
+}
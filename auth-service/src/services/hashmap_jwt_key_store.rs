@@ -0,0 +1,70 @@
+use crate::domain::{JwtKeyRecord, JwtKeyStore, JwtKeyStoreError};
+
+#[derive(Default)]
+pub struct HashmapJwtKeyStore {
+    keys: Vec<JwtKeyRecord>,
+}
+
+#[async_trait::async_trait]
+impl JwtKeyStore for HashmapJwtKeyStore {
+    async fn add_key(&mut self, record: JwtKeyRecord) -> Result<(), JwtKeyStoreError> {
+        if self.keys.iter().any(|key| key.kid == record.kid) {
+            return Err(JwtKeyStoreError::KeyAlreadyExists);
+        }
+
+        self.keys.push(record);
+        self.keys.sort_by_key(|key| key.activates_at);
+        Ok(())
+    }
+
+    async fn get_keys(&self) -> Result<Vec<JwtKeyRecord>, JwtKeyStoreError> {
+        Ok(self.keys.clone())
+    }
+
+    async fn delete_keys(&mut self, activated_before: i64) -> Result<u64, JwtKeyStoreError> {
+        let count = self.keys.len();
+        self.keys.retain(|key| key.activates_at >= activated_before);
+        Ok((count - self.keys.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kid: &str, activates_at: i64) -> JwtKeyRecord {
+        JwtKeyRecord {
+            kid: kid.to_owned(),
+            algorithm: "HS256".to_owned(),
+            private_key: "c2VjcmV0".to_owned(),
+            activates_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_keys() {
+        let mut store = HashmapJwtKeyStore::default();
+        store.add_key(record("second", 20)).await.unwrap();
+        store.add_key(record("first", 10)).await.unwrap();
+
+        let result = store.add_key(record("first", 30)).await;
+        assert_eq!(result, Err(JwtKeyStoreError::KeyAlreadyExists));
+
+        let keys = store.get_keys().await.unwrap();
+        assert_eq!(keys, vec![record("first", 10), record("second", 20)]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_keys() {
+        let mut store = HashmapJwtKeyStore::default();
+        for (kid, activates_at) in [("first", 10), ("second", 20), ("third", 30)] {
+            store.add_key(record(kid, activates_at)).await.unwrap();
+        }
+
+        assert_eq!(store.delete_keys(20).await, Ok(1));
+        assert_eq!(store.delete_keys(20).await, Ok(0));
+
+        let keys = store.get_keys().await.unwrap();
+        assert_eq!(keys, vec![record("second", 20), record("third", 30)]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::domain::{ClientId, Email, OAuthClient, OAuthClientStore, OAuthClientStoreError};
+
+#[derive(Default)]
+pub struct HashmapOAuthClientStore {
+    clients: HashMap<ClientId, OAuthClient>,
+    consents: HashSet<(ClientId, Email)>,
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for HashmapOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient) -> Result<(), OAuthClientStoreError> {
+        if self.clients.contains_key(&client.client_id) {
+            return Err(OAuthClientStoreError::ClientAlreadyExists);
+        }
+
+        self.clients.insert(client.client_id.clone(), client);
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &ClientId) -> Result<OAuthClient, OAuthClientStoreError> {
+        self.clients
+            .get(client_id)
+            .cloned()
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+
+    async fn add_consent(
+        &mut self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<(), OAuthClientStoreError> {
+        if !self.clients.contains_key(client_id) {
+            return Err(OAuthClientStoreError::ClientNotFound);
+        }
+
+        self.consents.insert((client_id.clone(), email.clone()));
+        Ok(())
+    }
+
+    async fn has_consent(
+        &self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<bool, OAuthClientStoreError> {
+        Ok(self.consents.contains(&(client_id.clone(), email.clone())))
+    }
+
+    // Nothing else removes them, so a new user with the same email would
+    // inherit them
+    async fn delete_consents(&mut self, email: &Email) -> Result<(), OAuthClientStoreError> {
+        self.consents
+            .retain(|(_, consent_email)| consent_email != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::RedirectUri;
+
+    fn client() -> OAuthClient {
+        OAuthClient {
+            client_id: ClientId::default(),
+            name: "Test".to_owned(),
+            redirect_uris: vec![
+                RedirectUri::parse("http://localhost:8000/callback".to_owned()).unwrap(),
+            ],
+            secret_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_client() {
+        let mut store = HashmapOAuthClientStore::default();
+        let client = client();
+
+        let result = store.get_client(&client.client_id).await;
+        assert_eq!(result, Err(OAuthClientStoreError::ClientNotFound));
+
+        store.add_client(client.clone()).await.unwrap();
+        assert_eq!(
+            store.get_client(&client.client_id).await,
+            Ok(client.clone())
+        );
+
+        let result = store.add_client(client).await;
+        assert_eq!(result, Err(OAuthClientStoreError::ClientAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_consent() {
+        let mut store = HashmapOAuthClientStore::default();
+        let client = client();
+        let other_client = self::client();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        let result = store.add_consent(&client.client_id, &email).await;
+        assert_eq!(result, Err(OAuthClientStoreError::ClientNotFound));
+
+        store.add_client(client.clone()).await.unwrap();
+        store.add_client(other_client.clone()).await.unwrap();
+        assert!(!store.has_consent(&client.client_id, &email).await.unwrap());
+
+        store.add_consent(&client.client_id, &email).await.unwrap();
+        // Consenting again changes nothing
+        store.add_consent(&client.client_id, &email).await.unwrap();
+        assert!(store.has_consent(&client.client_id, &email).await.unwrap());
+        assert!(!store
+            .has_consent(&other_client.client_id, &email)
+            .await
+            .unwrap());
+
+        store.delete_consents(&email).await.unwrap();
+        assert!(!store.has_consent(&client.client_id, &email).await.unwrap());
+    }
+}
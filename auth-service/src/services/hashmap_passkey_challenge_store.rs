@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    PasskeyChallengeId, PasskeyChallengeRecord, PasskeyChallengeStore, PasskeyChallengeStoreError,
+};
+
+#[derive(Default)]
+pub struct HashmapPasskeyChallengeStore {
+    challenges: HashMap<PasskeyChallengeId, PasskeyChallengeRecord>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyChallengeStore for HashmapPasskeyChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        challenge_id: PasskeyChallengeId,
+        record: PasskeyChallengeRecord,
+    ) -> Result<(), PasskeyChallengeStoreError> {
+        self.challenges.insert(challenge_id, record);
+        Ok(())
+    }
+
+    async fn consume_challenge(
+        &mut self,
+        challenge_id: &PasskeyChallengeId,
+    ) -> Result<PasskeyChallengeRecord, PasskeyChallengeStoreError> {
+        self.challenges
+            .remove(challenge_id)
+            .ok_or(PasskeyChallengeStoreError::ChallengeNotFound)
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use webauthn_rs::prelude::{AuthenticationResult, Passkey};
+
+use crate::domain::{Email, PasskeyStore, PasskeyStoreError};
+
+#[derive(Default)]
+pub struct HashmapPasskeyStore {
+    passkeys: HashMap<Email, Vec<Passkey>>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for HashmapPasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: &Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        let is_registered = self
+            .passkeys
+            .values()
+            .flatten()
+            .any(|existing| existing.cred_id() == passkey.cred_id());
+        if is_registered {
+            return Err(PasskeyStoreError::PasskeyAlreadyExists);
+        }
+
+        self.passkeys
+            .entry(email.clone())
+            .or_default()
+            .push(passkey);
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        Ok(self.passkeys.get(email).cloned().unwrap_or_default())
+    }
+
+    async fn update_passkey(
+        &mut self,
+        email: &Email,
+        result: &AuthenticationResult,
+    ) -> Result<(), PasskeyStoreError> {
+        let passkey = self
+            .passkeys
+            .get_mut(email)
+            .and_then(|passkeys| {
+                passkeys
+                    .iter_mut()
+                    .find(|passkey| passkey.cred_id() == result.cred_id())
+            })
+            .ok_or(PasskeyStoreError::PasskeyNotFound)?;
+
+        passkey.update_credential(result);
+        Ok(())
+    }
+
+    // Nothing else removes them, so a new user with the same email would
+    // inherit them
+    async fn delete_passkeys(&mut self, email: &Email) -> Result<(), PasskeyStoreError> {
+        self.passkeys.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+    use webauthn_rs::prelude::{Url, Uuid, Webauthn, WebauthnBuilder};
+
+    // Register a passkey with a software authenticator, which is returned
+    // for signing in with it
+    fn register() -> (Webauthn, WebauthnAuthenticator<SoftPasskey>, Passkey) {
+        let origin = Url::parse("http://localhost:3000").unwrap();
+        let webauthn = WebauthnBuilder::new("localhost", &origin)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (options, registration) = webauthn
+            .start_passkey_registration(Uuid::new_v4(), "test", "test", None)
+            .unwrap();
+        let credential = authenticator.do_registration(origin, options).unwrap();
+        let passkey = webauthn
+            .finish_passkey_registration(&credential, &registration)
+            .unwrap();
+
+        (webauthn, authenticator, passkey)
+    }
+
+    fn sign_in(
+        webauthn: &Webauthn,
+        authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+        passkey: &Passkey,
+    ) -> AuthenticationResult {
+        let origin = Url::parse("http://localhost:3000").unwrap();
+        let (options, authentication) = webauthn
+            .start_passkey_authentication(std::slice::from_ref(passkey))
+            .unwrap();
+        let credential = authenticator.do_authentication(origin, options).unwrap();
+        webauthn
+            .finish_passkey_authentication(&credential, &authentication)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_passkey() {
+        let mut store = HashmapPasskeyStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other_email = Email::parse("other@example.com".to_owned()).unwrap();
+        let (_, _, passkey) = register();
+
+        assert_eq!(store.get_passkeys(&email).await.unwrap().len(), 0);
+
+        store.add_passkey(&email, passkey.clone()).await.unwrap();
+        let passkeys = store.get_passkeys(&email).await.unwrap();
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].cred_id(), passkey.cred_id());
+
+        // Credential IDs are unique across users
+        let result = store.add_passkey(&other_email, passkey.clone()).await;
+        assert_eq!(result, Err(PasskeyStoreError::PasskeyAlreadyExists));
+
+        store.delete_passkeys(&email).await.unwrap();
+        assert_eq!(store.get_passkeys(&email).await.unwrap().len(), 0);
+        assert_eq!(store.add_passkey(&other_email, passkey).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_update_passkey() {
+        let mut store = HashmapPasskeyStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other_email = Email::parse("other@example.com".to_owned()).unwrap();
+        let (webauthn, mut authenticator, passkey) = register();
+        let result = sign_in(&webauthn, &mut authenticator, &passkey);
+
+        store.add_passkey(&email, passkey).await.unwrap();
+
+        let update = store.update_passkey(&other_email, &result).await;
+        assert_eq!(update, Err(PasskeyStoreError::PasskeyNotFound));
+
+        store.update_passkey(&email, &result).await.unwrap();
+        // The saved passkey already reflects the sign-in
+        let mut passkey = store.get_passkeys(&email).await.unwrap().remove(0);
+        assert_eq!(passkey.update_credential(&result), Some(false));
+    }
+}
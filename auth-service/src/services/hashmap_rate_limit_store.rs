@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::{
+    Email, LockoutParams, RateLimitDecision, RateLimitParams, RateLimitStore, RateLimitStoreError,
+};
+
+#[derive(Default)]
+pub struct HashmapRateLimitStore {
+    buckets: HashMap<String, Bucket>,
+    login_failures: HashMap<Email, LoginFailures>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at_ms: i64,
+    params: RateLimitParams,
+}
+
+impl Bucket {
+    fn tokens_at(&self, now_ms: i64) -> f64 {
+        let tokens_per_ms = f64::from(self.params.per_minute) / 60_000.0;
+        let elapsed_ms = (now_ms - self.updated_at_ms).max(0) as f64;
+        (self.tokens + elapsed_ms * tokens_per_ms).min(f64::from(self.params.burst))
+    }
+}
+
+struct LoginFailures {
+    count: u32,
+    last_failure_at: i64,
+    locked_until: Option<i64>,
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for HashmapRateLimitStore {
+    async fn take_token(
+        &mut self,
+        key: &str,
+        params: RateLimitParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let now_ms = Utc::now().timestamp_millis();
+        let tokens_per_ms = f64::from(params.per_minute) / 60_000.0;
+
+        // A full bucket is the same as no bucket, so it can go
+        self.buckets
+            .retain(|_, bucket| bucket.tokens_at(now_ms) < f64::from(bucket.params.burst));
+
+        // A new client starts with a full bucket
+        let bucket = self.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: f64::from(params.burst),
+            updated_at_ms: now_ms,
+            params,
+        });
+
+        bucket.tokens = bucket.tokens_at(now_ms);
+        bucket.updated_at_ms = now_ms;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(RateLimitDecision::Allowed);
+        }
+
+        let retry_after_ms = (1.0 - bucket.tokens) / tokens_per_ms;
+        Ok(RateLimitDecision::Limited {
+            retry_after_seconds: (retry_after_ms / 1000.0).ceil() as u64,
+        })
+    }
+
+    async fn record_login_attempt(
+        &mut self,
+        email: &Email,
+        params: LockoutParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let now = Utc::now().timestamp();
+        let lockout_seconds = i64::try_from(params.lockout_seconds)
+            .map_err(|_| RateLimitStoreError::UnexpectedError)?;
+
+        // Failures older than the lockout window are not counted any more
+        self.login_failures.retain(|_, failures| {
+            failures.last_failure_at + lockout_seconds >= now
+                || failures
+                    .locked_until
+                    .is_some_and(|locked_until| locked_until > now)
+        });
+
+        let failures = self
+            .login_failures
+            .entry(email.clone())
+            .or_insert(LoginFailures {
+                count: 0,
+                last_failure_at: now,
+                locked_until: None,
+            });
+
+        if let Some(locked_until) = failures.locked_until.filter(|until| *until > now) {
+            return Ok(RateLimitDecision::Limited {
+                retry_after_seconds: (locked_until - now).max(1) as u64,
+            });
+        }
+
+        if failures.last_failure_at + lockout_seconds < now {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last_failure_at = now;
+
+        if failures.count <= params.max_failures {
+            return Ok(RateLimitDecision::Allowed);
+        }
+
+        // Start counting again once the lockout is over
+        failures.count = 0;
+        failures.locked_until = Some(now + lockout_seconds);
+        Ok(RateLimitDecision::Limited {
+            retry_after_seconds: params.lockout_seconds.max(1),
+        })
+    }
+
+    async fn reset_login_failures(&mut self, email: &Email) -> Result<(), RateLimitStoreError> {
+        self.login_failures.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse("test@example.com".to_owned()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_take_token_limits_bursts() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = RateLimitParams {
+            burst: 2,
+            per_minute: 60,
+        };
+
+        for _ in 0..2 {
+            let result = store.take_token("127.0.0.1", params).await;
+            assert_eq!(result, Ok(RateLimitDecision::Allowed));
+        }
+
+        // One request is refilled every second
+        let result = store.take_token("127.0.0.1", params).await;
+        assert_eq!(
+            result,
+            Ok(RateLimitDecision::Limited {
+                retry_after_seconds: 1
+            })
+        );
+
+        // Other clients have their own bucket
+        let result = store.take_token("127.0.0.2", params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+    }
+
+    #[tokio::test]
+    async fn test_take_token_refills_over_time() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = RateLimitParams {
+            burst: 1,
+            per_minute: 60,
+        };
+
+        store.take_token("127.0.0.1", params).await.unwrap();
+
+        // Pretend the last request was two seconds ago
+        let bucket = store.buckets.get_mut("127.0.0.1").unwrap();
+        bucket.updated_at_ms -= 2000;
+
+        let result = store.take_token("127.0.0.1", params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+    }
+
+    #[tokio::test]
+    async fn test_full_buckets_and_stale_failures_are_pruned() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = RateLimitParams {
+            burst: 2,
+            per_minute: 60,
+        };
+
+        store.take_token("127.0.0.1", params).await.unwrap();
+        store.take_token("127.0.0.2", params).await.unwrap();
+
+        // The first bucket has refilled since
+        let bucket = store.buckets.get_mut("127.0.0.1").unwrap();
+        bucket.updated_at_ms -= 1000;
+        store.take_token("127.0.0.3", params).await.unwrap();
+
+        assert!(!store.buckets.contains_key("127.0.0.1"));
+        assert!(store.buckets.contains_key("127.0.0.2"));
+        assert!(store.buckets.contains_key("127.0.0.3"));
+
+        let lockout = LockoutParams {
+            max_failures: 3,
+            lockout_seconds: 60,
+        };
+        let stale = Email::parse("stale@example.com".to_owned()).unwrap();
+
+        store.record_login_attempt(&stale, lockout).await.unwrap();
+        let failures = store.login_failures.get_mut(&stale).unwrap();
+        failures.last_failure_at -= 61;
+        store.record_login_attempt(&email(), lockout).await.unwrap();
+
+        assert!(!store.login_failures.contains_key(&stale));
+        assert!(store.login_failures.contains_key(&email()));
+    }
+
+    #[tokio::test]
+    async fn test_account_is_locked_after_max_failures() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = LockoutParams {
+            max_failures: 3,
+            lockout_seconds: 60,
+        };
+
+        for _ in 0..3 {
+            let result = store.record_login_attempt(&email(), params).await;
+            assert_eq!(result, Ok(RateLimitDecision::Allowed));
+        }
+
+        // Attempts that were never reset count as failures
+        for _ in 0..2 {
+            let result = store.record_login_attempt(&email(), params).await;
+            assert_eq!(
+                result,
+                Ok(RateLimitDecision::Limited {
+                    retry_after_seconds: 60
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_and_stale_failures_are_not_counted() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = LockoutParams {
+            max_failures: 1,
+            lockout_seconds: 60,
+        };
+
+        // A successful login resets the count
+        store.record_login_attempt(&email(), params).await.unwrap();
+        store.reset_login_failures(&email()).await.unwrap();
+        let result = store.record_login_attempt(&email(), params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+
+        // So does a long enough pause between failures
+        let failures = store.login_failures.get_mut(&email()).unwrap();
+        failures.last_failure_at -= 61;
+        let result = store.record_login_attempt(&email(), params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+    }
+
+    #[tokio::test]
+    async fn test_lockout_expires_or_is_reset() {
+        let mut store = HashmapRateLimitStore::default();
+        let params = LockoutParams {
+            max_failures: 1,
+            lockout_seconds: 60,
+        };
+
+        store.record_login_attempt(&email(), params).await.unwrap();
+        store.record_login_attempt(&email(), params).await.unwrap();
+        let failures = store.login_failures.get_mut(&email()).unwrap();
+        failures.locked_until = Some(Utc::now().timestamp() - 1);
+
+        let result = store.record_login_attempt(&email(), params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+
+        // A password reset ends the lockout early
+        let result = store.record_login_attempt(&email(), params).await;
+        assert_ne!(result, Ok(RateLimitDecision::Allowed));
+        store.reset_login_failures(&email()).await.unwrap();
+        let result = store.record_login_attempt(&email(), params).await;
+        assert_eq!(result, Ok(RateLimitDecision::Allowed));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::{
+    Email, RefreshToken, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError,
+};
+
+#[derive(Default)]
+pub struct HashmapRefreshTokenStore {
+    // Used tokens are kept until they would have expired so reuse can be
+    // detected, unless their family is revoked first
+    tokens: HashMap<RefreshToken, (RefreshTokenRecord, bool)>,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError> {
+        // Expired tokens can't be refreshed or reused, so they would only
+        // pile up
+        let now = Utc::now().timestamp();
+        self.tokens.retain(|_, (record, _)| record.expires_at > now);
+
+        self.tokens.insert(token, (record, false));
+        Ok(())
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        let (record, used) = self
+            .tokens
+            .get_mut(token)
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        if *used {
+            return Err(RefreshTokenStoreError::TokenReused(record.clone()));
+        }
+
+        *used = true;
+        Ok(record.clone())
+    }
+
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError> {
+        self.tokens
+            .retain(|_, (record, _)| record.family_id != family_id);
+        Ok(())
+    }
+
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.retain(|_, (record, _)| &record.email != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(email: &str, family_id: &str) -> RefreshTokenRecord {
+        RefreshTokenRecord {
+            email: Email::parse(email.to_owned()).unwrap(),
+            family_id: family_id.to_owned(),
+            expires_at: Utc::now().timestamp() + 3600,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consume_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let record = record("test@example.com", "family");
+
+        // Test consuming a token that doesn't exist
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+
+        store
+            .add_token(token.clone(), record.clone())
+            .await
+            .unwrap();
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Ok(record.clone()));
+
+        // Test consuming the same token again
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused(record)));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let other = RefreshToken::default();
+
+        store
+            .add_token(first.clone(), record("test@example.com", "family"))
+            .await
+            .unwrap();
+        store
+            .add_token(second.clone(), record("test@example.com", "family"))
+            .await
+            .unwrap();
+        store
+            .add_token(other.clone(), record("test@example.com", "other-family"))
+            .await
+            .unwrap();
+
+        store.revoke_family("family").await.unwrap();
+
+        for token in [first, second] {
+            let result = store.consume_token(&token).await;
+            assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+        }
+        assert!(store.consume_token(&other).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_tokens() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let other = RefreshToken::default();
+
+        store
+            .add_token(token.clone(), record("test@example.com", "family"))
+            .await
+            .unwrap();
+        store
+            .add_token(other.clone(), record("other@example.com", "other-family"))
+            .await
+            .unwrap();
+
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        store.revoke_user_tokens(&email).await.unwrap();
+
+        let result = store.consume_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+        assert!(store.consume_token(&other).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_token_prunes_expired_tokens() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let expired = RefreshToken::default();
+        let used = RefreshToken::default();
+        let token = RefreshToken::default();
+
+        let expired_record = RefreshTokenRecord {
+            expires_at: Utc::now().timestamp() - 1,
+            ..record("test@example.com", "family")
+        };
+        store
+            .add_token(expired.clone(), expired_record)
+            .await
+            .unwrap();
+        store
+            .add_token(used.clone(), record("test@example.com", "other-family"))
+            .await
+            .unwrap();
+        store.consume_token(&used).await.unwrap();
+
+        store
+            .add_token(token.clone(), record("test@example.com", "other-family"))
+            .await
+            .unwrap();
+
+        let result = store.consume_token(&expired).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+
+        // Used tokens that haven't expired are kept to detect reuse
+        let result = store.consume_token(&used).await;
+        assert!(matches!(
+            result,
+            Err(RefreshTokenStoreError::TokenReused(_))
+        ));
+        assert!(store.consume_token(&token).await.is_ok());
+    }
+}
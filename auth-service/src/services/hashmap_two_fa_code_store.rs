@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+
+pub struct HashmapTwoFACodeStore {
+    codes: HashMap<Email, PendingCode>,
+    code_ttl_seconds: i64,
+}
+
+struct PendingCode {
+    login_attempt_id: LoginAttemptId,
+    code: TwoFACode,
+    expires_at: i64,
+    failed_attempts: u32,
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn new(code_ttl_seconds: u64) -> Self {
+        Self {
+            codes: HashMap::new(),
+            code_ttl_seconds: i64::try_from(code_ttl_seconds).unwrap_or(i64::MAX),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for HashmapTwoFACodeStore {
+    async fn add_code(
+        &mut self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let now = Utc::now().timestamp();
+
+        // Codes nobody came back for would otherwise pile up
+        self.codes.retain(|_, pending| pending.expires_at > now);
+
+        // A new login attempt replaces any pending one for the same email
+        self.codes.insert(
+            email,
+            PendingCode {
+                login_attempt_id,
+                code,
+                expires_at: now.saturating_add(self.code_ttl_seconds),
+                failed_attempts: 0,
+            },
+        );
+        Ok(())
+    }
+
+    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+        self.codes
+            .remove(email)
+            .map(|_| ())
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError> {
+        let pending = self
+            .codes
+            .get_mut(email)
+            .filter(|pending| pending.expires_at > Utc::now().timestamp())
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        pending.failed_attempts += 1;
+        Ok(pending.failed_attempts)
+    }
+
+    async fn get_code(
+        &self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        self.codes
+            .get(email)
+            .filter(|pending| pending.expires_at > Utc::now().timestamp())
+            .map(|pending| (pending.login_attempt_id.clone(), pending.code.clone()))
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_get_code() {
+        let mut store = HashmapTwoFACodeStore::new(600);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::default();
+
+        // Test getting a code that doesn't exist
+        let result = store.get_code(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        let result = store
+            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.get_code(&email).await;
+        assert_eq!(result, Ok((login_attempt_id, code)));
+    }
+
+    #[tokio::test]
+    async fn test_add_code_replaces_existing_code() {
+        let mut store = HashmapTwoFACodeStore::new(600);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::default();
+        store
+            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
+
+        let result = store.get_code(&email).await;
+        assert_eq!(result, Ok((login_attempt_id, code)));
+    }
+
+    #[tokio::test]
+    async fn test_remove_code() {
+        let mut store = HashmapTwoFACodeStore::new(600);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        // Test removing a code that doesn't exist
+        let result = store.remove_code(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = store.remove_code(&email).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.get_code(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_code_is_not_found() {
+        let mut store = HashmapTwoFACodeStore::new(0);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = store.get_code(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        let result = store.record_failed_attempt(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt() {
+        let mut store = HashmapTwoFACodeStore::new(600);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+
+        let result = store.record_failed_attempt(&email).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&email).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&email).await, Ok(2));
+
+        // A new code starts counting again
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&email).await, Ok(1));
+    }
+}
@@ -1,10 +1,20 @@
 use std::collections::HashMap;
 
-use crate::domain::{Email, Password, User, UserStore, UserStoreError};
+use uuid::Uuid;
+
+use crate::domain::{
+    Email, PasswordHash, RecoveryCodeHash, TotpSecret, User, UserStore, UserStoreError,
+};
 
 #[derive(Default)]
 pub struct HashmapUserStore {
     users: HashMap<Email, User>,
+    // Soft deleted users and when they were deleted
+    deleted_users: HashMap<Email, (User, i64)>,
+    pending_totp_secrets: HashMap<Email, TotpSecret>,
+    // Time step of the last authenticator app code each user logged in with
+    totp_last_steps: HashMap<Email, i64>,
+    passkey_user_handles: HashMap<Email, Uuid>,
 }
 
 #[async_trait::async_trait]
@@ -12,7 +22,7 @@ impl UserStore for HashmapUserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
         // Return `UserStoreError::UserAlreadyExists` if the user already exists,
         // otherwise insert the user into the hashmap and return `Ok(())`.
-        if self.users.contains_key(&user.email) {
+        if self.users.contains_key(&user.email) || self.deleted_users.contains_key(&user.email) {
             return Err(UserStoreError::UserAlreadyExists);
         }
         self.users.insert(user.email.clone(), user);
@@ -27,32 +37,248 @@ impl UserStore for HashmapUserStore {
             .ok_or(UserStoreError::UserNotFound)
     }
 
-    async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        // Return `UserStoreError::UserNotFound` if the user can not be found.
-        // Return `UserStoreError::InvalidCredentials` if the password is incorrect.
-        match self.users.get(email) {
-            Some(user) => {
-                if user.password.as_ref() == password.as_ref() {
-                    Ok(())
-                } else {
-                    Err(UserStoreError::InvalidCredentials)
-                }
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.email_verified = true;
+        Ok(())
+    }
+
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.password_hash = password_hash;
+        Ok(())
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.requires_2fa = requires_2fa;
+        Ok(())
+    }
+
+    async fn set_pending_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.pending_totp_secrets.insert(email.clone(), secret);
+        Ok(())
+    }
+
+    async fn get_pending_totp_secret(
+        &self,
+        email: &Email,
+    ) -> Result<Option<TotpSecret>, UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(self.pending_totp_secrets.get(email).cloned())
+    }
+
+    async fn confirm_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        if self.pending_totp_secrets.get(email) != Some(secret) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.pending_totp_secrets.remove(email);
+        self.totp_last_steps.remove(email);
+        user.totp_secret = Some(secret.clone());
+        user.requires_2fa = true;
+        Ok(())
+    }
+
+    async fn set_recovery_codes(
+        &mut self,
+        email: &Email,
+        recovery_codes: Vec<RecoveryCodeHash>,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.recovery_codes = recovery_codes;
+        Ok(())
+    }
+
+    async fn use_recovery_code(
+        &mut self,
+        email: &Email,
+        recovery_code: &RecoveryCodeHash,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        let index = user
+            .recovery_codes
+            .iter()
+            .position(|hash| hash == recovery_code)
+            .ok_or(UserStoreError::InvalidCredentials)?;
+        user.recovery_codes.remove(index);
+        Ok(())
+    }
+
+    async fn use_totp_step(&mut self, email: &Email, time_step: i64) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        if self
+            .totp_last_steps
+            .get(email)
+            .is_some_and(|&last_step| last_step >= time_step)
+        {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+        self.totp_last_steps.insert(email.clone(), time_step);
+        Ok(())
+    }
+
+    async fn passkey_user_handle(&mut self, email: &Email) -> Result<Uuid, UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(*self
+            .passkey_user_handles
+            .entry(email.clone())
+            .or_insert_with(Uuid::new_v4))
+    }
+
+    async fn increment_session_version(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.session_version += 1;
+        Ok(())
+    }
+
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let stored = self
+            .users
+            .get_mut(&user.email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        *stored = User {
+            session_version: stored.session_version,
+            ..user
+        };
+        Ok(())
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        self.pending_totp_secrets.remove(email);
+        self.totp_last_steps.remove(email);
+        self.passkey_user_handles.remove(email);
+        self.users
+            .remove(email)
+            .map(|_| ())
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn list_users(&self, offset: u64, limit: u64) -> Result<Vec<User>, UserStoreError> {
+        let offset = usize::try_from(offset).map_err(|_| UserStoreError::UnexpectedError)?;
+        let limit = usize::try_from(limit).map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // Sorted like the SQL stores, so pages are stable
+        let mut users: Vec<&User> = self.users.values().collect();
+        users.sort_by(|a, b| a.email.as_ref().cmp(b.email.as_ref()));
+
+        Ok(users
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn soft_delete_user(
+        &mut self,
+        email: &Email,
+        deleted_at: i64,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .remove(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        self.pending_totp_secrets.remove(email);
+        self.totp_last_steps.remove(email);
+        self.deleted_users.insert(email.clone(), (user, deleted_at));
+        Ok(())
+    }
+
+    async fn deleted_users(&self, deleted_before: i64) -> Result<Vec<Email>, UserStoreError> {
+        Ok(self
+            .deleted_users
+            .iter()
+            .filter(|(_, (_, deleted_at))| *deleted_at <= deleted_before)
+            .map(|(email, _)| email.clone())
+            .collect())
+    }
+
+    async fn purge_deleted_users(
+        &mut self,
+        deleted_before: i64,
+    ) -> Result<Vec<Email>, UserStoreError> {
+        let mut purged = Vec::new();
+        self.deleted_users.retain(|email, (_, deleted_at)| {
+            let purge = *deleted_at <= deleted_before;
+            if purge {
+                purged.push(email.clone());
             }
-            None => Err(UserStoreError::UserNotFound),
+            !purge
+        });
+        for email in &purged {
+            self.passkey_user_handles.remove(email);
         }
+        Ok(purged)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{Password, PasswordHashingParams, RecoveryCode, Role};
+
+    async fn hash(password: &Password) -> PasswordHash {
+        let params = PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        PasswordHash::hash(password, params).await.unwrap()
+    }
 
     #[tokio::test]
     async fn test_add_user() {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("password123".to_string()).unwrap();
-        let user = User::new(email, password, true);
+        let user = User::new(email, hash(&password).await, true);
 
         // Test adding a user successfully
         let result = store.add_user(user.clone()).await;
@@ -68,7 +294,7 @@ mod tests {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("password123".to_string()).unwrap();
-        let user = User::new(email.clone(), password, true);
+        let user = User::new(email.clone(), hash(&password).await, true);
 
         // Test getting a user that doesn't exist
         let result = store.get_user(&email).await;
@@ -80,13 +306,337 @@ mod tests {
         assert_eq!(result, Ok(user));
     }
 
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.mark_email_verified(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().email_verified);
+
+        store.mark_email_verified(&email).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().email_verified);
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let new_password = Password::parse("newpassword123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store
+            .update_password(&email, hash(&new_password).await)
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store
+            .update_password(&email, hash(&new_password).await)
+            .await
+            .unwrap();
+
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+        let result = store.validate_user(&email, &new_password).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_set_requires_2fa() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.set_requires_2fa(&email, true).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store.set_requires_2fa(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().requires_2fa);
+
+        store.set_requires_2fa(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().requires_2fa);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_totp_secret() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+        let secret = TotpSecret::default();
+
+        let result = store.set_pending_totp_secret(&email, secret.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.get_pending_totp_secret(&email).await, Ok(None));
+        store
+            .set_pending_totp_secret(&email, secret.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_pending_totp_secret(&email).await,
+            Ok(Some(secret.clone()))
+        );
+        assert_eq!(store.get_user(&email).await.unwrap().totp_secret, None);
+
+        // Only the pending secret can be confirmed
+        let result = store
+            .confirm_totp_secret(&email, &TotpSecret::default())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.confirm_totp_secret(&email, &secret).await.unwrap();
+        let user = store.get_user(&email).await.unwrap();
+        assert_eq!(user.totp_secret, Some(secret.clone()));
+        assert!(user.requires_2fa);
+        assert_eq!(store.get_pending_totp_secret(&email).await, Ok(None));
+
+        let result = store.confirm_totp_secret(&email, &secret).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_use_totp_step() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.use_totp_step(&email, 10).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store.use_totp_step(&email, 10).await.unwrap();
+
+        // Each step is only accepted once, and earlier steps not at all
+        for time_step in [10, 9] {
+            let result = store.use_totp_step(&email, time_step).await;
+            assert_eq!(
+                result,
+                Err(UserStoreError::InvalidCredentials),
+                "Failed for input: {:?}",
+                time_step
+            );
+        }
+        store.use_totp_step(&email, 11).await.unwrap();
+
+        // A newly confirmed secret starts over
+        let secret = TotpSecret::default();
+        store
+            .set_pending_totp_secret(&email, secret.clone())
+            .await
+            .unwrap();
+        store.confirm_totp_secret(&email, &secret).await.unwrap();
+        store.use_totp_step(&email, 11).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_passkey_user_handle() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+
+        let result = store.passkey_user_handle(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        for email in [&email, &other_email] {
+            let user = User::new(email.clone(), hash(&password).await, false);
+            store.add_user(user).await.unwrap();
+        }
+
+        // Assigned once, and not derived from the email
+        let handle = store.passkey_user_handle(&email).await.unwrap();
+        assert_eq!(store.passkey_user_handle(&email).await, Ok(handle));
+        assert_ne!(store.passkey_user_handle(&other_email).await, Ok(handle));
+    }
+
+    #[tokio::test]
+    async fn test_use_recovery_code() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+        let codes = RecoveryCode::generate_batch();
+        let hashes: Vec<RecoveryCodeHash> = codes.iter().map(RecoveryCode::hash).collect();
+
+        let result = store.set_recovery_codes(&email, hashes.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store
+            .set_recovery_codes(&email, hashes.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().recovery_codes, hashes);
+
+        store.use_recovery_code(&email, &hashes[3]).await.unwrap();
+        let remaining = store.get_user(&email).await.unwrap().recovery_codes;
+        assert_eq!(remaining.len(), hashes.len() - 1);
+        assert!(!remaining.contains(&hashes[3]));
+
+        // Each code only works once
+        let result = store.use_recovery_code(&email, &hashes[3]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        // A new batch replaces the old one
+        store
+            .set_recovery_codes(&email, vec![RecoveryCode::default().hash()])
+            .await
+            .unwrap();
+        let result = store.use_recovery_code(&email, &hashes[0]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_update_user() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.update_user(user.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        let updated = User::new(email.clone(), hash(&password).await, true)
+            .with_roles([Role::User, Role::Admin].into())
+            .with_email_verified(true)
+            .with_totp_secret(Some(TotpSecret::default()))
+            .with_recovery_codes(vec![RecoveryCode::default().hash()]);
+        store.update_user(updated.clone()).await.unwrap();
+
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Ok(updated));
+    }
+
+    #[tokio::test]
+    async fn test_increment_session_version() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.increment_session_version(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.increment_session_version(&email).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_version, 1);
+
+        // Updating the user doesn't bring old sessions back
+        store.update_user(user).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.delete_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.delete_user(&email).await.unwrap();
+
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        // The address can be used for a new account
+        let result = store.add_user(user).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let mut store = HashmapUserStore::default();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let password_hash = hash(&password).await;
+
+        assert_eq!(store.list_users(0, 10).await, Ok(Vec::new()));
+
+        // Added out of order, listed by email
+        for name in ["c", "a", "d", "b", "e"] {
+            let email = Email::parse(format!("{}@example.com", name)).unwrap();
+            let user = User::new(email, password_hash.clone(), false);
+            store.add_user(user).await.unwrap();
+        }
+
+        let test_cases = [
+            (0, 2, vec!["a", "b"]),
+            (2, 2, vec!["c", "d"]),
+            (4, 2, vec!["e"]),
+            (5, 2, vec![]),
+            (0, 0, vec![]),
+        ];
+
+        for (offset, limit, expected) in test_cases {
+            let users = store.list_users(offset, limit).await.unwrap();
+            let names: Vec<&str> = users
+                .iter()
+                .map(|user| user.email.as_ref().split('@').next().unwrap())
+                .collect();
+            assert_eq!(names, expected, "Failed for input: {:?}", (offset, limit));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_and_purge() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.soft_delete_user(&email, 100).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.soft_delete_user(&email, 100).await.unwrap();
+
+        // Soft deleted users are hidden, but keep their email taken
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.list_users(0, 10).await, Ok(Vec::new()));
+        let result = store.soft_delete_user(&email, 100).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.add_user(user.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+
+        // Only users deleted at or before the cutoff are listed and purged
+        assert_eq!(store.deleted_users(99).await, Ok(Vec::new()));
+        assert_eq!(store.deleted_users(100).await, Ok(vec![email.clone()]));
+        assert_eq!(store.purge_deleted_users(99).await, Ok(Vec::new()));
+        assert_eq!(
+            store.purge_deleted_users(100).await,
+            Ok(vec![email.clone()])
+        );
+
+        let result = store.add_user(user).await;
+        assert_eq!(result, Ok(()));
+    }
+
     #[tokio::test]
     async fn test_validate_user() {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("password123".to_string()).unwrap();
         let wrong_password = Password::parse("wrongpassword".to_string()).unwrap();
-        let user = User::new(email.clone(), password.clone(), true);
+        let user = User::new(email.clone(), hash(&password).await, true);
 
         // Test validating a user that doesn't exist
         let result = store.validate_user(&email, &password).await;
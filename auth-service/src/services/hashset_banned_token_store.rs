@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+
+#[derive(Default)]
+pub struct HashsetBannedTokenStore {
+    token_ids: HashSet<String>,
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for HashsetBannedTokenStore {
+    async fn add_token(&mut self, token_id: String) -> Result<(), BannedTokenStoreError> {
+        self.token_ids.insert(token_id);
+        Ok(())
+    }
+
+    async fn contains_token(&self, token_id: &str) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.token_ids.contains(token_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_token() {
+        let mut store = HashsetBannedTokenStore::default();
+        let token = "test_token".to_owned();
+
+        let result = store.add_token(token.clone()).await;
+        assert_eq!(result, Ok(()));
+        assert!(store.token_ids.contains(&token));
+
+        // Adding the same token twice is not an error
+        let result = store.add_token(token).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_contains_token() {
+        let mut store = HashsetBannedTokenStore::default();
+        let token = "test_token".to_owned();
+
+        let result = store.contains_token(&token).await;
+        assert_eq!(result, Ok(false));
+
+        store.add_token(token.clone()).await.unwrap();
+        let result = store.contains_token(&token).await;
+        assert_eq!(result, Ok(true));
+    }
+}
@@ -0,0 +1,25 @@
+use crate::domain::{Email, EmailClient, EmailClientError};
+
+// Prints outgoing emails instead of sending them, for local development
+// without an outbox directory
+#[derive(Default)]
+pub struct LogEmailClient;
+
+#[async_trait::async_trait]
+impl EmailClient for LogEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), EmailClientError> {
+        println!(
+            "Sending email to {} with subject: {} and content: {}",
+            recipient.as_ref(),
+            subject,
+            content
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use tokio::sync::RwLock;
+
+use crate::domain::{Email, EmailClient, EmailClientError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentEmail {
+    pub recipient: Email,
+    pub subject: String,
+    pub content: String,
+}
+
+// Keeps a copy of each outgoing email instead of sending it, so tests can
+// check what was sent
+#[derive(Default)]
+pub struct MockEmailClient {
+    sent_emails: RwLock<Vec<SentEmail>>,
+}
+
+impl MockEmailClient {
+    pub async fn sent_emails(&self) -> Vec<SentEmail> {
+        self.sent_emails.read().await.clone()
+    }
+
+    pub async fn last_email_to(&self, recipient: &Email) -> Option<SentEmail> {
+        self.sent_emails
+            .read()
+            .await
+            .iter()
+            .rev()
+            .find(|email| &email.recipient == recipient)
+            .cloned()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for MockEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), EmailClientError> {
+        self.sent_emails.write().await.push(SentEmail {
+            recipient: recipient.clone(),
+            subject: subject.to_owned(),
+            content: content.to_owned(),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_email_records_message() {
+        let client = MockEmailClient::default();
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+
+        let result = client.send_email(&recipient, "Subject", "Content").await;
+        assert_eq!(result, Ok(()));
+
+        let expected = SentEmail {
+            recipient,
+            subject: "Subject".to_owned(),
+            content: "Content".to_owned(),
+        };
+        assert_eq!(client.sent_emails().await, vec![expected]);
+    }
+
+    #[tokio::test]
+    async fn test_last_email_to() {
+        let client = MockEmailClient::default();
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+
+        assert_eq!(client.last_email_to(&recipient).await, None);
+
+        client.send_email(&recipient, "First", "1").await.unwrap();
+        client.send_email(&recipient, "Second", "2").await.unwrap();
+        client.send_email(&other, "Third", "3").await.unwrap();
+
+        let email = client.last_email_to(&recipient).await.unwrap();
+        assert_eq!(email.subject, "Second");
+    }
+}
@@ -1,2 +1,58 @@
+pub mod file_outbox_email_client;
+pub mod hashmap_authorization_code_store;
+pub mod hashmap_email_token_store;
+pub mod hashmap_jwt_key_store;
+pub mod hashmap_oauth_client_store;
+pub mod hashmap_passkey_challenge_store;
+pub mod hashmap_passkey_store;
+pub mod hashmap_rate_limit_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
+pub mod hashset_banned_token_store;
+pub mod log_email_client;
+pub mod mock_email_client;
+pub mod postgres_jwt_key_store;
+pub mod postgres_oauth_client_store;
+pub mod postgres_passkey_store;
+pub mod postgres_user_store;
+pub mod redis_authorization_code_store;
+pub mod redis_banned_token_store;
+pub mod redis_email_token_store;
+pub mod redis_passkey_challenge_store;
+pub mod redis_rate_limit_store;
+pub mod redis_refresh_token_store;
+pub mod redis_two_fa_code_store;
+pub mod sqlite_jwt_key_store;
+pub mod sqlite_oauth_client_store;
+pub mod sqlite_passkey_store;
+pub mod sqlite_user_store;
+pub use file_outbox_email_client::FileOutboxEmailClient;
+pub use hashmap_authorization_code_store::HashmapAuthorizationCodeStore;
+pub use hashmap_email_token_store::HashmapEmailTokenStore;
+pub use hashmap_jwt_key_store::HashmapJwtKeyStore;
+pub use hashmap_oauth_client_store::HashmapOAuthClientStore;
+pub use hashmap_passkey_challenge_store::HashmapPasskeyChallengeStore;
+pub use hashmap_passkey_store::HashmapPasskeyStore;
+pub use hashmap_rate_limit_store::HashmapRateLimitStore;
+pub use hashmap_refresh_token_store::HashmapRefreshTokenStore;
+pub use hashmap_two_fa_code_store::HashmapTwoFACodeStore;
 pub use hashmap_user_store::HashmapUserStore;
+pub use hashset_banned_token_store::HashsetBannedTokenStore;
+pub use log_email_client::LogEmailClient;
+pub use mock_email_client::MockEmailClient;
+pub use postgres_jwt_key_store::PostgresJwtKeyStore;
+pub use postgres_oauth_client_store::PostgresOAuthClientStore;
+pub use postgres_passkey_store::PostgresPasskeyStore;
+pub use postgres_user_store::PostgresUserStore;
+pub use redis_authorization_code_store::RedisAuthorizationCodeStore;
+pub use redis_banned_token_store::RedisBannedTokenStore;
+pub use redis_email_token_store::RedisEmailTokenStore;
+pub use redis_passkey_challenge_store::RedisPasskeyChallengeStore;
+pub use redis_rate_limit_store::RedisRateLimitStore;
+pub use redis_refresh_token_store::RedisRefreshTokenStore;
+pub use redis_two_fa_code_store::RedisTwoFACodeStore;
+pub use sqlite_jwt_key_store::SqliteJwtKeyStore;
+pub use sqlite_oauth_client_store::SqliteOAuthClientStore;
+pub use sqlite_passkey_store::SqlitePasskeyStore;
+pub use sqlite_user_store::SqliteUserStore;
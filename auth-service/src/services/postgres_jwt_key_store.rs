@@ -0,0 +1,76 @@
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::domain::{JwtKeyRecord, JwtKeyStore, JwtKeyStoreError};
+
+pub struct PostgresJwtKeyStore {
+    pool: PgPool,
+}
+
+impl PostgresJwtKeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl JwtKeyStore for PostgresJwtKeyStore {
+    async fn add_key(&mut self, record: JwtKeyRecord) -> Result<(), JwtKeyStoreError> {
+        sqlx::query(
+            r#"
+            INSERT INTO jwt_keys (kid, algorithm, private_key, activates_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(&record.kid)
+        .bind(&record.algorithm)
+        .bind(&record.private_key)
+        .bind(record.activates_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => JwtKeyStoreError::KeyAlreadyExists,
+            _ => JwtKeyStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_keys(&self) -> Result<Vec<JwtKeyRecord>, JwtKeyStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT kid, algorithm, private_key, activates_at
+            FROM jwt_keys
+            ORDER BY activates_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| JwtKeyStoreError::UnexpectedError)?;
+
+        Ok(rows.iter().map(record_from_row).collect())
+    }
+
+    async fn delete_keys(&mut self, activated_before: i64) -> Result<u64, JwtKeyStoreError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM jwt_keys
+            WHERE activates_at < $1
+            "#,
+        )
+        .bind(activated_before)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| JwtKeyStoreError::UnexpectedError)?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+fn record_from_row(row: &PgRow) -> JwtKeyRecord {
+    JwtKeyRecord {
+        kid: row.get("kid"),
+        algorithm: row.get("algorithm"),
+        private_key: row.get("private_key"),
+        activates_at: row.get("activates_at"),
+    }
+}
@@ -0,0 +1,137 @@
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::domain::{
+    ClientId, ClientSecretHash, Email, OAuthClient, OAuthClientStore, OAuthClientStoreError,
+    RedirectUri,
+};
+
+pub struct PostgresOAuthClientStore {
+    pool: PgPool,
+}
+
+impl PostgresOAuthClientStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for PostgresOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient) -> Result<(), OAuthClientStoreError> {
+        let redirect_uris = serde_json::to_string(
+            &client
+                .redirect_uris
+                .iter()
+                .map(AsRef::as_ref)
+                .collect::<Vec<&str>>(),
+        )
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_clients (client_id, name, redirect_uris, secret_hash)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(client.client_id.as_ref())
+        .bind(&client.name)
+        .bind(redirect_uris)
+        .bind(client.secret_hash.as_ref().map(AsRef::as_ref))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => {
+                OAuthClientStoreError::ClientAlreadyExists
+            }
+            _ => OAuthClientStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &ClientId) -> Result<OAuthClient, OAuthClientStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT client_id, name, redirect_uris, secret_hash
+            FROM oauth_clients
+            WHERE client_id = $1
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?;
+
+        client_from_row(&row)
+    }
+
+    async fn add_consent(
+        &mut self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<(), OAuthClientStoreError> {
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_consents (client_id, email)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        // Consent is given by a logged in user, so the missing row is the client's
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_foreign_key_violation() => {
+                OAuthClientStoreError::ClientNotFound
+            }
+            _ => OAuthClientStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn has_consent(
+        &self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<bool, OAuthClientStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT 1
+            FROM oauth_consents
+            WHERE client_id = $1 AND email = $2
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+}
+
+fn client_from_row(row: &PgRow) -> Result<OAuthClient, OAuthClientStoreError> {
+    let redirect_uris: Vec<String> = serde_json::from_str(row.get("redirect_uris"))
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+    let secret_hash: Option<String> = row.get("secret_hash");
+
+    Ok(OAuthClient {
+        client_id: ClientId::parse(row.get("client_id"))
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+        name: row.get("name"),
+        redirect_uris: redirect_uris
+            .into_iter()
+            .map(RedirectUri::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+        secret_hash: secret_hash
+            .map(ClientSecretHash::parse)
+            .transpose()
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+    })
+}
@@ -0,0 +1,122 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sqlx::{PgPool, Row};
+use webauthn_rs::prelude::{AuthenticationResult, CredentialID, Passkey};
+
+use crate::domain::{Email, PasskeyStore, PasskeyStoreError};
+
+pub struct PostgresPasskeyStore {
+    pool: PgPool,
+}
+
+impl PostgresPasskeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for PostgresPasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: &Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        let value =
+            serde_json::to_string(&passkey).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO passkeys (credential_id, email, passkey)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(encode_credential_id(passkey.cred_id()))
+        .bind(email.as_ref())
+        .bind(value)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => {
+                PasskeyStoreError::PasskeyAlreadyExists
+            }
+            _ => PasskeyStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT passkey
+            FROM passkeys
+            WHERE email = $1
+            ORDER BY credential_id
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| {
+                let value: String = row.get("passkey");
+                serde_json::from_str(&value).map_err(|_| PasskeyStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+
+    async fn update_passkey(
+        &mut self,
+        email: &Email,
+        result: &AuthenticationResult,
+    ) -> Result<(), PasskeyStoreError> {
+        let credential_id = encode_credential_id(result.cred_id());
+
+        let row = sqlx::query(
+            r#"
+            SELECT passkey
+            FROM passkeys
+            WHERE credential_id = $1 AND email = $2
+            "#,
+        )
+        .bind(&credential_id)
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?
+        .ok_or(PasskeyStoreError::PasskeyNotFound)?;
+
+        let value: String = row.get("passkey");
+        let mut passkey: Passkey =
+            serde_json::from_str(&value).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        // Nothing to save unless the counter or backup state changed
+        if passkey.update_credential(result) != Some(true) {
+            return Ok(());
+        }
+
+        let value =
+            serde_json::to_string(&passkey).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            UPDATE passkeys
+            SET passkey = $1
+            WHERE credential_id = $2
+            "#,
+        )
+        .bind(value)
+        .bind(&credential_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+fn encode_credential_id(credential_id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(credential_id)
+}
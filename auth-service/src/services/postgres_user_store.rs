@@ -0,0 +1,553 @@
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+
+use crate::domain::{
+    format_recovery_codes, format_roles, parse_recovery_codes, parse_roles, Email,
+    KeyEncryptionKey, PasswordHash, RecoveryCodeHash, TotpSecret, User, UserStore, UserStoreError,
+};
+
+pub struct PostgresUserStore {
+    pool: PgPool,
+    key_encryption_key: KeyEncryptionKey,
+}
+
+impl PostgresUserStore {
+    pub fn new(pool: PgPool, key_encryption_key: KeyEncryptionKey) -> Self {
+        Self {
+            pool,
+            key_encryption_key,
+        }
+    }
+
+    // TOTP secrets are stored encrypted, bound to the user's email
+    fn encrypt_totp_secret(
+        &self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<String, UserStoreError> {
+        self.key_encryption_key
+            .encrypt(secret.as_ref(), email.as_ref())
+            .ok_or(UserStoreError::UnexpectedError)
+    }
+
+    fn decrypt_totp_secret(
+        &self,
+        email: &Email,
+        encrypted: &str,
+    ) -> Result<TotpSecret, UserStoreError> {
+        let secret = self
+            .key_encryption_key
+            .decrypt(encrypted, email.as_ref())
+            .ok_or(UserStoreError::UnexpectedError)?;
+        TotpSecret::parse(secret).map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    // Rows are only ever written from valid domain types, so a parse failure
+    // here means the table was modified by hand
+    fn user_from_row(&self, row: &PgRow) -> Result<User, UserStoreError> {
+        let email = Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let password_hash = PasswordHash::parse(row.get("password_hash"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let roles = parse_roles(row.get("roles")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let totp_secret = row
+            .get::<Option<String>, _>("totp_secret")
+            .map(|secret| self.decrypt_totp_secret(&email, &secret))
+            .transpose()?;
+        let recovery_codes = parse_recovery_codes(row.get("recovery_codes"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(User::new(email, password_hash, row.get("requires_2fa"))
+            .with_roles(roles)
+            .with_email_verified(row.get("email_verified"))
+            .with_totp_secret(totp_secret)
+            .with_recovery_codes(recovery_codes)
+            .with_session_version(row.get("session_version")))
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for PostgresUserStore {
+    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let totp_secret = user
+            .totp_secret
+            .as_ref()
+            .map(|secret| self.encrypt_totp_secret(&user.email, secret))
+            .transpose()?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO users (
+                email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(user.email.as_ref())
+        .bind(user.password_hash.as_ref())
+        .bind(user.requires_2fa)
+        .bind(format_roles(&user.roles))
+        .bind(user.email_verified)
+        .bind(totp_secret)
+        .bind(format_recovery_codes(&user.recovery_codes))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes,
+                session_version
+            FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        self.user_from_row(&row)
+    }
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET email_verified = TRUE
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(password_hash.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET requires_2fa = $1
+            WHERE email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(requires_2fa)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_pending_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        let encrypted_secret = self.encrypt_totp_secret(email, &secret)?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_pending_secret = $1
+            WHERE email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(encrypted_secret)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_pending_totp_secret(
+        &self,
+        email: &Email,
+    ) -> Result<Option<TotpSecret>, UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT totp_pending_secret
+            FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        row.get::<Option<String>, _>("totp_pending_secret")
+            .map(|secret| self.decrypt_totp_secret(email, &secret))
+            .transpose()
+    }
+
+    async fn confirm_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        // The same secret encrypts differently every time, so compare the
+        // decrypted one
+        let pending_secret = sqlx::query(
+            r#"
+            SELECT totp_pending_secret
+            FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .and_then(|row| row.get::<Option<String>, _>("totp_pending_secret"))
+        .ok_or(UserStoreError::UserNotFound)?;
+        if self.decrypt_totp_secret(email, &pending_secret)? != *secret {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        // Unless it was replaced in the meantime
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_secret = totp_pending_secret, totp_pending_secret = NULL, requires_2fa = TRUE,
+                totp_last_step = NULL
+            WHERE email = $1 AND totp_pending_secret = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .bind(pending_secret)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_recovery_codes(
+        &mut self,
+        email: &Email,
+        recovery_codes: Vec<RecoveryCodeHash>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET recovery_codes = $1
+            WHERE email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(format_recovery_codes(&recovery_codes))
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn use_recovery_code(
+        &mut self,
+        email: &Email,
+        recovery_code: &RecoveryCodeHash,
+    ) -> Result<(), UserStoreError> {
+        // Compare and swap, so two replicas can't both use the same code.
+        // If the update misses, another request changed the codes first.
+        loop {
+            let user = self.get_user(email).await?;
+            let remaining: Vec<RecoveryCodeHash> = user
+                .recovery_codes
+                .iter()
+                .filter(|hash| *hash != recovery_code)
+                .cloned()
+                .collect();
+
+            if remaining.len() == user.recovery_codes.len() {
+                return Err(UserStoreError::InvalidCredentials);
+            }
+
+            let result = sqlx::query(
+                r#"
+                UPDATE users
+                SET recovery_codes = $1
+                WHERE email = $2 AND recovery_codes = $3 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(format_recovery_codes(&remaining))
+            .bind(email.as_ref())
+            .bind(format_recovery_codes(&user.recovery_codes))
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+            if result.rows_affected() > 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn use_totp_step(&mut self, email: &Email, time_step: i64) -> Result<(), UserStoreError> {
+        // Only moves forward, so two replicas can't both accept the same code
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_last_step = $1
+            WHERE email = $2 AND deleted_at IS NULL
+                AND (totp_last_step IS NULL OR totp_last_step < $1)
+            "#,
+        )
+        .bind(time_step)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+        self.get_user(email).await?;
+        Err(UserStoreError::InvalidCredentials)
+    }
+
+    async fn passkey_user_handle(&mut self, email: &Email) -> Result<Uuid, UserStoreError> {
+        // Only set if still unset, so concurrent registrations agree on it
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET passkey_user_handle = $1
+            WHERE email = $2 AND deleted_at IS NULL AND passkey_user_handle IS NULL
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT passkey_user_handle
+            FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Uuid::parse_str(&row.get::<String, _>("passkey_user_handle"))
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn increment_session_version(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET session_version = session_version + 1
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let totp_secret = user
+            .totp_secret
+            .as_ref()
+            .map(|secret| self.encrypt_totp_secret(&user.email, secret))
+            .transpose()?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $1, requires_2fa = $2, roles = $3, email_verified = $4,
+                totp_secret = $5, recovery_codes = $6
+            WHERE email = $7 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(user.password_hash.as_ref())
+        .bind(user.requires_2fa)
+        .bind(format_roles(&user.roles))
+        .bind(user.email_verified)
+        .bind(totp_secret)
+        .bind(format_recovery_codes(&user.recovery_codes))
+        .bind(user.email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list_users(&self, offset: u64, limit: u64) -> Result<Vec<User>, UserStoreError> {
+        let offset = i64::try_from(offset).map_err(|_| UserStoreError::UnexpectedError)?;
+        let limit = i64::try_from(limit).map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes,
+                session_version
+            FROM users
+            WHERE deleted_at IS NULL
+            ORDER BY email COLLATE "C"
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter().map(|row| self.user_from_row(row)).collect()
+    }
+
+    async fn soft_delete_user(
+        &mut self,
+        email: &Email,
+        deleted_at: i64,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET deleted_at = $1
+            WHERE email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(deleted_at)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn deleted_users(&self, deleted_before: i64) -> Result<Vec<Email>, UserStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT email
+            FROM users
+            WHERE deleted_at <= $1
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    async fn purge_deleted_users(
+        &mut self,
+        deleted_before: i64,
+    ) -> Result<Vec<Email>, UserStoreError> {
+        let rows = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE deleted_at <= $1
+            RETURNING email
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+}
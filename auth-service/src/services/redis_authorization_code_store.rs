@@ -0,0 +1,97 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    AuthorizationCode, AuthorizationCodeRecord, AuthorizationCodeStore,
+    AuthorizationCodeStoreError, ClientId, CodeChallenge, Email, RedirectUri,
+};
+
+pub struct RedisAuthorizationCodeStore {
+    conn: ConnectionManager,
+}
+
+impl RedisAuthorizationCodeStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for RedisAuthorizationCodeStore {
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        record: AuthorizationCodeRecord,
+    ) -> Result<(), AuthorizationCodeStoreError> {
+        let value = serde_json::to_string(&StoredRecord::from(&record))
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        // Redis forgets the code once it has expired
+        self.conn
+            .set_ex::<_, _, ()>(get_key(&code), value, remaining_seconds(&record))
+            .await
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)
+    }
+
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationCodeRecord, AuthorizationCodeStoreError> {
+        // GETDEL is atomic, so a code can only be exchanged once
+        let value: Option<String> = self
+            .conn
+            .get_del(get_key(code))
+            .await
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(AuthorizationCodeStoreError::CodeNotFound)?;
+        let stored: StoredRecord = serde_json::from_str(&value)
+            .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?;
+
+        Ok(AuthorizationCodeRecord {
+            client_id: ClientId::parse(stored.client_id)
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            redirect_uri: RedirectUri::parse(stored.redirect_uri)
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            code_challenge: CodeChallenge::parse(stored.code_challenge)
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            email: Email::parse(stored.email)
+                .map_err(|_| AuthorizationCodeStoreError::UnexpectedError)?,
+            expires_at: stored.expires_at,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    email: String,
+    expires_at: i64,
+}
+
+impl From<&AuthorizationCodeRecord> for StoredRecord {
+    fn from(record: &AuthorizationCodeRecord) -> Self {
+        Self {
+            client_id: record.client_id.as_ref().to_owned(),
+            redirect_uri: record.redirect_uri.as_ref().to_owned(),
+            code_challenge: record.code_challenge.as_ref().to_owned(),
+            email: record.email.as_ref().to_owned(),
+            expires_at: record.expires_at,
+        }
+    }
+}
+
+// Redis rejects an expiry of zero, so expired records are kept for one more second
+fn remaining_seconds(record: &AuthorizationCodeRecord) -> u64 {
+    (record.expires_at - Utc::now().timestamp()).max(1) as u64
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const AUTHORIZATION_CODE_KEY_PREFIX: &str = "authorization_code:";
+
+fn get_key(code: &AuthorizationCode) -> String {
+    format!("{}{}", AUTHORIZATION_CODE_KEY_PREFIX, code.as_ref())
+}
@@ -0,0 +1,49 @@
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+
+pub struct RedisBannedTokenStore {
+    conn: ConnectionManager,
+    token_ttl_seconds: u64,
+}
+
+impl RedisBannedTokenStore {
+    pub fn new(conn: ConnectionManager, token_ttl_seconds: u64) -> Self {
+        Self {
+            conn,
+            token_ttl_seconds,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for RedisBannedTokenStore {
+    async fn add_token(&mut self, token_id: String) -> Result<(), BannedTokenStoreError> {
+        let key = get_key(&token_id);
+
+        // A banned token only needs to be remembered until it would have
+        // expired anyway, so let Redis drop it after the token TTL
+        self.conn
+            .set_ex::<_, _, ()>(key, true, self.token_ttl_seconds)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+
+    async fn contains_token(&self, token_id: &str) -> Result<bool, BannedTokenStoreError> {
+        let key = get_key(token_id);
+
+        // ConnectionManager is a cheap handle onto a shared connection
+        self.conn
+            .clone()
+            .exists(key)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+
+fn get_key(token_id: &str) -> String {
+    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token_id)
+}
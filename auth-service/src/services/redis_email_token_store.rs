@@ -0,0 +1,86 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    Email, EmailToken, EmailTokenPurpose, EmailTokenRecord, EmailTokenStore, EmailTokenStoreError,
+};
+
+pub struct RedisEmailTokenStore {
+    conn: ConnectionManager,
+}
+
+impl RedisEmailTokenStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTokenStore for RedisEmailTokenStore {
+    async fn add_token(
+        &mut self,
+        token: EmailToken,
+        record: EmailTokenRecord,
+    ) -> Result<(), EmailTokenStoreError> {
+        let value = serde_json::to_string(&StoredRecord::from(&record))
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        // Redis forgets the token once its link has expired
+        self.conn
+            .set_ex::<_, _, ()>(get_key(&token), value, remaining_seconds(&record))
+            .await
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &EmailToken,
+    ) -> Result<EmailTokenRecord, EmailTokenStoreError> {
+        // GETDEL is atomic, so only one request can consume a token
+        let value: Option<String> = self
+            .conn
+            .get_del(get_key(token))
+            .await
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(EmailTokenStoreError::TokenNotFound)?;
+        let stored: StoredRecord =
+            serde_json::from_str(&value).map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        Ok(EmailTokenRecord {
+            email: Email::parse(stored.email).map_err(|_| EmailTokenStoreError::UnexpectedError)?,
+            purpose: stored.purpose,
+            expires_at: stored.expires_at,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    email: String,
+    purpose: EmailTokenPurpose,
+    expires_at: i64,
+}
+
+impl From<&EmailTokenRecord> for StoredRecord {
+    fn from(record: &EmailTokenRecord) -> Self {
+        Self {
+            email: record.email.as_ref().to_owned(),
+            purpose: record.purpose,
+            expires_at: record.expires_at,
+        }
+    }
+}
+
+// Redis rejects an expiry of zero, so expired records are kept for one more second
+fn remaining_seconds(record: &EmailTokenRecord) -> u64 {
+    (record.expires_at - Utc::now().timestamp()).max(1) as u64
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const EMAIL_TOKEN_KEY_PREFIX: &str = "email_token:";
+
+fn get_key(token: &EmailToken) -> String {
+    format!("{}{}", EMAIL_TOKEN_KEY_PREFIX, token.as_ref())
+}
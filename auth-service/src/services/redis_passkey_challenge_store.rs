@@ -0,0 +1,88 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    Email, PasskeyCeremony, PasskeyChallengeId, PasskeyChallengeRecord, PasskeyChallengeStore,
+    PasskeyChallengeStoreError,
+};
+
+pub struct RedisPasskeyChallengeStore {
+    conn: ConnectionManager,
+}
+
+impl RedisPasskeyChallengeStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyChallengeStore for RedisPasskeyChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        challenge_id: PasskeyChallengeId,
+        record: PasskeyChallengeRecord,
+    ) -> Result<(), PasskeyChallengeStoreError> {
+        let value = serde_json::to_string(&StoredRecord::from(&record))
+            .map_err(|_| PasskeyChallengeStoreError::UnexpectedError)?;
+
+        // Redis forgets the challenge once it has expired
+        self.conn
+            .set_ex::<_, _, ()>(get_key(&challenge_id), value, remaining_seconds(&record))
+            .await
+            .map_err(|_| PasskeyChallengeStoreError::UnexpectedError)
+    }
+
+    async fn consume_challenge(
+        &mut self,
+        challenge_id: &PasskeyChallengeId,
+    ) -> Result<PasskeyChallengeRecord, PasskeyChallengeStoreError> {
+        // GETDEL is atomic, so only one request can answer a challenge
+        let value: Option<String> = self
+            .conn
+            .get_del(get_key(challenge_id))
+            .await
+            .map_err(|_| PasskeyChallengeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(PasskeyChallengeStoreError::ChallengeNotFound)?;
+        let stored: StoredRecord = serde_json::from_str(&value)
+            .map_err(|_| PasskeyChallengeStoreError::UnexpectedError)?;
+
+        Ok(PasskeyChallengeRecord {
+            email: Email::parse(stored.email)
+                .map_err(|_| PasskeyChallengeStoreError::UnexpectedError)?,
+            ceremony: stored.ceremony,
+            expires_at: stored.expires_at,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    email: String,
+    ceremony: PasskeyCeremony,
+    expires_at: i64,
+}
+
+impl From<&PasskeyChallengeRecord> for StoredRecord {
+    fn from(record: &PasskeyChallengeRecord) -> Self {
+        Self {
+            email: record.email.as_ref().to_owned(),
+            ceremony: record.ceremony.clone(),
+            expires_at: record.expires_at,
+        }
+    }
+}
+
+// Redis rejects an expiry of zero, so expired records are kept for one more second
+fn remaining_seconds(record: &PasskeyChallengeRecord) -> u64 {
+    (record.expires_at - Utc::now().timestamp()).max(1) as u64
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const PASSKEY_CHALLENGE_KEY_PREFIX: &str = "passkey_challenge:";
+
+fn get_key(challenge_id: &PasskeyChallengeId) -> String {
+    format!("{}{}", PASSKEY_CHALLENGE_KEY_PREFIX, challenge_id.as_ref())
+}
@@ -0,0 +1,134 @@
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+
+use crate::domain::{
+    Email, LockoutParams, RateLimitDecision, RateLimitParams, RateLimitStore, RateLimitStoreError,
+};
+
+pub struct RedisRateLimitStore {
+    conn: ConnectionManager,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+// Refill and take from the bucket in one step, so concurrent requests from
+// different replicas can not both take the last request. Time comes from
+// the Redis server, so clock skew between replicas does not matter.
+// Returns 0 if the request is allowed, otherwise the seconds to wait.
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local burst = tonumber(ARGV[1])
+local tokens_per_ms = tonumber(ARGV[2]) / 60000
+local time = redis.call('TIME')
+local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at_ms')
+local tokens = tonumber(bucket[1]) or burst
+local updated_at_ms = tonumber(bucket[2]) or now_ms
+tokens = math.min(burst, tokens + math.max(0, now_ms - updated_at_ms) * tokens_per_ms)
+
+local retry_after_seconds = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    retry_after_seconds = math.ceil((1 - tokens) / tokens_per_ms / 1000)
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at_ms', now_ms)
+-- A bucket that has refilled completely is the same as no bucket
+redis.call('PEXPIRE', KEYS[1], math.ceil(burst / tokens_per_ms))
+return retry_after_seconds
+"#;
+
+// Count a login attempt and lock the account in one step, so concurrent
+// attempts on different replicas can not all get in before the lockout.
+// Returns 0 if the attempt is allowed, otherwise the seconds to wait.
+const LOGIN_ATTEMPT_SCRIPT: &str = r#"
+local max_failures = tonumber(ARGV[1])
+local lockout_seconds = tonumber(ARGV[2])
+
+local locked_for = redis.call('TTL', KEYS[2])
+if locked_for > 0 then
+    return locked_for
+end
+
+-- The count expires after `lockout_seconds` without another attempt
+local count = redis.call('INCR', KEYS[1])
+redis.call('EXPIRE', KEYS[1], lockout_seconds)
+if count <= max_failures then
+    return 0
+end
+
+-- Start counting again once the lockout is over
+redis.call('SET', KEYS[2], 1, 'EX', lockout_seconds)
+redis.call('DEL', KEYS[1])
+return lockout_seconds
+"#;
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn take_token(
+        &mut self,
+        key: &str,
+        params: RateLimitParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let retry_after_seconds: u64 = Script::new(TAKE_TOKEN_SCRIPT)
+            .key(format!("{}{}", RATE_LIMIT_KEY_PREFIX, key))
+            .arg(params.burst)
+            .arg(params.per_minute)
+            .invoke_async(&mut self.conn)
+            .await
+            .map_err(|_| RateLimitStoreError::UnexpectedError)?;
+
+        match retry_after_seconds {
+            0 => Ok(RateLimitDecision::Allowed),
+            retry_after_seconds => Ok(RateLimitDecision::Limited {
+                retry_after_seconds,
+            }),
+        }
+    }
+
+    async fn record_login_attempt(
+        &mut self,
+        email: &Email,
+        params: LockoutParams,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let retry_after_seconds: u64 = Script::new(LOGIN_ATTEMPT_SCRIPT)
+            .key(get_failures_key(email))
+            .key(get_lockout_key(email))
+            .arg(params.max_failures)
+            .arg(params.lockout_seconds.max(1))
+            .invoke_async(&mut self.conn)
+            .await
+            .map_err(|_| RateLimitStoreError::UnexpectedError)?;
+
+        match retry_after_seconds {
+            0 => Ok(RateLimitDecision::Allowed),
+            retry_after_seconds => Ok(RateLimitDecision::Limited {
+                retry_after_seconds,
+            }),
+        }
+    }
+
+    async fn reset_login_failures(&mut self, email: &Email) -> Result<(), RateLimitStoreError> {
+        self.conn
+            .del::<_, ()>(&[get_failures_key(email), get_lockout_key(email)])
+            .await
+            .map_err(|_| RateLimitStoreError::UnexpectedError)
+    }
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+const LOGIN_FAILURES_KEY_PREFIX: &str = "login_failures:";
+const LOGIN_LOCKOUT_KEY_PREFIX: &str = "login_lockout:";
+
+fn get_failures_key(email: &Email) -> String {
+    format!("{}{}", LOGIN_FAILURES_KEY_PREFIX, email.as_ref())
+}
+
+fn get_lockout_key(email: &Email) -> String {
+    format!("{}{}", LOGIN_LOCKOUT_KEY_PREFIX, email.as_ref())
+}
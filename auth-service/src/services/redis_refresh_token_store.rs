@@ -0,0 +1,199 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    Email, RefreshToken, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError,
+};
+
+pub struct RedisRefreshTokenStore {
+    conn: ConnectionManager,
+    token_ttl_seconds: u64,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(conn: ConnectionManager, token_ttl_seconds: u64) -> Self {
+        Self {
+            conn,
+            token_ttl_seconds,
+        }
+    }
+
+    // Add `member` to the set at `key`, keeping the set alive for as long as
+    // the newest token it refers to
+    async fn add_to_set(
+        &mut self,
+        key: String,
+        member: &str,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let ttl: i64 = self
+            .token_ttl_seconds
+            .try_into()
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        redis::pipe()
+            .sadd(&key, member)
+            .ignore()
+            .expire(&key, ttl)
+            .ignore()
+            .query_async::<()>(&mut self.conn)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let value = serde_json::to_string(&StoredRecord::from(&record))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        self.conn
+            .set_ex::<_, _, ()>(get_token_key(&token), value, remaining_seconds(&record))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        self.add_to_set(get_family_key(&record.family_id), token.as_ref())
+            .await?;
+        self.add_to_set(get_user_key(&record.email), &record.family_id)
+            .await
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        // GETDEL is atomic, so only one request can consume a token
+        let value: Option<String> = self
+            .conn
+            .get_del(get_token_key(token))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if let Some(value) = value {
+            let record = parse_record(&value)?;
+
+            // Remember the token until it would have expired so reuse can be detected
+            self.conn
+                .set_ex::<_, _, ()>(get_used_key(token), value, remaining_seconds(&record))
+                .await
+                .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+            return Ok(record);
+        }
+
+        let used: Option<String> = self
+            .conn
+            .get(get_used_key(token))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        match used {
+            Some(value) => Err(RefreshTokenStoreError::TokenReused(parse_record(&value)?)),
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError> {
+        let family_key = get_family_key(family_id);
+
+        let tokens: Vec<String> = self
+            .conn
+            .smembers(&family_key)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let mut keys = vec![family_key];
+        for token in tokens {
+            keys.push(format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token));
+            keys.push(format!("{}{}", USED_REFRESH_TOKEN_KEY_PREFIX, token));
+        }
+
+        self.conn
+            .del::<_, ()>(keys)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        let user_key = get_user_key(email);
+
+        let family_ids: Vec<String> = self
+            .conn
+            .smembers(&user_key)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        for family_id in family_ids {
+            self.revoke_family(&family_id).await?;
+        }
+
+        self.conn
+            .del::<_, ()>(user_key)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    email: String,
+    family_id: String,
+    expires_at: i64,
+}
+
+impl From<&RefreshTokenRecord> for StoredRecord {
+    fn from(record: &RefreshTokenRecord) -> Self {
+        Self {
+            email: record.email.as_ref().to_owned(),
+            family_id: record.family_id.clone(),
+            expires_at: record.expires_at,
+        }
+    }
+}
+
+fn parse_record(value: &str) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+    let stored: StoredRecord =
+        serde_json::from_str(value).map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+    Ok(RefreshTokenRecord {
+        email: Email::parse(stored.email).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+        family_id: stored.family_id,
+        expires_at: stored.expires_at,
+    })
+}
+
+// Redis rejects an expiry of zero, so expired records are kept for one more second
+fn remaining_seconds(record: &RefreshTokenRecord) -> u64 {
+    (record.expires_at - Utc::now().timestamp()).max(1) as u64
+}
+
+// We are using key prefixes to prevent collisions and organize data!
+const REFRESH_TOKEN_KEY_PREFIX: &str = "refresh_token:";
+const USED_REFRESH_TOKEN_KEY_PREFIX: &str = "used_refresh_token:";
+const REFRESH_TOKEN_FAMILY_KEY_PREFIX: &str = "refresh_token_family:";
+const USER_REFRESH_TOKEN_FAMILIES_KEY_PREFIX: &str = "refresh_token_families:";
+
+fn get_token_key(token: &RefreshToken) -> String {
+    format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, token.as_ref())
+}
+
+fn get_used_key(token: &RefreshToken) -> String {
+    format!("{}{}", USED_REFRESH_TOKEN_KEY_PREFIX, token.as_ref())
+}
+
+fn get_family_key(family_id: &str) -> String {
+    format!("{}{}", REFRESH_TOKEN_FAMILY_KEY_PREFIX, family_id)
+}
+
+fn get_user_key(email: &Email) -> String {
+    format!(
+        "{}{}",
+        USER_REFRESH_TOKEN_FAMILIES_KEY_PREFIX,
+        email.as_ref()
+    )
+}
@@ -0,0 +1,129 @@
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+
+pub struct RedisTwoFACodeStore {
+    conn: ConnectionManager,
+    code_ttl_seconds: u64,
+}
+
+impl RedisTwoFACodeStore {
+    pub fn new(conn: ConnectionManager, code_ttl_seconds: u64) -> Self {
+        Self {
+            conn,
+            code_ttl_seconds,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for RedisTwoFACodeStore {
+    async fn add_code(
+        &mut self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let key = get_key(&email);
+
+        let two_fa_tuple = TwoFATuple(
+            login_attempt_id.as_ref().to_owned(),
+            code.as_ref().to_owned(),
+        );
+        let value = serde_json::to_string(&two_fa_tuple)
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        // Overwrites any pending code for this email and lets Redis expire
+        // it once the code lifetime has passed. The new code starts without
+        // failed attempts.
+        redis::pipe()
+            .atomic()
+            .set_ex(key, value, self.code_ttl_seconds)
+            .ignore()
+            .del(get_failures_key(&email))
+            .ignore()
+            .query_async::<()>(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)
+    }
+
+    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+        let key = get_key(email);
+
+        let (removed,): (u64,) = redis::pipe()
+            .atomic()
+            .del(key)
+            .del(get_failures_key(email))
+            .ignore()
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        match removed {
+            0 => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError> {
+        let failures_key = get_failures_key(email);
+
+        // The count goes when the code does, at the latest
+        let (exists, failed_attempts): (bool, u32) = redis::pipe()
+            .atomic()
+            .exists(get_key(email))
+            .incr(&failures_key, 1)
+            .expire(&failures_key, self.code_ttl_seconds as i64)
+            .ignore()
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        match exists {
+            true => Ok(failed_attempts),
+            false => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    async fn get_code(
+        &self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let key = get_key(email);
+
+        // ConnectionManager is a cheap handle onto a shared connection
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(key)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        let TwoFATuple(login_attempt_id, code) =
+            serde_json::from_str(&value).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        let login_attempt_id = LoginAttemptId::parse(login_attempt_id)
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        let code = TwoFACode::parse(code).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok((login_attempt_id, code))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TwoFATuple(pub String, pub String);
+
+// We are using a key prefix to prevent collisions and organize data!
+const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_FAILURES_PREFIX: &str = "two_fa_failures:";
+
+fn get_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref())
+}
+
+fn get_failures_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_FAILURES_PREFIX, email.as_ref())
+}
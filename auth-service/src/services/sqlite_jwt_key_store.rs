@@ -0,0 +1,124 @@
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+
+use crate::domain::{JwtKeyRecord, JwtKeyStore, JwtKeyStoreError};
+
+pub struct SqliteJwtKeyStore {
+    pool: SqlitePool,
+}
+
+impl SqliteJwtKeyStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl JwtKeyStore for SqliteJwtKeyStore {
+    async fn add_key(&mut self, record: JwtKeyRecord) -> Result<(), JwtKeyStoreError> {
+        sqlx::query(
+            r#"
+            INSERT INTO jwt_keys (kid, algorithm, private_key, activates_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(&record.kid)
+        .bind(&record.algorithm)
+        .bind(&record.private_key)
+        .bind(record.activates_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => JwtKeyStoreError::KeyAlreadyExists,
+            _ => JwtKeyStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_keys(&self) -> Result<Vec<JwtKeyRecord>, JwtKeyStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT kid, algorithm, private_key, activates_at
+            FROM jwt_keys
+            ORDER BY activates_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| JwtKeyStoreError::UnexpectedError)?;
+
+        Ok(rows.iter().map(record_from_row).collect())
+    }
+
+    async fn delete_keys(&mut self, activated_before: i64) -> Result<u64, JwtKeyStoreError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM jwt_keys
+            WHERE activates_at < ?
+            "#,
+        )
+        .bind(activated_before)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| JwtKeyStoreError::UnexpectedError)?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+fn record_from_row(row: &SqliteRow) -> JwtKeyRecord {
+    JwtKeyRecord {
+        kid: row.get("kid"),
+        algorithm: row.get("algorithm"),
+        private_key: row.get("private_key"),
+        activates_at: row.get("activates_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_sqlite_pool, run_sqlite_migrations};
+
+    async fn in_memory_store() -> SqliteJwtKeyStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+        SqliteJwtKeyStore::new(pool)
+    }
+
+    fn record(kid: &str, activates_at: i64) -> JwtKeyRecord {
+        JwtKeyRecord {
+            kid: kid.to_owned(),
+            algorithm: "HS256".to_owned(),
+            private_key: "c2VjcmV0".to_owned(),
+            activates_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_keys() {
+        let mut store = in_memory_store().await;
+        store.add_key(record("second", 20)).await.unwrap();
+        store.add_key(record("first", 10)).await.unwrap();
+
+        let result = store.add_key(record("first", 30)).await;
+        assert_eq!(result, Err(JwtKeyStoreError::KeyAlreadyExists));
+
+        let keys = store.get_keys().await.unwrap();
+        assert_eq!(keys, vec![record("first", 10), record("second", 20)]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_keys() {
+        let mut store = in_memory_store().await;
+        for (kid, activates_at) in [("first", 10), ("second", 20), ("third", 30)] {
+            store.add_key(record(kid, activates_at)).await.unwrap();
+        }
+
+        assert_eq!(store.delete_keys(20).await, Ok(1));
+        assert_eq!(store.delete_keys(20).await, Ok(0));
+
+        let keys = store.get_keys().await.unwrap();
+        assert_eq!(keys, vec![record("second", 20), record("third", 30)]);
+    }
+}
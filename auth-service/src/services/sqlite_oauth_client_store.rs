@@ -0,0 +1,245 @@
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+
+use crate::domain::{
+    ClientId, ClientSecretHash, Email, OAuthClient, OAuthClientStore, OAuthClientStoreError,
+    RedirectUri,
+};
+
+pub struct SqliteOAuthClientStore {
+    pool: SqlitePool,
+}
+
+impl SqliteOAuthClientStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for SqliteOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient) -> Result<(), OAuthClientStoreError> {
+        let redirect_uris = serde_json::to_string(
+            &client
+                .redirect_uris
+                .iter()
+                .map(AsRef::as_ref)
+                .collect::<Vec<&str>>(),
+        )
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_clients (client_id, name, redirect_uris, secret_hash)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(client.client_id.as_ref())
+        .bind(&client.name)
+        .bind(redirect_uris)
+        .bind(client.secret_hash.as_ref().map(AsRef::as_ref))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => {
+                OAuthClientStoreError::ClientAlreadyExists
+            }
+            _ => OAuthClientStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &ClientId) -> Result<OAuthClient, OAuthClientStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT client_id, name, redirect_uris, secret_hash
+            FROM oauth_clients
+            WHERE client_id = ?
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?;
+
+        client_from_row(&row)
+    }
+
+    async fn add_consent(
+        &mut self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<(), OAuthClientStoreError> {
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_consents (client_id, email)
+            VALUES (?, ?)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        // Consent is given by a logged in user, so the missing row is the client's
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_foreign_key_violation() => {
+                OAuthClientStoreError::ClientNotFound
+            }
+            _ => OAuthClientStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn has_consent(
+        &self,
+        client_id: &ClientId,
+        email: &Email,
+    ) -> Result<bool, OAuthClientStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT 1
+            FROM oauth_consents
+            WHERE client_id = ? AND email = ?
+            "#,
+        )
+        .bind(client_id.as_ref())
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+}
+
+fn client_from_row(row: &SqliteRow) -> Result<OAuthClient, OAuthClientStoreError> {
+    let redirect_uris: Vec<String> = serde_json::from_str(row.get("redirect_uris"))
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+    let secret_hash: Option<String> = row.get("secret_hash");
+
+    Ok(OAuthClient {
+        client_id: ClientId::parse(row.get("client_id"))
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+        name: row.get("name"),
+        redirect_uris: redirect_uris
+            .into_iter()
+            .map(RedirectUri::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+        secret_hash: secret_hash
+            .map(ClientSecretHash::parse)
+            .transpose()
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            ClientSecret, KeyEncryptionKey, Password, PasswordHash, PasswordHashingParams, User,
+            UserStore,
+        },
+        get_sqlite_pool, run_sqlite_migrations,
+        services::SqliteUserStore,
+    };
+
+    fn client(secret: Option<&ClientSecret>) -> OAuthClient {
+        OAuthClient {
+            client_id: ClientId::default(),
+            name: "Test".to_owned(),
+            redirect_uris: vec![
+                RedirectUri::parse("http://localhost:8000/callback".to_owned()).unwrap(),
+                RedirectUri::parse("http://127.0.0.1:8000/callback".to_owned()).unwrap(),
+            ],
+            secret_hash: secret.map(ClientSecret::hash),
+        }
+    }
+
+    fn key_encryption_key() -> KeyEncryptionKey {
+        KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap()
+    }
+
+    // Consent can only be given by existing users
+    async fn in_memory_stores(emails: &[&Email]) -> (SqliteUserStore, SqliteOAuthClientStore) {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+
+        let mut user_store = SqliteUserStore::new(pool.clone(), key_encryption_key());
+        let params = PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let password_hash = PasswordHash::hash(&password, params).await.unwrap();
+        for email in emails {
+            let user = User::new((*email).clone(), password_hash.clone(), false);
+            user_store.add_user(user).await.unwrap();
+        }
+
+        (user_store, SqliteOAuthClientStore::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_add_client() {
+        let (_, mut store) = in_memory_stores(&[]).await;
+        let confidential = client(Some(&ClientSecret::default()));
+        let public = client(None);
+
+        let result = store.get_client(&confidential.client_id).await;
+        assert_eq!(result, Err(OAuthClientStoreError::ClientNotFound));
+
+        for client in [confidential, public] {
+            store.add_client(client.clone()).await.unwrap();
+            assert_eq!(
+                store.get_client(&client.client_id).await,
+                Ok(client.clone())
+            );
+
+            let result = store.add_client(client).await;
+            assert_eq!(result, Err(OAuthClientStoreError::ClientAlreadyExists));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consent() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let (_, mut store) = in_memory_stores(&[&email]).await;
+        let client = client(None);
+        let other_client = self::client(None);
+
+        let result = store.add_consent(&client.client_id, &email).await;
+        assert_eq!(result, Err(OAuthClientStoreError::ClientNotFound));
+
+        store.add_client(client.clone()).await.unwrap();
+        store.add_client(other_client.clone()).await.unwrap();
+        assert!(!store.has_consent(&client.client_id, &email).await.unwrap());
+
+        store.add_consent(&client.client_id, &email).await.unwrap();
+        // Consenting again changes nothing
+        store.add_consent(&client.client_id, &email).await.unwrap();
+        assert!(store.has_consent(&client.client_id, &email).await.unwrap());
+        assert!(!store
+            .has_consent(&other_client.client_id, &email)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_consents_are_removed_with_user() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let (mut user_store, mut store) = in_memory_stores(&[&email]).await;
+        let client = client(None);
+
+        store.add_client(client.clone()).await.unwrap();
+        store.add_consent(&client.client_id, &email).await.unwrap();
+        user_store.delete_user(&email).await.unwrap();
+
+        assert!(!store.has_consent(&client.client_id, &email).await.unwrap());
+    }
+}
@@ -0,0 +1,252 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sqlx::{Row, SqlitePool};
+use webauthn_rs::prelude::{AuthenticationResult, CredentialID, Passkey};
+
+use crate::domain::{Email, PasskeyStore, PasskeyStoreError};
+
+pub struct SqlitePasskeyStore {
+    pool: SqlitePool,
+}
+
+impl SqlitePasskeyStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for SqlitePasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: &Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        let value =
+            serde_json::to_string(&passkey).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO passkeys (credential_id, email, passkey)
+            VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(encode_credential_id(passkey.cred_id()))
+        .bind(email.as_ref())
+        .bind(value)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => {
+                PasskeyStoreError::PasskeyAlreadyExists
+            }
+            _ => PasskeyStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT passkey
+            FROM passkeys
+            WHERE email = ?
+            ORDER BY credential_id
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| {
+                let value: String = row.get("passkey");
+                serde_json::from_str(&value).map_err(|_| PasskeyStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+
+    async fn update_passkey(
+        &mut self,
+        email: &Email,
+        result: &AuthenticationResult,
+    ) -> Result<(), PasskeyStoreError> {
+        let credential_id = encode_credential_id(result.cred_id());
+
+        let row = sqlx::query(
+            r#"
+            SELECT passkey
+            FROM passkeys
+            WHERE credential_id = ? AND email = ?
+            "#,
+        )
+        .bind(&credential_id)
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?
+        .ok_or(PasskeyStoreError::PasskeyNotFound)?;
+
+        let value: String = row.get("passkey");
+        let mut passkey: Passkey =
+            serde_json::from_str(&value).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        // Nothing to save unless the counter or backup state changed
+        if passkey.update_credential(result) != Some(true) {
+            return Ok(());
+        }
+
+        let value =
+            serde_json::to_string(&passkey).map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            r#"
+            UPDATE passkeys
+            SET passkey = ?
+            WHERE credential_id = ?
+            "#,
+        )
+        .bind(value)
+        .bind(&credential_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+fn encode_credential_id(credential_id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(credential_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            KeyEncryptionKey, Password, PasswordHash, PasswordHashingParams, User, UserStore,
+        },
+        get_sqlite_pool, run_sqlite_migrations,
+        services::SqliteUserStore,
+    };
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+    use webauthn_rs::prelude::{Url, Uuid, Webauthn, WebauthnBuilder};
+
+    // Register a passkey with a software authenticator, which is returned
+    // for signing in with it
+    fn register() -> (Webauthn, WebauthnAuthenticator<SoftPasskey>, Passkey) {
+        let origin = Url::parse("http://localhost:3000").unwrap();
+        let webauthn = WebauthnBuilder::new("localhost", &origin)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (options, registration) = webauthn
+            .start_passkey_registration(Uuid::new_v4(), "test", "test", None)
+            .unwrap();
+        let credential = authenticator.do_registration(origin, options).unwrap();
+        let passkey = webauthn
+            .finish_passkey_registration(&credential, &registration)
+            .unwrap();
+
+        (webauthn, authenticator, passkey)
+    }
+
+    fn sign_in(
+        webauthn: &Webauthn,
+        authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+        passkey: &Passkey,
+    ) -> AuthenticationResult {
+        let origin = Url::parse("http://localhost:3000").unwrap();
+        let (options, authentication) = webauthn
+            .start_passkey_authentication(std::slice::from_ref(passkey))
+            .unwrap();
+        let credential = authenticator.do_authentication(origin, options).unwrap();
+        webauthn
+            .finish_passkey_authentication(&credential, &authentication)
+            .unwrap()
+    }
+
+    fn key_encryption_key() -> KeyEncryptionKey {
+        KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap()
+    }
+
+    // Passkeys can only belong to existing users
+    async fn in_memory_stores(emails: &[&Email]) -> (SqliteUserStore, SqlitePasskeyStore) {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+
+        let mut user_store = SqliteUserStore::new(pool.clone(), key_encryption_key());
+        let params = PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let password_hash = PasswordHash::hash(&password, params).await.unwrap();
+        for email in emails {
+            let user = User::new((*email).clone(), password_hash.clone(), false);
+            user_store.add_user(user).await.unwrap();
+        }
+
+        (user_store, SqlitePasskeyStore::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_add_passkey() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other_email = Email::parse("other@example.com".to_owned()).unwrap();
+        let unknown_email = Email::parse("unknown@example.com".to_owned()).unwrap();
+        let (_, mut store) = in_memory_stores(&[&email, &other_email]).await;
+        let (_, _, passkey) = register();
+
+        assert_eq!(store.get_passkeys(&email).await.unwrap().len(), 0);
+
+        store.add_passkey(&email, passkey.clone()).await.unwrap();
+        let passkeys = store.get_passkeys(&email).await.unwrap();
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].cred_id(), passkey.cred_id());
+
+        // Credential IDs are unique across users
+        let result = store.add_passkey(&other_email, passkey).await;
+        assert_eq!(result, Err(PasskeyStoreError::PasskeyAlreadyExists));
+
+        let (_, _, passkey) = register();
+        let result = store.add_passkey(&unknown_email, passkey).await;
+        assert_eq!(result, Err(PasskeyStoreError::UnexpectedError));
+    }
+
+    #[tokio::test]
+    async fn test_update_passkey() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other_email = Email::parse("other@example.com".to_owned()).unwrap();
+        let (_, mut store) = in_memory_stores(&[&email, &other_email]).await;
+        let (webauthn, mut authenticator, passkey) = register();
+        let result = sign_in(&webauthn, &mut authenticator, &passkey);
+
+        store.add_passkey(&email, passkey).await.unwrap();
+
+        let update = store.update_passkey(&other_email, &result).await;
+        assert_eq!(update, Err(PasskeyStoreError::PasskeyNotFound));
+
+        store.update_passkey(&email, &result).await.unwrap();
+        // The saved passkey already reflects the sign-in
+        let mut passkey = store.get_passkeys(&email).await.unwrap().remove(0);
+        assert_eq!(passkey.update_credential(&result), Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_passkeys_are_removed_with_user() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let (mut user_store, mut store) = in_memory_stores(&[&email]).await;
+        let (_, _, passkey) = register();
+
+        store.add_passkey(&email, passkey).await.unwrap();
+        user_store.delete_user(&email).await.unwrap();
+
+        assert_eq!(store.get_passkeys(&email).await.unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,1042 @@
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::domain::{
+    format_recovery_codes, format_roles, parse_recovery_codes, parse_roles, Email,
+    KeyEncryptionKey, PasswordHash, RecoveryCodeHash, TotpSecret, User, UserStore, UserStoreError,
+};
+
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+    key_encryption_key: KeyEncryptionKey,
+}
+
+impl SqliteUserStore {
+    pub fn new(pool: SqlitePool, key_encryption_key: KeyEncryptionKey) -> Self {
+        Self {
+            pool,
+            key_encryption_key,
+        }
+    }
+
+    // TOTP secrets are stored encrypted, bound to the user's email
+    fn encrypt_totp_secret(
+        &self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<String, UserStoreError> {
+        self.key_encryption_key
+            .encrypt(secret.as_ref(), email.as_ref())
+            .ok_or(UserStoreError::UnexpectedError)
+    }
+
+    fn decrypt_totp_secret(
+        &self,
+        email: &Email,
+        encrypted: &str,
+    ) -> Result<TotpSecret, UserStoreError> {
+        let secret = self
+            .key_encryption_key
+            .decrypt(encrypted, email.as_ref())
+            .ok_or(UserStoreError::UnexpectedError)?;
+        TotpSecret::parse(secret).map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    // Rows are only ever written from valid domain types, so a parse failure
+    // here means the table was modified by hand
+    fn user_from_row(&self, row: &SqliteRow) -> Result<User, UserStoreError> {
+        let email = Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let password_hash = PasswordHash::parse(row.get("password_hash"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let roles = parse_roles(row.get("roles")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let totp_secret = row
+            .get::<Option<String>, _>("totp_secret")
+            .map(|secret| self.decrypt_totp_secret(&email, &secret))
+            .transpose()?;
+        let recovery_codes = parse_recovery_codes(row.get("recovery_codes"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(User::new(email, password_hash, row.get("requires_2fa"))
+            .with_roles(roles)
+            .with_email_verified(row.get("email_verified"))
+            .with_totp_secret(totp_secret)
+            .with_recovery_codes(recovery_codes)
+            .with_session_version(row.get("session_version")))
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqliteUserStore {
+    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let totp_secret = user
+            .totp_secret
+            .as_ref()
+            .map(|secret| self.encrypt_totp_secret(&user.email, secret))
+            .transpose()?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO users (
+                email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(user.email.as_ref())
+        .bind(user.password_hash.as_ref())
+        .bind(user.requires_2fa)
+        .bind(format_roles(&user.roles))
+        .bind(user.email_verified)
+        .bind(totp_secret)
+        .bind(format_recovery_codes(&user.recovery_codes))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes,
+                session_version
+            FROM users
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        self.user_from_row(&row)
+    }
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET email_verified = TRUE
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(password_hash.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET requires_2fa = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(requires_2fa)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_pending_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        let encrypted_secret = self.encrypt_totp_secret(email, &secret)?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_pending_secret = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(encrypted_secret)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_pending_totp_secret(
+        &self,
+        email: &Email,
+    ) -> Result<Option<TotpSecret>, UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT totp_pending_secret
+            FROM users
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        row.get::<Option<String>, _>("totp_pending_secret")
+            .map(|secret| self.decrypt_totp_secret(email, &secret))
+            .transpose()
+    }
+
+    async fn confirm_totp_secret(
+        &mut self,
+        email: &Email,
+        secret: &TotpSecret,
+    ) -> Result<(), UserStoreError> {
+        // The same secret encrypts differently every time, so compare the
+        // decrypted one
+        let pending_secret = sqlx::query(
+            r#"
+            SELECT totp_pending_secret
+            FROM users
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .and_then(|row| row.get::<Option<String>, _>("totp_pending_secret"))
+        .ok_or(UserStoreError::UserNotFound)?;
+        if self.decrypt_totp_secret(email, &pending_secret)? != *secret {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        // Unless it was replaced in the meantime
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_secret = totp_pending_secret, totp_pending_secret = NULL, requires_2fa = TRUE,
+                totp_last_step = NULL
+            WHERE email = ? AND totp_pending_secret = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .bind(pending_secret)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn set_recovery_codes(
+        &mut self,
+        email: &Email,
+        recovery_codes: Vec<RecoveryCodeHash>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET recovery_codes = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(format_recovery_codes(&recovery_codes))
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn use_recovery_code(
+        &mut self,
+        email: &Email,
+        recovery_code: &RecoveryCodeHash,
+    ) -> Result<(), UserStoreError> {
+        // Compare and swap, so two replicas can't both use the same code.
+        // If the update misses, another request changed the codes first.
+        loop {
+            let user = self.get_user(email).await?;
+            let remaining: Vec<RecoveryCodeHash> = user
+                .recovery_codes
+                .iter()
+                .filter(|hash| *hash != recovery_code)
+                .cloned()
+                .collect();
+
+            if remaining.len() == user.recovery_codes.len() {
+                return Err(UserStoreError::InvalidCredentials);
+            }
+
+            let result = sqlx::query(
+                r#"
+                UPDATE users
+                SET recovery_codes = ?
+                WHERE email = ? AND recovery_codes = ? AND deleted_at IS NULL
+                "#,
+            )
+            .bind(format_recovery_codes(&remaining))
+            .bind(email.as_ref())
+            .bind(format_recovery_codes(&user.recovery_codes))
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+            if result.rows_affected() > 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn use_totp_step(&mut self, email: &Email, time_step: i64) -> Result<(), UserStoreError> {
+        // Only moves forward, so two replicas can't both accept the same code
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_last_step = ?
+            WHERE email = ? AND deleted_at IS NULL
+                AND (totp_last_step IS NULL OR totp_last_step < ?)
+            "#,
+        )
+        .bind(time_step)
+        .bind(email.as_ref())
+        .bind(time_step)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+        self.get_user(email).await?;
+        Err(UserStoreError::InvalidCredentials)
+    }
+
+    async fn passkey_user_handle(&mut self, email: &Email) -> Result<Uuid, UserStoreError> {
+        // Only set if still unset, so concurrent registrations agree on it
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET passkey_user_handle = ?
+            WHERE email = ? AND deleted_at IS NULL AND passkey_user_handle IS NULL
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT passkey_user_handle
+            FROM users
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Uuid::parse_str(&row.get::<String, _>("passkey_user_handle"))
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn increment_session_version(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET session_version = session_version + 1
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let totp_secret = user
+            .totp_secret
+            .as_ref()
+            .map(|secret| self.encrypt_totp_secret(&user.email, secret))
+            .transpose()?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = ?, requires_2fa = ?, roles = ?, email_verified = ?,
+                totp_secret = ?, recovery_codes = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(user.password_hash.as_ref())
+        .bind(user.requires_2fa)
+        .bind(format_roles(&user.roles))
+        .bind(user.email_verified)
+        .bind(totp_secret)
+        .bind(format_recovery_codes(&user.recovery_codes))
+        .bind(user.email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list_users(&self, offset: u64, limit: u64) -> Result<Vec<User>, UserStoreError> {
+        let offset = i64::try_from(offset).map_err(|_| UserStoreError::UnexpectedError)?;
+        let limit = i64::try_from(limit).map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, roles, email_verified, totp_secret, recovery_codes,
+                session_version
+            FROM users
+            WHERE deleted_at IS NULL
+            ORDER BY email
+            LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter().map(|row| self.user_from_row(row)).collect()
+    }
+
+    async fn soft_delete_user(
+        &mut self,
+        email: &Email,
+        deleted_at: i64,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET deleted_at = ?
+            WHERE email = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(deleted_at)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn deleted_users(&self, deleted_before: i64) -> Result<Vec<Email>, UserStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT email
+            FROM users
+            WHERE deleted_at <= ?
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    async fn purge_deleted_users(
+        &mut self,
+        deleted_before: i64,
+    ) -> Result<Vec<Email>, UserStoreError> {
+        let rows = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE deleted_at <= ?
+            RETURNING email
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter()
+            .map(|row| Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{Password, PasswordHashingParams, RecoveryCode, Role},
+        get_sqlite_pool, run_sqlite_migrations,
+    };
+
+    async fn hash(password: &Password) -> PasswordHash {
+        let params = PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        PasswordHash::hash(password, params).await.unwrap()
+    }
+
+    fn key_encryption_key() -> KeyEncryptionKey {
+        KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap()
+    }
+
+    async fn in_memory_store() -> SqliteUserStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+        SqliteUserStore::new(pool, key_encryption_key())
+    }
+
+    #[tokio::test]
+    async fn test_add_user() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, true);
+
+        // Test adding a user successfully
+        let result = store.add_user(user.clone()).await;
+        assert_eq!(result, Ok(()));
+
+        // Test adding the same user again should fail
+        let result = store.add_user(user).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_get_user() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+
+        // Test getting a user that doesn't exist
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        // Add user and test getting it
+        store.add_user(user.clone()).await.unwrap();
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Ok(user));
+    }
+
+    #[tokio::test]
+    async fn test_roles_are_stored() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false)
+            .with_roles([Role::User, Role::Admin].into());
+
+        store.add_user(user).await.unwrap();
+        let user = store.get_user(&email).await.unwrap();
+        assert!(user.has_role(Role::Admin));
+        assert!(user.has_role(Role::User));
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.mark_email_verified(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().email_verified);
+
+        store.mark_email_verified(&email).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().email_verified);
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let new_password = Password::parse("newpassword123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store
+            .update_password(&email, hash(&new_password).await)
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store
+            .update_password(&email, hash(&new_password).await)
+            .await
+            .unwrap();
+
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+        let result = store.validate_user(&email, &new_password).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_set_requires_2fa() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.set_requires_2fa(&email, true).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store.set_requires_2fa(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().requires_2fa);
+
+        store.set_requires_2fa(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().requires_2fa);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_totp_secret() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+        let secret = TotpSecret::default();
+
+        let result = store.set_pending_totp_secret(&email, secret.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.get_pending_totp_secret(&email).await, Ok(None));
+        store
+            .set_pending_totp_secret(&email, secret.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_pending_totp_secret(&email).await,
+            Ok(Some(secret.clone()))
+        );
+        assert_eq!(store.get_user(&email).await.unwrap().totp_secret, None);
+
+        // Only the pending secret can be confirmed
+        let result = store
+            .confirm_totp_secret(&email, &TotpSecret::default())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.confirm_totp_secret(&email, &secret).await.unwrap();
+        let user = store.get_user(&email).await.unwrap();
+        assert_eq!(user.totp_secret, Some(secret.clone()));
+        assert!(user.requires_2fa);
+        assert_eq!(store.get_pending_totp_secret(&email).await, Ok(None));
+
+        let result = store.confirm_totp_secret(&email, &secret).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_totp_secrets_are_stored_encrypted() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let secret = TotpSecret::default();
+        let user = User::new(email.clone(), hash(&password).await, true)
+            .with_totp_secret(Some(secret.clone()));
+        let other_user = User::new(other_email.clone(), hash(&password).await, false);
+
+        store.add_user(user).await.unwrap();
+        store.add_user(other_user).await.unwrap();
+        store
+            .set_pending_totp_secret(&email, secret.clone())
+            .await
+            .unwrap();
+
+        let row = sqlx::query("SELECT totp_secret, totp_pending_secret FROM users WHERE email = ?")
+            .bind(email.as_ref())
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        let stored_secret: String = row.get("totp_secret");
+        let stored_pending_secret: String = row.get("totp_pending_secret");
+        assert!(!stored_secret.contains(secret.as_ref()));
+        assert!(!stored_pending_secret.contains(secret.as_ref()));
+
+        // A secret copied to another user can not be decrypted
+        sqlx::query("UPDATE users SET totp_secret = ? WHERE email = ?")
+            .bind(&stored_secret)
+            .bind(other_email.as_ref())
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        let result = store.get_user(&other_email).await;
+        assert_eq!(result, Err(UserStoreError::UnexpectedError));
+    }
+
+    #[tokio::test]
+    async fn test_use_totp_step() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.use_totp_step(&email, 10).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store.use_totp_step(&email, 10).await.unwrap();
+
+        // Each step is only accepted once, and earlier steps not at all
+        for time_step in [10, 9] {
+            let result = store.use_totp_step(&email, time_step).await;
+            assert_eq!(
+                result,
+                Err(UserStoreError::InvalidCredentials),
+                "Failed for input: {:?}",
+                time_step
+            );
+        }
+        store.use_totp_step(&email, 11).await.unwrap();
+
+        // A newly confirmed secret starts over
+        let secret = TotpSecret::default();
+        store
+            .set_pending_totp_secret(&email, secret.clone())
+            .await
+            .unwrap();
+        store.confirm_totp_secret(&email, &secret).await.unwrap();
+        store.use_totp_step(&email, 11).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_passkey_user_handle() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+
+        let result = store.passkey_user_handle(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        for email in [&email, &other_email] {
+            let user = User::new(email.clone(), hash(&password).await, false);
+            store.add_user(user).await.unwrap();
+        }
+
+        // Assigned once, and not derived from the email
+        let handle = store.passkey_user_handle(&email).await.unwrap();
+        assert_eq!(store.passkey_user_handle(&email).await, Ok(handle));
+        assert_ne!(store.passkey_user_handle(&other_email).await, Ok(handle));
+    }
+
+    #[tokio::test]
+    async fn test_use_recovery_code() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+        let codes = RecoveryCode::generate_batch();
+        let hashes: Vec<RecoveryCodeHash> = codes.iter().map(RecoveryCode::hash).collect();
+
+        let result = store.set_recovery_codes(&email, hashes.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user).await.unwrap();
+        store
+            .set_recovery_codes(&email, hashes.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().recovery_codes, hashes);
+
+        store.use_recovery_code(&email, &hashes[3]).await.unwrap();
+        let remaining = store.get_user(&email).await.unwrap().recovery_codes;
+        assert_eq!(remaining.len(), hashes.len() - 1);
+        assert!(!remaining.contains(&hashes[3]));
+
+        // Each code only works once
+        let result = store.use_recovery_code(&email, &hashes[3]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        // A new batch replaces the old one
+        store
+            .set_recovery_codes(&email, vec![RecoveryCode::default().hash()])
+            .await
+            .unwrap();
+        let result = store.use_recovery_code(&email, &hashes[0]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_update_user() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.update_user(user.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        let updated = User::new(email.clone(), hash(&password).await, true)
+            .with_roles([Role::User, Role::Admin].into())
+            .with_email_verified(true)
+            .with_totp_secret(Some(TotpSecret::default()))
+            .with_recovery_codes(vec![RecoveryCode::default().hash()]);
+        store.update_user(updated.clone()).await.unwrap();
+
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Ok(updated));
+    }
+
+    #[tokio::test]
+    async fn test_increment_session_version() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.increment_session_version(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.increment_session_version(&email).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_version, 1);
+
+        // Updating the user doesn't bring old sessions back
+        store.update_user(user).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.delete_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.delete_user(&email).await.unwrap();
+
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        // The address can be used for a new account
+        let result = store.add_user(user).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let mut store = in_memory_store().await;
+        let password = Password::parse("password123".to_string()).unwrap();
+        let password_hash = hash(&password).await;
+
+        assert_eq!(store.list_users(0, 10).await, Ok(Vec::new()));
+
+        // Added out of order, listed by email
+        for name in ["c", "a", "d", "b", "e"] {
+            let email = Email::parse(format!("{}@example.com", name)).unwrap();
+            let user = User::new(email, password_hash.clone(), false);
+            store.add_user(user).await.unwrap();
+        }
+
+        let test_cases = [
+            (0, 2, vec!["a", "b"]),
+            (2, 2, vec!["c", "d"]),
+            (4, 2, vec!["e"]),
+            (5, 2, vec![]),
+            (0, 0, vec![]),
+        ];
+
+        for (offset, limit, expected) in test_cases {
+            let users = store.list_users(offset, limit).await.unwrap();
+            let names: Vec<&str> = users
+                .iter()
+                .map(|user| user.email.as_ref().split('@').next().unwrap())
+                .collect();
+            assert_eq!(names, expected, "Failed for input: {:?}", (offset, limit));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_and_purge() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        let result = store.soft_delete_user(&email, 100).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        store.soft_delete_user(&email, 100).await.unwrap();
+
+        // Soft deleted users are hidden, but keep their email taken
+        let result = store.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.list_users(0, 10).await, Ok(Vec::new()));
+        let result = store.soft_delete_user(&email, 100).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.add_user(user.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+
+        // Only users deleted at or before the cutoff are listed and purged
+        assert_eq!(store.deleted_users(99).await, Ok(Vec::new()));
+        assert_eq!(store.deleted_users(100).await, Ok(vec![email.clone()]));
+        assert_eq!(store.purge_deleted_users(99).await, Ok(Vec::new()));
+        assert_eq!(
+            store.purge_deleted_users(100).await,
+            Ok(vec![email.clone()])
+        );
+
+        let result = store.add_user(user).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_validate_user() {
+        let mut store = in_memory_store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let wrong_password = Password::parse("wrongpassword".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, false);
+
+        // Test validating a user that doesn't exist
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+
+        // Add user and test validating with correct password
+        store.add_user(user).await.unwrap();
+        let result = store.validate_user(&email, &password).await;
+        assert_eq!(result, Ok(()));
+
+        // Test validating with incorrect password
+        let result = store.validate_user(&email, &wrong_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_file_backed_store_survives_reconnect() {
+        let path = std::env::temp_dir().join(format!("auth-{}.db", uuid::Uuid::new_v4()));
+        let url = format!("sqlite://{}", path.display());
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+
+        let pool = get_sqlite_pool(&url).await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+        SqliteUserStore::new(pool.clone(), key_encryption_key())
+            .add_user(user.clone())
+            .await
+            .unwrap();
+        pool.close().await;
+
+        // Reopening the file should find the user and leave migrations as-is
+        let pool = get_sqlite_pool(&url).await.unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+        let result = SqliteUserStore::new(pool.clone(), key_encryption_key())
+            .get_user(&email)
+            .await;
+        assert_eq!(result, Ok(user));
+        pool.close().await;
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,1247 @@
+use std::{error::Error, fmt, net::SocketAddr, path::Path, str::FromStr};
+
+use dotenvy::dotenv;
+use serde::Deserialize;
+
+use crate::{
+    domain::{Email, KeyEncryptionKey, LockoutParams, PasswordHashingParams, RateLimitParams},
+    utils::{
+        client_ip::TrustedProxy,
+        constants::{defaults, env},
+        webauthn::build_webauthn,
+    },
+};
+
+// Settings are layered: built-in defaults, then an optional TOML file, then
+// environment variables. They are validated once at startup.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub address: String,
+    // Where users reach this service, used to build links sent by email
+    pub public_url: String,
+    pub jwt: JwtSettings,
+    pub two_fa_code_ttl_seconds: u64,
+    // Wrong answers to a 2FA challenge before it has to be started again
+    pub two_fa_max_failed_attempts: u32,
+    // Shown next to the account in authenticator apps
+    pub totp_issuer: String,
+    pub webauthn: WebauthnSettings,
+    pub password_hashing: PasswordHashingParams,
+    // Requests per client IP to the routes that accept passwords or codes, or
+    // send emails: signup, login, magic links, 2FA, verification emails,
+    // password reset and change, account deletion, TOTP, recovery codes,
+    // passkeys, OAuth consent and /token
+    pub rate_limit: RateLimitParams,
+    // Reverse proxies whose forwarding headers name the client IP
+    pub trusted_proxies: Vec<TrustedProxy>,
+    // Consecutive failed logins before an account is locked
+    pub login_lockout: LockoutParams,
+    pub email_verification: EmailVerificationSettings,
+    pub password_reset: PasswordResetSettings,
+    // Turning this off leaves login links and passkeys as the only ways to log in
+    pub password_login_enabled: bool,
+    pub magic_link: MagicLinkSettings,
+    pub oauth: OAuthSettings,
+    pub account_deletion: AccountDeletionSettings,
+    pub email: EmailSettings,
+    pub database_url: Option<String>,
+    pub redis_url: Option<String>,
+    // Bearer token for the /admin routes, which are disabled while it is unset
+    pub admin_api_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtSettings {
+    pub algorithm: JwtAlgorithm,
+    // Only used by HS256
+    pub secret: String,
+    // PEM encoded private key, required by RS256 and EdDSA
+    pub private_key_path: Option<String>,
+    // Keys that were used for signing before the current one. They keep
+    // verifying the tokens they signed until those tokens have expired.
+    pub retired_keys: Vec<JwtKeySettings>,
+    // `iss` claim of every token we sign, and the only issuer we accept
+    pub issuer: String,
+    // Services tokens are minted for. A token is accepted by a service only
+    // if the service is listed in its `aud` claim.
+    pub audiences: Vec<String>,
+    pub leeway_seconds: u64,
+    pub cookie_name: String,
+    pub token_ttl_seconds: u64,
+    pub refresh_cookie_name: String,
+    pub refresh_token_ttl_seconds: u64,
+    // How often keys rotated on any replica are loaded from the key store. A
+    // rotated key only signs tokens once every replica has had time to load it.
+    pub key_sync_interval_seconds: u64,
+    // Base64url encoded 32 byte key that secrets are encrypted with in the
+    // database: the private keys of rotated keys and users' TOTP secrets.
+    // Required by the admin routes and with a database.
+    pub key_encryption_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtKeySettings {
+    pub algorithm: JwtAlgorithm,
+    #[serde(default)]
+    pub secret: String,
+    pub private_key_path: Option<String>,
+}
+
+// HS256 signs tokens with a shared secret. RS256 and EdDSA sign them with a
+// private key, so other services can verify tokens with the published public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum JwtAlgorithm {
+    HS256,
+    RS256,
+    EdDSA,
+}
+
+impl JwtAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HS256 => "HS256",
+            Self::RS256 => "RS256",
+            Self::EdDSA => "EdDSA",
+        }
+    }
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Self::HS256),
+            "RS256" => Ok(Self::RS256),
+            "EdDSA" => Ok(Self::EdDSA),
+            _ => Err(()),
+        }
+    }
+}
+
+// Passkeys are bound to the relying party ID, so changing it invalidates
+// every passkey already registered
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebauthnSettings {
+    // Defaults to the domain of public_url. A parent domain lets passkeys
+    // be shared with sibling services.
+    pub rp_id: Option<String>,
+    pub rp_name: String,
+    pub challenge_ttl_seconds: u64,
+    // Secret that the made-up passkeys offered for addresses without any are
+    // derived from. Every replica must share it, or their answers would tell
+    // real passkeys from made-up ones. A random key is used when unset.
+    pub fake_credential_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailVerificationSettings {
+    // Refuse to log in users who have not verified their email address yet
+    pub required: bool,
+    // How long a verification link can be used after it was sent
+    pub token_ttl_seconds: u64,
+    // Verification emails per address sent by /resend-verification
+    pub resend_limit: RateLimitParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordResetSettings {
+    // How long a reset link can be used after it was sent
+    pub token_ttl_seconds: u64,
+    // Reset emails per address sent by /forgot-password
+    pub request_limit: RateLimitParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MagicLinkSettings {
+    // How long a login link can be used after it was sent
+    pub token_ttl_seconds: u64,
+    // Login emails per address sent by /login/magic-link
+    pub request_limit: RateLimitParams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OAuthSettings {
+    // How long a client has to exchange an authorization code at /token
+    pub authorization_code_ttl_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountDeletionSettings {
+    // How long a deleted account is kept before it is purged for good
+    pub grace_period_seconds: u64,
+    // How often the background task looks for accounts to purge
+    pub purge_interval_seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailSettings {
+    pub sender: String,
+    pub outbox_dir: Option<String>,
+    // Print emails to stdout instead, for local development. Only debug
+    // builds honour it, since emails hold login and password reset links.
+    pub log_only: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SettingsError {
+    ReadFile {
+        path: String,
+        reason: String,
+    },
+    ParseFile {
+        path: String,
+        reason: String,
+    },
+    InvalidEnvVar {
+        name: &'static str,
+    },
+    InvalidValue {
+        setting: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::ReadFile { path, reason } => {
+                write!(f, "failed to read config file {}: {}", path, reason)
+            }
+            SettingsError::ParseFile { path, reason } => {
+                write!(f, "failed to parse config file {}: {}", path, reason)
+            }
+            SettingsError::InvalidEnvVar { name } => {
+                write!(f, "environment variable {} has an invalid value", name)
+            }
+            SettingsError::InvalidValue { setting, reason } => {
+                write!(f, "setting `{}` {}", setting, reason)
+            }
+        }
+    }
+}
+
+impl Error for SettingsError {}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            address: defaults::BIND_ADDRESS.to_owned(),
+            public_url: defaults::PUBLIC_URL.to_owned(),
+            jwt: JwtSettings::default(),
+            two_fa_code_ttl_seconds: defaults::TWO_FA_CODE_TTL_SECONDS,
+            two_fa_max_failed_attempts: defaults::TWO_FA_MAX_FAILED_ATTEMPTS,
+            totp_issuer: defaults::TOTP_ISSUER.to_owned(),
+            webauthn: WebauthnSettings::default(),
+            password_hashing: PasswordHashingParams::default(),
+            rate_limit: RateLimitParams::default(),
+            trusted_proxies: Vec::new(),
+            login_lockout: LockoutParams::default(),
+            email_verification: EmailVerificationSettings::default(),
+            password_reset: PasswordResetSettings::default(),
+            password_login_enabled: true,
+            magic_link: MagicLinkSettings::default(),
+            oauth: OAuthSettings::default(),
+            account_deletion: AccountDeletionSettings::default(),
+            email: EmailSettings::default(),
+            database_url: None,
+            redis_url: None,
+            admin_api_token: None,
+        }
+    }
+}
+
+impl Default for JwtSettings {
+    // There is deliberately no default secret, so validation fails until one is set
+    fn default() -> Self {
+        Self {
+            algorithm: JwtAlgorithm::HS256,
+            secret: String::new(),
+            private_key_path: None,
+            retired_keys: Vec::new(),
+            issuer: defaults::JWT_ISSUER.to_owned(),
+            audiences: vec![defaults::JWT_AUDIENCE.to_owned()],
+            leeway_seconds: defaults::JWT_LEEWAY_SECONDS,
+            cookie_name: defaults::JWT_COOKIE_NAME.to_owned(),
+            token_ttl_seconds: defaults::TOKEN_TTL_SECONDS,
+            refresh_cookie_name: defaults::REFRESH_COOKIE_NAME.to_owned(),
+            refresh_token_ttl_seconds: defaults::REFRESH_TOKEN_TTL_SECONDS,
+            key_sync_interval_seconds: defaults::JWT_KEY_SYNC_INTERVAL_SECONDS,
+            key_encryption_key: None,
+        }
+    }
+}
+
+impl Default for WebauthnSettings {
+    fn default() -> Self {
+        Self {
+            rp_id: None,
+            rp_name: defaults::WEBAUTHN_RP_NAME.to_owned(),
+            challenge_ttl_seconds: defaults::WEBAUTHN_CHALLENGE_TTL_SECONDS,
+            fake_credential_key: None,
+        }
+    }
+}
+
+impl Default for EmailVerificationSettings {
+    fn default() -> Self {
+        Self {
+            required: true,
+            token_ttl_seconds: defaults::EMAIL_VERIFICATION_TOKEN_TTL_SECONDS,
+            resend_limit: RateLimitParams {
+                burst: 3,
+                per_minute: 1,
+            },
+        }
+    }
+}
+
+impl Default for PasswordResetSettings {
+    fn default() -> Self {
+        Self {
+            token_ttl_seconds: defaults::PASSWORD_RESET_TOKEN_TTL_SECONDS,
+            request_limit: RateLimitParams {
+                burst: 3,
+                per_minute: 1,
+            },
+        }
+    }
+}
+
+impl Default for MagicLinkSettings {
+    fn default() -> Self {
+        Self {
+            token_ttl_seconds: defaults::MAGIC_LINK_TOKEN_TTL_SECONDS,
+            request_limit: RateLimitParams {
+                burst: 3,
+                per_minute: 1,
+            },
+        }
+    }
+}
+
+impl Default for OAuthSettings {
+    fn default() -> Self {
+        Self {
+            authorization_code_ttl_seconds: defaults::OAUTH_AUTHORIZATION_CODE_TTL_SECONDS,
+        }
+    }
+}
+
+impl Default for AccountDeletionSettings {
+    fn default() -> Self {
+        Self {
+            grace_period_seconds: defaults::ACCOUNT_DELETION_GRACE_PERIOD_SECONDS,
+            purge_interval_seconds: defaults::ACCOUNT_PURGE_INTERVAL_SECONDS,
+        }
+    }
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            sender: defaults::EMAIL_SENDER.to_owned(),
+            outbox_dir: None,
+            log_only: false,
+        }
+    }
+}
+
+impl Settings {
+    // Load settings from the process environment (including a `.env` file)
+    pub fn load() -> Result<Self, SettingsError> {
+        dotenv().ok(); // Load environment variables
+        Self::load_from(|name| std::env::var(name).ok())
+    }
+
+    // Same as `load`, but reads environment variables through `env` so
+    // callers can supply them without touching the process environment
+    pub fn load_from(env: impl Fn(&str) -> Option<String>) -> Result<Self, SettingsError> {
+        let mut settings = match env(env::CONFIG_FILE_ENV_VAR) {
+            Some(path) => Self::from_toml_file(&path)?,
+            None if Path::new(defaults::CONFIG_FILE).exists() => {
+                Self::from_toml_file(defaults::CONFIG_FILE)?
+            }
+            None => Self::default(),
+        };
+
+        settings.apply_env(env)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn from_toml_file(path: &str) -> Result<Self, SettingsError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| SettingsError::ReadFile {
+            path: path.to_owned(),
+            reason: e.to_string(),
+        })?;
+
+        toml::from_str(&contents).map_err(|e| SettingsError::ParseFile {
+            path: path.to_owned(),
+            reason: e.message().to_owned(),
+        })
+    }
+
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), SettingsError> {
+        override_value(&env, env::BIND_ADDRESS_ENV_VAR, &mut self.address)?;
+        override_value(&env, env::PUBLIC_URL_ENV_VAR, &mut self.public_url)?;
+        override_value(&env, env::JWT_ALGORITHM_ENV_VAR, &mut self.jwt.algorithm)?;
+        override_value(&env, env::JWT_SECRET_ENV_VAR, &mut self.jwt.secret)?;
+        override_optional(
+            &env,
+            env::JWT_PRIVATE_KEY_PATH_ENV_VAR,
+            &mut self.jwt.private_key_path,
+        );
+        override_optional(
+            &env,
+            env::JWT_KEY_ENCRYPTION_KEY_ENV_VAR,
+            &mut self.jwt.key_encryption_key,
+        );
+        // Rotating JWT_SECRET without logging everyone out means moving the
+        // old value here until the tokens it signed have expired
+        if let Some(secret) = env(env::JWT_PREVIOUS_SECRET_ENV_VAR).filter(|s| !s.is_empty()) {
+            self.jwt.retired_keys.push(JwtKeySettings {
+                algorithm: JwtAlgorithm::HS256,
+                secret,
+                private_key_path: None,
+            });
+        }
+        override_value(&env, env::JWT_ISSUER_ENV_VAR, &mut self.jwt.issuer)?;
+        override_list(&env, env::JWT_AUDIENCES_ENV_VAR, &mut self.jwt.audiences)?;
+        override_value(
+            &env,
+            env::JWT_LEEWAY_SECONDS_ENV_VAR,
+            &mut self.jwt.leeway_seconds,
+        )?;
+        override_value(
+            &env,
+            env::JWT_COOKIE_NAME_ENV_VAR,
+            &mut self.jwt.cookie_name,
+        )?;
+        override_value(
+            &env,
+            env::TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut self.jwt.token_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::REFRESH_COOKIE_NAME_ENV_VAR,
+            &mut self.jwt.refresh_cookie_name,
+        )?;
+        override_value(
+            &env,
+            env::REFRESH_TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut self.jwt.refresh_token_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::JWT_KEY_SYNC_INTERVAL_SECONDS_ENV_VAR,
+            &mut self.jwt.key_sync_interval_seconds,
+        )?;
+        override_value(
+            &env,
+            env::TWO_FA_CODE_TTL_SECONDS_ENV_VAR,
+            &mut self.two_fa_code_ttl_seconds,
+        )?;
+        override_value(&env, env::TOTP_ISSUER_ENV_VAR, &mut self.totp_issuer)?;
+        override_optional(&env, env::WEBAUTHN_RP_ID_ENV_VAR, &mut self.webauthn.rp_id);
+        override_value(
+            &env,
+            env::WEBAUTHN_RP_NAME_ENV_VAR,
+            &mut self.webauthn.rp_name,
+        )?;
+        override_value(
+            &env,
+            env::WEBAUTHN_CHALLENGE_TTL_SECONDS_ENV_VAR,
+            &mut self.webauthn.challenge_ttl_seconds,
+        )?;
+        override_optional(
+            &env,
+            env::WEBAUTHN_FAKE_CREDENTIAL_KEY_ENV_VAR,
+            &mut self.webauthn.fake_credential_key,
+        );
+        override_value(
+            &env,
+            env::TWO_FA_MAX_FAILED_ATTEMPTS_ENV_VAR,
+            &mut self.two_fa_max_failed_attempts,
+        )?;
+        override_value(
+            &env,
+            env::ARGON2_MEMORY_COST_KIB_ENV_VAR,
+            &mut self.password_hashing.memory_cost_kib,
+        )?;
+        override_value(
+            &env,
+            env::ARGON2_ITERATIONS_ENV_VAR,
+            &mut self.password_hashing.iterations,
+        )?;
+        override_value(
+            &env,
+            env::ARGON2_PARALLELISM_ENV_VAR,
+            &mut self.password_hashing.parallelism,
+        )?;
+        override_value(
+            &env,
+            env::RATE_LIMIT_BURST_ENV_VAR,
+            &mut self.rate_limit.burst,
+        )?;
+        override_value(
+            &env,
+            env::RATE_LIMIT_PER_MINUTE_ENV_VAR,
+            &mut self.rate_limit.per_minute,
+        )?;
+        override_list(
+            &env,
+            env::TRUSTED_PROXIES_ENV_VAR,
+            &mut self.trusted_proxies,
+        )?;
+        override_value(
+            &env,
+            env::LOGIN_MAX_FAILURES_ENV_VAR,
+            &mut self.login_lockout.max_failures,
+        )?;
+        override_value(
+            &env,
+            env::LOGIN_LOCKOUT_SECONDS_ENV_VAR,
+            &mut self.login_lockout.lockout_seconds,
+        )?;
+        override_value(
+            &env,
+            env::EMAIL_VERIFICATION_REQUIRED_ENV_VAR,
+            &mut self.email_verification.required,
+        )?;
+        override_value(
+            &env,
+            env::EMAIL_VERIFICATION_TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut self.email_verification.token_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::PASSWORD_RESET_TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut self.password_reset.token_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::PASSWORD_LOGIN_ENABLED_ENV_VAR,
+            &mut self.password_login_enabled,
+        )?;
+        override_value(
+            &env,
+            env::MAGIC_LINK_TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut self.magic_link.token_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::OAUTH_AUTHORIZATION_CODE_TTL_SECONDS_ENV_VAR,
+            &mut self.oauth.authorization_code_ttl_seconds,
+        )?;
+        override_value(
+            &env,
+            env::ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR,
+            &mut self.account_deletion.grace_period_seconds,
+        )?;
+        override_value(
+            &env,
+            env::ACCOUNT_PURGE_INTERVAL_SECONDS_ENV_VAR,
+            &mut self.account_deletion.purge_interval_seconds,
+        )?;
+        override_value(&env, env::EMAIL_SENDER_ENV_VAR, &mut self.email.sender)?;
+        override_optional(
+            &env,
+            env::EMAIL_OUTBOX_DIR_ENV_VAR,
+            &mut self.email.outbox_dir,
+        );
+        override_value(&env, env::EMAIL_LOG_ONLY_ENV_VAR, &mut self.email.log_only)?;
+        override_optional(&env, env::DATABASE_URL_ENV_VAR, &mut self.database_url);
+        override_optional(&env, env::REDIS_URL_ENV_VAR, &mut self.redis_url);
+        override_optional(
+            &env,
+            env::ADMIN_API_TOKEN_ENV_VAR,
+            &mut self.admin_api_token,
+        );
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let invalid = |setting, reason| Err(SettingsError::InvalidValue { setting, reason });
+
+        if SocketAddr::from_str(&self.address).is_err() {
+            return invalid("address", "must be an IP address and port");
+        }
+
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            return invalid("public_url", "must be an http:// or https:// URL");
+        }
+
+        match self.jwt.algorithm {
+            JwtAlgorithm::HS256 if self.jwt.secret.is_empty() => {
+                return invalid("jwt.secret", "must be set");
+            }
+            JwtAlgorithm::RS256 | JwtAlgorithm::EdDSA if self.jwt.private_key_path.is_none() => {
+                return invalid("jwt.private_key_path", "must be set for RS256 and EdDSA");
+            }
+            _ => {}
+        }
+
+        if self.jwt.retired_keys.iter().any(|key| match key.algorithm {
+            JwtAlgorithm::HS256 => key.secret.is_empty(),
+            JwtAlgorithm::RS256 | JwtAlgorithm::EdDSA => key.private_key_path.is_none(),
+        }) {
+            return invalid(
+                "jwt.retired_keys",
+                "must each have a secret (HS256) or a private_key_path (RS256 and EdDSA)",
+            );
+        }
+
+        if self.jwt.issuer.is_empty() {
+            return invalid("jwt.issuer", "must be set");
+        }
+
+        if self.jwt.audiences.is_empty() || self.jwt.audiences.iter().any(|aud| aud.is_empty()) {
+            return invalid("jwt.audiences", "must list at least one non-empty audience");
+        }
+
+        if !is_valid_cookie_name(&self.jwt.cookie_name) {
+            return invalid("jwt.cookie_name", "must be a valid cookie name");
+        }
+
+        if self.jwt.token_ttl_seconds == 0 {
+            return invalid("jwt.token_ttl_seconds", "must be greater than zero");
+        }
+
+        // A leeway as long as the token itself would make expiry meaningless
+        if self.jwt.leeway_seconds >= self.jwt.token_ttl_seconds {
+            return invalid(
+                "jwt.leeway_seconds",
+                "must be less than jwt.token_ttl_seconds",
+            );
+        }
+
+        if !is_valid_cookie_name(&self.jwt.refresh_cookie_name)
+            || self.jwt.refresh_cookie_name == self.jwt.cookie_name
+        {
+            return invalid(
+                "jwt.refresh_cookie_name",
+                "must be a valid cookie name different from jwt.cookie_name",
+            );
+        }
+
+        // A refresh token that expires before the access token would be useless
+        if self.jwt.refresh_token_ttl_seconds <= self.jwt.token_ttl_seconds {
+            return invalid(
+                "jwt.refresh_token_ttl_seconds",
+                "must be greater than jwt.token_ttl_seconds",
+            );
+        }
+
+        if self.jwt.key_sync_interval_seconds == 0 {
+            return invalid("jwt.key_sync_interval_seconds", "must be greater than zero");
+        }
+
+        if self.two_fa_code_ttl_seconds == 0 {
+            return invalid("two_fa_code_ttl_seconds", "must be greater than zero");
+        }
+
+        if self.two_fa_max_failed_attempts == 0 {
+            return invalid("two_fa_max_failed_attempts", "must be greater than zero");
+        }
+
+        // Authenticator apps split the label on ':'
+        if self.totp_issuer.is_empty() || self.totp_issuer.contains(':') {
+            return invalid("totp_issuer", "must be set and must not contain ':'");
+        }
+
+        if build_webauthn(self).is_err() {
+            return invalid(
+                "webauthn.rp_id",
+                "must be the domain of public_url or a parent of it",
+            );
+        }
+
+        if self.webauthn.rp_name.is_empty() {
+            return invalid("webauthn.rp_name", "must be set");
+        }
+
+        if self.webauthn.challenge_ttl_seconds == 0 {
+            return invalid(
+                "webauthn.challenge_ttl_seconds",
+                "must be greater than zero",
+            );
+        }
+
+        if let Some(key) = &self.webauthn.fake_credential_key {
+            if key.len() < 32 {
+                return invalid(
+                    "webauthn.fake_credential_key",
+                    "must be at least 32 characters long",
+                );
+            }
+        }
+
+        let params = &self.password_hashing;
+        if argon2::Params::new(
+            params.memory_cost_kib,
+            params.iterations,
+            params.parallelism,
+            None,
+        )
+        .is_err()
+        {
+            return invalid("password_hashing", "are not valid Argon2 parameters");
+        }
+
+        if self.rate_limit.burst == 0 || self.rate_limit.per_minute == 0 {
+            return invalid("rate_limit", "must allow at least one request");
+        }
+
+        if self.login_lockout.max_failures == 0 || self.login_lockout.lockout_seconds == 0 {
+            return invalid(
+                "login_lockout",
+                "must allow at least one failure and last a second",
+            );
+        }
+
+        if self.email_verification.token_ttl_seconds == 0 {
+            return invalid(
+                "email_verification.token_ttl_seconds",
+                "must be greater than zero",
+            );
+        }
+
+        let resend_limit = &self.email_verification.resend_limit;
+        if resend_limit.burst == 0 || resend_limit.per_minute == 0 {
+            return invalid(
+                "email_verification.resend_limit",
+                "must allow at least one email",
+            );
+        }
+
+        if self.password_reset.token_ttl_seconds == 0 {
+            return invalid(
+                "password_reset.token_ttl_seconds",
+                "must be greater than zero",
+            );
+        }
+
+        let request_limit = &self.password_reset.request_limit;
+        if request_limit.burst == 0 || request_limit.per_minute == 0 {
+            return invalid(
+                "password_reset.request_limit",
+                "must allow at least one email",
+            );
+        }
+
+        if self.magic_link.token_ttl_seconds == 0 {
+            return invalid("magic_link.token_ttl_seconds", "must be greater than zero");
+        }
+
+        let request_limit = &self.magic_link.request_limit;
+        if request_limit.burst == 0 || request_limit.per_minute == 0 {
+            return invalid("magic_link.request_limit", "must allow at least one email");
+        }
+
+        if self.oauth.authorization_code_ttl_seconds == 0 {
+            return invalid(
+                "oauth.authorization_code_ttl_seconds",
+                "must be greater than zero",
+            );
+        }
+
+        if self.account_deletion.purge_interval_seconds == 0 {
+            return invalid(
+                "account_deletion.purge_interval_seconds",
+                "must be greater than zero",
+            );
+        }
+
+        if Email::parse(self.email.sender.clone()).is_err() {
+            return invalid("email.sender", "must be a valid email address");
+        }
+
+        if self.email.log_only && self.email.outbox_dir.is_some() {
+            return invalid(
+                "email.log_only",
+                "must not be set together with email.outbox_dir",
+            );
+        }
+
+        if let Some(url) = &self.database_url {
+            if !["postgres://", "postgresql://", "sqlite:"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+            {
+                return invalid("database_url", "must be a postgres:// or sqlite: URL");
+            }
+        }
+
+        if let Some(url) = &self.redis_url {
+            if !url.starts_with("redis://") && !url.starts_with("rediss://") {
+                return invalid("redis_url", "must be a redis:// or rediss:// URL");
+            }
+        }
+
+        if let Some(token) = &self.admin_api_token {
+            if token.len() < 32 {
+                return invalid("admin_api_token", "must be at least 32 characters long");
+            }
+        }
+
+        match &self.jwt.key_encryption_key {
+            Some(key) if KeyEncryptionKey::parse(key).is_none() => {
+                return invalid(
+                    "jwt.key_encryption_key",
+                    "must be a base64url encoded 32 byte key",
+                );
+            }
+            // Signing keys are rotated through the admin routes, and only
+            // stored encrypted
+            None if self.admin_api_token.is_some() => {
+                return invalid("jwt.key_encryption_key", "must be set with admin_api_token");
+            }
+            // TOTP secrets are only stored encrypted
+            None if self.database_url.is_some() => {
+                return invalid("jwt.key_encryption_key", "must be set with database_url");
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn is_valid_cookie_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+fn override_value<T: FromStr>(
+    env: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    value: &mut T,
+) -> Result<(), SettingsError> {
+    if let Some(raw) = env(name) {
+        *value = raw
+            .parse()
+            .map_err(|_| SettingsError::InvalidEnvVar { name })?;
+    }
+    Ok(())
+}
+
+// An empty variable clears the setting
+fn override_optional(env: impl Fn(&str) -> Option<String>, name: &str, value: &mut Option<String>) {
+    if let Some(raw) = env(name) {
+        *value = Some(raw).filter(|raw| !raw.is_empty());
+    }
+}
+
+// A comma separated list, e.g. `app-service,billing-service`
+fn override_list<T: FromStr>(
+    env: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    value: &mut Vec<T>,
+) -> Result<(), SettingsError> {
+    if let Some(raw) = env(name) {
+        *value = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| SettingsError::InvalidEnvVar { name })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn valid_settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.jwt.secret = "secret".to_owned();
+        settings
+    }
+
+    #[test]
+    fn test_defaults_require_jwt_secret() {
+        let result = Settings::load_from(env_from(&[]));
+        assert_eq!(
+            result,
+            Err(SettingsError::InvalidValue {
+                setting: "jwt.secret",
+                reason: "must be set"
+            })
+        );
+    }
+
+    #[test]
+    fn test_env_overrides_defaults() {
+        let settings = Settings::load_from(env_from(&[
+            ("JWT_SECRET", "secret"),
+            ("BIND_ADDRESS", "127.0.0.1:4000"),
+            ("TOKEN_TTL_SECONDS", "60"),
+            ("JWT_COOKIE_NAME", "session"),
+            ("JWT_PREVIOUS_SECRET", "old-secret"),
+            (
+                "JWT_KEY_ENCRYPTION_KEY",
+                "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8",
+            ),
+            ("REFRESH_TOKEN_TTL_SECONDS", "3600"),
+            ("DATABASE_URL", "sqlite::memory:"),
+            ("REDIS_URL", ""),
+            ("JWT_ISSUER", "https://auth.example.com"),
+            ("JWT_AUDIENCES", "app-service, billing-service,"),
+            ("JWT_LEEWAY_SECONDS", "5"),
+            ("TWO_FA_MAX_FAILED_ATTEMPTS", "3"),
+            ("JWT_KEY_SYNC_INTERVAL_SECONDS", "10"),
+            ("RATE_LIMIT_BURST", "20"),
+            ("LOGIN_LOCKOUT_SECONDS", "60"),
+            ("PUBLIC_URL", "https://auth.example.com"),
+            ("EMAIL_VERIFICATION_REQUIRED", "false"),
+            ("PASSWORD_RESET_TOKEN_TTL_SECONDS", "300"),
+            ("ACCOUNT_DELETION_GRACE_PERIOD_SECONDS", "0"),
+            ("TOTP_ISSUER", "Example"),
+            ("WEBAUTHN_RP_ID", "example.com"),
+            (
+                "WEBAUTHN_FAKE_CREDENTIAL_KEY",
+                "fake-credential-key-0123456789abcdef",
+            ),
+            ("PASSWORD_LOGIN_ENABLED", "false"),
+            ("MAGIC_LINK_TOKEN_TTL_SECONDS", "120"),
+            ("OAUTH_AUTHORIZATION_CODE_TTL_SECONDS", "30"),
+            ("EMAIL_LOG_ONLY", "true"),
+            ("TRUSTED_PROXIES", "10.0.0.0/8, 192.168.1.1"),
+        ]))
+        .unwrap();
+
+        assert_eq!(settings.address, "127.0.0.1:4000");
+        assert_eq!(settings.jwt.secret, "secret");
+        assert_eq!(settings.jwt.token_ttl_seconds, 60);
+        assert_eq!(settings.jwt.cookie_name, "session");
+        assert_eq!(settings.jwt.retired_keys[0].secret, "old-secret");
+        assert_eq!(
+            settings.jwt.key_encryption_key.as_deref(),
+            Some("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8")
+        );
+        assert_eq!(settings.jwt.refresh_token_ttl_seconds, 3600);
+        assert_eq!(settings.jwt.issuer, "https://auth.example.com");
+        assert_eq!(
+            settings.jwt.audiences,
+            vec!["app-service", "billing-service"]
+        );
+        assert_eq!(settings.jwt.leeway_seconds, 5);
+        assert_eq!(settings.jwt.key_sync_interval_seconds, 10);
+        assert_eq!(settings.rate_limit.burst, 20);
+        assert_eq!(settings.login_lockout.lockout_seconds, 60);
+        assert_eq!(settings.public_url, "https://auth.example.com");
+        assert!(!settings.email_verification.required);
+        assert_eq!(settings.password_reset.token_ttl_seconds, 300);
+        assert_eq!(settings.account_deletion.grace_period_seconds, 0);
+        assert_eq!(settings.totp_issuer, "Example");
+        assert_eq!(settings.webauthn.rp_id.as_deref(), Some("example.com"));
+        assert_eq!(settings.webauthn.rp_name, defaults::WEBAUTHN_RP_NAME);
+        assert_eq!(
+            settings.webauthn.fake_credential_key.as_deref(),
+            Some("fake-credential-key-0123456789abcdef")
+        );
+        assert!(!settings.password_login_enabled);
+        assert_eq!(settings.magic_link.token_ttl_seconds, 120);
+        assert_eq!(settings.oauth.authorization_code_ttl_seconds, 30);
+        assert!(settings.email.log_only);
+        assert_eq!(
+            settings.email_verification.token_ttl_seconds,
+            defaults::EMAIL_VERIFICATION_TOKEN_TTL_SECONDS
+        );
+        assert_eq!(
+            settings.trusted_proxies,
+            vec![
+                "10.0.0.0/8".parse().unwrap(),
+                "192.168.1.1".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            settings.login_lockout.max_failures,
+            LockoutParams::default().max_failures
+        );
+        assert_eq!(
+            settings.jwt.refresh_cookie_name,
+            defaults::REFRESH_COOKIE_NAME
+        );
+        assert_eq!(settings.database_url.as_deref(), Some("sqlite::memory:"));
+        assert_eq!(settings.redis_url, None);
+        assert_eq!(settings.two_fa_max_failed_attempts, 3);
+        assert_eq!(
+            settings.two_fa_code_ttl_seconds,
+            defaults::TWO_FA_CODE_TTL_SECONDS
+        );
+    }
+
+    #[test]
+    fn test_asymmetric_algorithm_does_not_need_secret() {
+        let settings = Settings::load_from(env_from(&[
+            ("JWT_ALGORITHM", "EdDSA"),
+            ("JWT_PRIVATE_KEY_PATH", "keys/private.pem"),
+        ]))
+        .unwrap();
+
+        assert_eq!(settings.jwt.algorithm, JwtAlgorithm::EdDSA);
+        assert_eq!(
+            settings.jwt.private_key_path.as_deref(),
+            Some("keys/private.pem")
+        );
+
+        let result = Settings::load_from(env_from(&[("JWT_ALGORITHM", "ES256")]));
+        assert_eq!(
+            result,
+            Err(SettingsError::InvalidEnvVar {
+                name: "JWT_ALGORITHM"
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_env_var_is_rejected() {
+        let result = Settings::load_from(env_from(&[
+            ("JWT_SECRET", "secret"),
+            ("TOKEN_TTL_SECONDS", "ten minutes"),
+        ]));
+        assert_eq!(
+            result,
+            Err(SettingsError::InvalidEnvVar {
+                name: "TOKEN_TTL_SECONDS"
+            })
+        );
+
+        // Trusted proxies are parsed up front, so a bad entry stops startup
+        let result = Settings::load_from(env_from(&[
+            ("JWT_SECRET", "secret"),
+            ("TRUSTED_PROXIES", "10.0.0.0/8, proxy.local"),
+        ]));
+        assert_eq!(
+            result,
+            Err(SettingsError::InvalidEnvVar {
+                name: "TRUSTED_PROXIES"
+            })
+        );
+    }
+
+    #[test]
+    fn test_toml_file_is_layered_under_env() {
+        let path = std::env::temp_dir().join(format!("settings-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+            address = "127.0.0.1:5000"
+
+            [jwt]
+            algorithm = "RS256"
+            secret = "file-secret"
+            private_key_path = "keys/private.pem"
+            token_ttl_seconds = 120
+
+            [[jwt.retired_keys]]
+            algorithm = "EdDSA"
+            private_key_path = "keys/retired.pem"
+
+            [password_hashing]
+            iterations = 3
+            "#,
+        )
+        .unwrap();
+
+        let settings = Settings::load_from(env_from(&[
+            ("AUTH_SERVICE_CONFIG", path.to_str().unwrap()),
+            ("JWT_SECRET", "env-secret"),
+        ]))
+        .unwrap();
+
+        assert_eq!(settings.address, "127.0.0.1:5000");
+        assert_eq!(settings.jwt.algorithm, JwtAlgorithm::RS256);
+        assert_eq!(settings.jwt.secret, "env-secret");
+        assert_eq!(settings.jwt.token_ttl_seconds, 120);
+        assert_eq!(
+            settings.jwt.retired_keys,
+            vec![JwtKeySettings {
+                algorithm: JwtAlgorithm::EdDSA,
+                secret: String::new(),
+                private_key_path: Some("keys/retired.pem".to_owned()),
+            }]
+        );
+        assert_eq!(settings.jwt.cookie_name, defaults::JWT_COOKIE_NAME);
+        assert_eq!(settings.password_hashing.iterations, 3);
+        assert_eq!(
+            settings.password_hashing.memory_cost_kib,
+            PasswordHashingParams::default().memory_cost_kib
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_or_malformed_toml_file_is_rejected() {
+        let result =
+            Settings::load_from(env_from(&[("AUTH_SERVICE_CONFIG", "/does/not/exist.toml")]));
+        assert!(matches!(result, Err(SettingsError::ReadFile { .. })));
+
+        let path = std::env::temp_dir().join(format!("settings-{}.toml", uuid::Uuid::new_v4()));
+        for contents in [
+            "unknown_setting = true",
+            r#"trusted_proxies = ["proxy.local"]"#,
+        ] {
+            std::fs::write(&path, contents).unwrap();
+            let result =
+                Settings::load_from(env_from(&[("AUTH_SERVICE_CONFIG", path.to_str().unwrap())]));
+            assert!(
+                matches!(result, Err(SettingsError::ParseFile { .. })),
+                "Failed for: {contents}"
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(valid_settings().validate(), Ok(()));
+
+        type Modify = fn(&mut Settings);
+
+        let cases: [(&str, Modify); 41] = [
+            ("address", |s| s.address = "localhost".to_owned()),
+            ("public_url", |s| s.public_url = "localhost:3000".to_owned()),
+            ("jwt.private_key_path", |s| {
+                s.jwt.algorithm = JwtAlgorithm::RS256
+            }),
+            ("jwt.retired_keys", |s| {
+                s.jwt.retired_keys.push(JwtKeySettings {
+                    algorithm: JwtAlgorithm::EdDSA,
+                    secret: "secret".to_owned(),
+                    private_key_path: None,
+                })
+            }),
+            ("jwt.issuer", |s| s.jwt.issuer = String::new()),
+            ("jwt.audiences", |s| s.jwt.audiences = Vec::new()),
+            ("jwt.audiences", |s| s.jwt.audiences.push(String::new())),
+            ("jwt.cookie_name", |s| s.jwt.cookie_name = "a b".to_owned()),
+            ("jwt.token_ttl_seconds", |s| s.jwt.token_ttl_seconds = 0),
+            ("jwt.leeway_seconds", |s| {
+                s.jwt.leeway_seconds = s.jwt.token_ttl_seconds
+            }),
+            ("jwt.refresh_cookie_name", |s| {
+                s.jwt.refresh_cookie_name = String::new()
+            }),
+            ("jwt.refresh_cookie_name", |s| {
+                s.jwt.refresh_cookie_name = s.jwt.cookie_name.clone()
+            }),
+            ("jwt.refresh_token_ttl_seconds", |s| {
+                s.jwt.refresh_token_ttl_seconds = s.jwt.token_ttl_seconds
+            }),
+            ("two_fa_code_ttl_seconds", |s| s.two_fa_code_ttl_seconds = 0),
+            ("two_fa_max_failed_attempts", |s| {
+                s.two_fa_max_failed_attempts = 0
+            }),
+            ("totp_issuer", |s| {
+                s.totp_issuer = "Example: Auth".to_owned()
+            }),
+            ("webauthn.rp_id", |s| {
+                s.webauthn.rp_id = Some("example.com".to_owned())
+            }),
+            ("webauthn.rp_id", |s| {
+                s.public_url = "http://127.0.0.1:3000".to_owned()
+            }),
+            ("webauthn.rp_name", |s| s.webauthn.rp_name = String::new()),
+            ("webauthn.challenge_ttl_seconds", |s| {
+                s.webauthn.challenge_ttl_seconds = 0
+            }),
+            ("webauthn.fake_credential_key", |s| {
+                s.webauthn.fake_credential_key = Some("short".to_owned())
+            }),
+            ("password_hashing", |s| s.password_hashing.iterations = 0),
+            ("rate_limit", |s| s.rate_limit.per_minute = 0),
+            ("login_lockout", |s| s.login_lockout.max_failures = 0),
+            ("email_verification.token_ttl_seconds", |s| {
+                s.email_verification.token_ttl_seconds = 0
+            }),
+            ("email_verification.resend_limit", |s| {
+                s.email_verification.resend_limit.burst = 0
+            }),
+            ("password_reset.token_ttl_seconds", |s| {
+                s.password_reset.token_ttl_seconds = 0
+            }),
+            ("password_reset.request_limit", |s| {
+                s.password_reset.request_limit.per_minute = 0
+            }),
+            ("magic_link.token_ttl_seconds", |s| {
+                s.magic_link.token_ttl_seconds = 0
+            }),
+            ("magic_link.request_limit", |s| {
+                s.magic_link.request_limit.burst = 0
+            }),
+            ("oauth.authorization_code_ttl_seconds", |s| {
+                s.oauth.authorization_code_ttl_seconds = 0
+            }),
+            ("account_deletion.purge_interval_seconds", |s| {
+                s.account_deletion.purge_interval_seconds = 0
+            }),
+            ("jwt.key_sync_interval_seconds", |s| {
+                s.jwt.key_sync_interval_seconds = 0
+            }),
+            ("email.sender", |s| s.email.sender = "invalid".to_owned()),
+            ("email.log_only", |s| {
+                s.email.log_only = true;
+                s.email.outbox_dir = Some("outbox".to_owned());
+            }),
+            ("database_url", |s| {
+                s.database_url = Some("mysql://db".to_owned())
+            }),
+            ("redis_url", |s| {
+                s.redis_url = Some("http://redis".to_owned())
+            }),
+            ("admin_api_token", |s| {
+                s.admin_api_token = Some("short".to_owned())
+            }),
+            ("jwt.key_encryption_key", |s| {
+                s.jwt.key_encryption_key = Some("c2hvcnQ".to_owned())
+            }),
+            ("jwt.key_encryption_key", |s| {
+                s.admin_api_token = Some("a".repeat(32))
+            }),
+            ("jwt.key_encryption_key", |s| {
+                s.database_url = Some("sqlite::memory:".to_owned())
+            }),
+        ];
+
+        for (setting, modify) in cases {
+            let mut settings = valid_settings();
+            modify(&mut settings);
+            assert!(
+                matches!(
+                    settings.validate(),
+                    Err(SettingsError::InvalidValue { setting: s, .. }) if s == setting
+                ),
+                "Failed for setting: {}",
+                setting
+            );
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::{
+    app_state::{OAuthClientStoreType, PasskeyStoreType, UserStoreType},
+    settings::AccountDeletionSettings,
+};
+
+// Runs forever, purging soft deleted accounts once their grace period is over.
+// Every replica runs it; purging is idempotent, so they do not get in each
+// other's way.
+pub async fn purge_deleted_accounts(
+    user_store: UserStoreType,
+    passkey_store: PasskeyStoreType,
+    oauth_client_store: OAuthClientStoreType,
+    settings: AccountDeletionSettings,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.purge_interval_seconds));
+
+    loop {
+        interval.tick().await;
+
+        let Ok(grace_period_seconds) = i64::try_from(settings.grace_period_seconds) else {
+            eprintln!("Account deletion grace period is too long, not purging");
+            return;
+        };
+        let deleted_before = Utc::now().timestamp() - grace_period_seconds;
+
+        match purge_accounts(
+            &user_store,
+            &passkey_store,
+            &oauth_client_store,
+            deleted_before,
+        )
+        .await
+        {
+            Ok(0) => {}
+            Ok(count) => println!("Purged {} deleted accounts", count),
+            Err(e) => eprintln!("Failed to purge deleted accounts: {}", e),
+        }
+    }
+}
+
+// Purge users deleted at or before `deleted_before`, along with their
+// passkeys and consents, returning how many were purged. The passkeys and
+// consents go first, so if that fails the users are still there for the next
+// run to try again, instead of leaving their data behind.
+async fn purge_accounts(
+    user_store: &UserStoreType,
+    passkey_store: &PasskeyStoreType,
+    oauth_client_store: &OAuthClientStoreType,
+    deleted_before: i64,
+) -> Result<usize, String> {
+    let emails = user_store
+        .read()
+        .await
+        .deleted_users(deleted_before)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    for email in &emails {
+        passkey_store
+            .write()
+            .await
+            .delete_passkeys(email)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        oauth_client_store
+            .write()
+            .await
+            .delete_consents(email)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+    }
+
+    let purged = user_store
+        .write()
+        .await
+        .purge_deleted_users(deleted_before)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    Ok(purged.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use webauthn_rs::prelude::{AuthenticationResult, Passkey};
+
+    use super::*;
+    use crate::{
+        domain::{
+            ClientId, Email, OAuthClient, PasskeyStore, PasskeyStoreError, Password, PasswordHash,
+            PasswordHashingParams, RedirectUri, User, UserStore, UserStoreError,
+        },
+        services::{HashmapOAuthClientStore, HashmapPasskeyStore, HashmapUserStore},
+    };
+
+    // A passkey store whose database is down
+    struct FailingPasskeyStore;
+
+    #[async_trait::async_trait]
+    impl PasskeyStore for FailingPasskeyStore {
+        async fn add_passkey(
+            &mut self,
+            _email: &Email,
+            _passkey: Passkey,
+        ) -> Result<(), PasskeyStoreError> {
+            Err(PasskeyStoreError::UnexpectedError)
+        }
+
+        async fn get_passkeys(&self, _email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+            Err(PasskeyStoreError::UnexpectedError)
+        }
+
+        async fn update_passkey(
+            &mut self,
+            _email: &Email,
+            _result: &AuthenticationResult,
+        ) -> Result<(), PasskeyStoreError> {
+            Err(PasskeyStoreError::UnexpectedError)
+        }
+
+        async fn delete_passkeys(&mut self, _email: &Email) -> Result<(), PasskeyStoreError> {
+            Err(PasskeyStoreError::UnexpectedError)
+        }
+    }
+
+    async fn deleted_user_store(email: &Email) -> UserStoreType {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let params = PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let password_hash = PasswordHash::hash(&password, params).await.unwrap();
+
+        let mut users = HashmapUserStore::default();
+        users
+            .add_user(User::new(email.clone(), password_hash, false))
+            .await
+            .unwrap();
+        users.soft_delete_user(email, 100).await.unwrap();
+        Arc::new(RwLock::new(users))
+    }
+
+    #[tokio::test]
+    async fn test_purge_accounts_removes_consents() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user_store = deleted_user_store(&email).await;
+        let passkey_store: PasskeyStoreType = Arc::new(RwLock::new(HashmapPasskeyStore::default()));
+        let oauth_client_store: OAuthClientStoreType =
+            Arc::new(RwLock::new(HashmapOAuthClientStore::default()));
+
+        let client = OAuthClient {
+            client_id: ClientId::default(),
+            name: "Test".to_owned(),
+            redirect_uris: vec![
+                RedirectUri::parse("http://localhost:8000/callback".to_owned()).unwrap(),
+            ],
+            secret_hash: None,
+        };
+
+        let mut clients = oauth_client_store.write().await;
+        clients.add_client(client.clone()).await.unwrap();
+        clients
+            .add_consent(&client.client_id, &email)
+            .await
+            .unwrap();
+        drop(clients);
+
+        let result = purge_accounts(&user_store, &passkey_store, &oauth_client_store, 99).await;
+        assert_eq!(result, Ok(0));
+        let result = purge_accounts(&user_store, &passkey_store, &oauth_client_store, 100).await;
+        assert_eq!(result, Ok(1));
+
+        // A new user with the same email starts without the old consent
+        let has_consent = oauth_client_store
+            .read()
+            .await
+            .has_consent(&client.client_id, &email)
+            .await;
+        assert_eq!(has_consent, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_users_are_kept_if_their_data_can_not_be_removed() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user_store = deleted_user_store(&email).await;
+        let passkey_store: PasskeyStoreType = Arc::new(RwLock::new(FailingPasskeyStore));
+        let oauth_client_store: OAuthClientStoreType =
+            Arc::new(RwLock::new(HashmapOAuthClientStore::default()));
+
+        let result = purge_accounts(&user_store, &passkey_store, &oauth_client_store, 100).await;
+        assert!(result.is_err());
+
+        // Still there for the next run to try again
+        let result = user_store.read().await.deleted_users(100).await;
+        assert_eq!(result, Ok(vec![email.clone()]));
+
+        let passkey_store: PasskeyStoreType = Arc::new(RwLock::new(HashmapPasskeyStore::default()));
+        let result = purge_accounts(&user_store, &passkey_store, &oauth_client_store, 100).await;
+        assert_eq!(result, Ok(1));
+        let result = user_store.read().await.deleted_users(100).await;
+        assert_eq!(result, Ok(Vec::new()));
+        let result = user_store.write().await.get_user(&email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+}
@@ -1,21 +1,38 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use std::collections::BTreeSet;
+
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::errors::ErrorKind;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::domain::email::Email;
-
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use crate::{
+    app_state::{
+        AppState, BannedTokenStoreType, JwtKeyringType, RefreshTokenStoreType, UserStoreType,
+    },
+    domain::{email::Email, AuthAPIError, ClientId, RefreshToken, RefreshTokenRecord, Role, User},
+    settings::JwtSettings,
+    utils::jwt_keys::JwtKeyring,
+};
 
-// Create cookie with a new JWT auth token
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(email)?;
-    Ok(create_auth_cookie(token))
+// Create cookie with a new JWT auth token for the session whose refresh
+// tokens are in `family_id`
+pub async fn generate_auth_cookie(
+    user: &User,
+    family_id: &str,
+    settings: &JwtSettings,
+    jwt_keyring: JwtKeyringType,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(user, family_id, settings, &*jwt_keyring.read().await)?;
+    Ok(create_auth_cookie(token, settings))
 }
 
-// Create cookie and set the value to the passed-in token string 
-fn create_auth_cookie(token: String) -> Cookie<'static> {
-    let cookie = Cookie::build((JWT_COOKIE_NAME, token))
+// Create cookie and set the value to the passed-in token string
+fn create_auth_cookie(token: String, settings: &JwtSettings) -> Cookie<'static> {
+    let cookie = Cookie::build((settings.cookie_name.clone(), token))
         .path("/") // apply cookie to all URLs on the server
         .http_only(true) // prevent JavaScript from accessing the cookie
         .same_site(SameSite::Lax) // send cookie with "same-site" requests, and with "cross-site" top-level navigations.
@@ -24,72 +41,289 @@ fn create_auth_cookie(token: String) -> Cookie<'static> {
     cookie
 }
 
+// ID of the refresh token family started by a fresh login
+pub fn new_refresh_family_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+// Create cookie with a new refresh token and save it in the store. Rotated
+// tokens keep the family of the token they replace.
+pub async fn generate_refresh_cookie(
+    email: &Email,
+    family_id: &str,
+    refresh_token_store: RefreshTokenStoreType,
+    settings: &JwtSettings,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let ttl_seconds: i64 = settings
+        .refresh_token_ttl_seconds
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+    let expires_at = Utc::now()
+        .timestamp()
+        .checked_add(ttl_seconds)
+        .ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let token = RefreshToken::default();
+    let record = RefreshTokenRecord {
+        email: email.clone(),
+        family_id: family_id.to_owned(),
+        expires_at,
+    };
+
+    refresh_token_store
+        .write()
+        .await
+        .add_token(token.clone(), record)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(create_refresh_cookie(token, settings))
+}
+
+// The refresh cookie is only ever sent to the /refresh route
+fn create_refresh_cookie(token: RefreshToken, settings: &JwtSettings) -> Cookie<'static> {
+    let max_age = time::Duration::seconds(
+        settings
+            .refresh_token_ttl_seconds
+            .try_into()
+            .unwrap_or(i64::MAX),
+    );
+
+    Cookie::build((
+        settings.refresh_cookie_name.clone(),
+        token.as_ref().to_owned(),
+    ))
+    .path("/refresh")
+    .http_only(true)
+    .same_site(SameSite::Strict)
+    .max_age(max_age)
+    .build()
+}
+
 #[derive(Debug)]
 pub enum GenerateTokenError {
     TokenError(jsonwebtoken::errors::Error),
     UnexpectedError,
 }
 
-// This value determines how long the JWT auth token is valid for
-pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
-
 // Create JWT auth token
-fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .ok_or(GenerateTokenError::UnexpectedError)?;
+fn generate_auth_token(
+    user: &User,
+    family_id: &str,
+    settings: &JwtSettings,
+    keyring: &JwtKeyring,
+) -> Result<String, GenerateTokenError> {
+    generate_token(
+        user,
+        settings.audiences.clone(),
+        Some(family_id.to_owned()),
+        settings,
+        keyring,
+    )
+}
+
+// Create an access token for an OAuth client. Its only audience is the
+// client, so it is not accepted as a session by this service or by other
+// clients.
+pub fn generate_access_token(
+    user: &User,
+    client_id: &ClientId,
+    settings: &JwtSettings,
+    keyring: &JwtKeyring,
+) -> Result<String, GenerateTokenError> {
+    generate_token(
+        user,
+        vec![client_id.as_ref().to_owned()],
+        None,
+        settings,
+        keyring,
+    )
+}
+
+fn generate_token(
+    user: &User,
+    aud: Vec<String>,
+    sid: Option<String>,
+    settings: &JwtSettings,
+    keyring: &JwtKeyring,
+) -> Result<String, GenerateTokenError> {
+    let ttl_seconds = settings
+        .token_ttl_seconds
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+    let delta =
+        chrono::Duration::try_seconds(ttl_seconds).ok_or(GenerateTokenError::UnexpectedError)?;
 
-    // Create JWT expiration time
-    let exp = Utc::now()
+    // Create JWT issue and expiration times
+    let now = Utc::now();
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
 
-    // Cast exp to a usize, which is what Claims expects
+    // Cast timestamps to a usize, which is what Claims expects
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
     let exp: usize = exp
         .try_into()
         .map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    let sub = email.as_ref().to_owned();
+    let sub = user.email.as_ref().to_owned();
 
-    let claims = Claims { sub, exp };
+    let claims = Claims {
+        sub,
+        exp,
+        iat,
+        nbf: iat,
+        jti: Uuid::new_v4().to_string(),
+        iss: settings.issuer.clone(),
+        aud,
+        roles: user.roles.clone(),
+        ver: user.session_version,
+        sid,
+    };
 
-    create_token(&claims).map_err(GenerateTokenError::TokenError)
+    create_token(&claims, keyring).map_err(GenerateTokenError::TokenError)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
-pub async fn validate_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
+// Check if JWT auth token is valid by verifying its signature and claims,
+// and making sure it has not been banned. With an `audience`, the token must
+// have been minted for that service rather than any configured audience.
+// Tokens of deleted users, and of sessions ended by a password change or
+// reset, are rejected before they expire.
+pub async fn validate_token(
+    token: &str,
+    audience: Option<&str>,
+    banned_token_store: BannedTokenStoreType,
+    user_store: UserStoreType,
+    jwt_keyring: JwtKeyringType,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let claims = {
+        let keyring = jwt_keyring.read().await;
+        match audience {
+            Some(audience) => keyring.decode_for_audience::<Claims>(token, audience)?,
+            None => keyring.decode::<Claims>(token)?,
+        }
+    };
+
+    match banned_token_store
+        .read()
+        .await
+        .contains_token(&claims.jti)
+        .await
+    {
+        Ok(false) => {}
+        _ => return Err(ErrorKind::InvalidToken.into()),
+    }
+
+    let email = Email::parse(claims.sub.clone()).map_err(|_| ErrorKind::InvalidSubject)?;
+    match user_store.read().await.get_user(&email).await {
+        Ok(user) if user.session_version == claims.ver => Ok(claims),
+        _ => Err(ErrorKind::InvalidToken.into()),
+    }
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
-fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+// The email of the user logged in with the request's auth cookie
+pub async fn authenticated_email(state: &AppState, jar: &CookieJar) -> Result<Email, AuthAPIError> {
+    let token = jar
+        .get(&state.settings.jwt.cookie_name)
+        .ok_or(AuthAPIError::MissingToken)?
+        .value()
+        .to_owned();
+
+    let claims = validate_token(
+        &token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.jwt_keyring.clone(),
     )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Email::parse(claims.sub).map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+// Create JWT auth token by signing the claims with the configured key
+fn create_token(
+    claims: &Claims,
+    keyring: &JwtKeyring,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    keyring.encode(claims)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    // Unique ID of the token, used to ban it on logout
+    pub jti: String,
+    pub iss: String,
+    pub aud: Vec<String>,
+    pub roles: BTreeSet<Role>,
+    // Session version of the user when the token was issued. Tokens from
+    // before the claim existed count as the first version.
+    #[serde(default)]
+    pub ver: i64,
+    // Refresh token family of the session, so logging out ends only this
+    // session. Access tokens for OAuth clients have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    use crate::{
+        domain::{BannedTokenStore, PasswordHash, RefreshTokenStore, UserStore},
+        services::{HashmapRefreshTokenStore, HashmapUserStore, HashsetBannedTokenStore},
+    };
+
+    fn settings() -> JwtSettings {
+        JwtSettings {
+            secret: "secret".to_owned(),
+            ..JwtSettings::default()
+        }
+    }
+
+    fn keyring() -> JwtKeyring {
+        JwtKeyring::from_settings(&settings()).unwrap()
+    }
+
+    fn user() -> User {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password_hash = PasswordHash::parse(
+            "$argon2id$v=19$m=8,t=1,p=1$c2FsdHNhbHQ$ZkfpGBBgO2T9F8Eg3lw+hg".to_owned(),
+        )
+        .unwrap();
+        User::new(email, password_hash, false)
+    }
+
+    // A store with `user()` in it, so tokens issued to them are accepted
+    async fn user_store() -> UserStoreType {
+        let mut store = HashmapUserStore::default();
+        store.add_user(user()).await.unwrap();
+        Arc::new(RwLock::new(store))
+    }
+
+    fn keyring_type(keyring: JwtKeyring) -> JwtKeyringType {
+        Arc::new(RwLock::new(keyring))
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
-        assert_eq!(cookie.name(), JWT_COOKIE_NAME);
+        let user = user();
+        let cookie = generate_auth_cookie(&user, "family", &settings(), keyring_type(keyring()))
+            .await
+            .unwrap();
+        assert_eq!(cookie.name(), "jwt");
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
@@ -99,8 +333,8 @@ mod tests {
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test_token".to_owned();
-        let cookie = create_auth_cookie(token.clone());
-        assert_eq!(cookie.name(), JWT_COOKIE_NAME);
+        let cookie = create_auth_cookie(token.clone(), &settings());
+        assert_eq!(cookie.name(), "jwt");
         assert_eq!(cookie.value(), token);
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
@@ -109,17 +343,27 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let user = user();
+        let result = generate_auth_token(&user, "family", &settings(), &keyring()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let result = validate_token(&token).await.unwrap();
+        let user = user();
+        let token = generate_auth_token(&user, "family", &settings(), &keyring()).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let result = validate_token(
+            &token,
+            None,
+            banned_token_store,
+            user_store().await,
+            keyring_type(keyring()),
+        )
+        .await
+        .unwrap();
         assert_eq!(result.sub, "test@example.com");
+        assert_eq!(result.sid.as_deref(), Some("family"));
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -127,13 +371,307 @@ mod tests {
             .timestamp();
 
         assert!(result.exp > exp as usize);
+        assert_eq!(result.iss, "auth-service");
+        assert_eq!(result.aud, vec!["app-service"]);
+        assert_eq!(result.nbf, result.iat);
+        assert!(Uuid::parse_str(&result.jti).is_ok());
+        assert_eq!(result.roles, BTreeSet::from([Role::User]));
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_carries_user_roles() {
+        let user = user().with_roles(BTreeSet::from([Role::User, Role::Admin]));
+        let keyring = keyring_type(keyring());
+        let token =
+            generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let claims = validate_token(
+            &token,
+            None,
+            banned_token_store,
+            user_store().await,
+            keyring,
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.roles, user.roles);
+    }
+
+    #[tokio::test]
+    async fn test_every_token_has_a_unique_id() {
+        let user = user();
+        let keyring = keyring_type(keyring());
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let token =
+                generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+            let claims = validate_token(
+                &token,
+                None,
+                banned_token_store.clone(),
+                user_store().await,
+                keyring.clone(),
+            )
+            .await
+            .unwrap();
+            ids.push(claims.jti);
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_access_token_is_only_for_the_client() {
+        let user = user();
+        let client_id = ClientId::default();
+        let keyring = keyring_type(keyring());
+        let token =
+            generate_access_token(&user, &client_id, &settings(), &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        let claims = validate_token(
+            &token,
+            Some(client_id.as_ref()),
+            banned_token_store.clone(),
+            user_store().await,
+            keyring.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+        assert_eq!(claims.aud, vec![client_id.as_ref().to_owned()]);
+
+        // Not a session for this service, nor for the configured audiences
+        assert!(validate_token(
+            &token,
+            None,
+            banned_token_store.clone(),
+            user_store().await,
+            keyring.clone()
+        )
+        .await
+        .is_err());
+        assert!(validate_token(
+            &token,
+            Some("app-service"),
+            banned_token_store,
+            user_store().await,
+            keyring
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_for_audience() {
+        let user = user();
+        let settings = JwtSettings {
+            audiences: vec!["app-service".to_owned(), "billing-service".to_owned()],
+            ..settings()
+        };
+        let keyring = keyring_type(JwtKeyring::from_settings(&settings).unwrap());
+        let token =
+            generate_auth_token(&user, "family", &settings, &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        for audience in ["app-service", "billing-service"] {
+            let result = validate_token(
+                &token,
+                Some(audience),
+                banned_token_store.clone(),
+                user_store().await,
+                keyring.clone(),
+            )
+            .await;
+            assert!(result.is_ok(), "Failed for input: {:?}", audience);
+        }
+
+        let result = validate_token(
+            &token,
+            Some("other-service"),
+            banned_token_store,
+            user_store().await,
+            keyring,
+        )
+        .await;
+        assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let result = validate_token(&token).await;
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let result = validate_token(
+            &token,
+            None,
+            banned_token_store,
+            user_store().await,
+            keyring_type(keyring()),
+        )
+        .await;
         assert!(result.is_err());
     }
-}
 
+    #[tokio::test]
+    async fn test_validate_token_with_banned_token() {
+        let user = user();
+        let keyring = keyring_type(keyring());
+        let token =
+            generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let claims = validate_token(
+            &token,
+            None,
+            banned_token_store.clone(),
+            user_store().await,
+            keyring.clone(),
+        )
+        .await
+        .unwrap();
+        banned_token_store
+            .write()
+            .await
+            .add_token(claims.jti)
+            .await
+            .unwrap();
+        let result = validate_token(
+            &token,
+            None,
+            banned_token_store,
+            user_store().await,
+            keyring,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_sessions_end() {
+        let user = user();
+        let keyring = keyring_type(keyring());
+        let token =
+            generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let user_store = user_store().await;
+
+        user_store
+            .write()
+            .await
+            .increment_session_version(&user.email)
+            .await
+            .unwrap();
+        let result = validate_token(
+            &token,
+            None,
+            banned_token_store.clone(),
+            user_store.clone(),
+            keyring.clone(),
+        )
+        .await;
+        assert!(result.is_err());
+
+        // Tokens issued afterwards are accepted
+        let user = user_store.read().await.get_user(&user.email).await.unwrap();
+        let token =
+            generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+        let result = validate_token(&token, None, banned_token_store, user_store, keyring).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_unknown_user() {
+        let user = user();
+        let keyring = keyring_type(keyring());
+        let token =
+            generate_auth_token(&user, "family", &settings(), &*keyring.read().await).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
+        let result = validate_token(&token, None, banned_token_store, user_store, keyring).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_different_secret() {
+        let user = user();
+        let settings = JwtSettings {
+            secret: "secret".to_owned(),
+            ..JwtSettings::default()
+        };
+        let other_settings = JwtSettings {
+            secret: "other-secret".to_owned(),
+            ..JwtSettings::default()
+        };
+
+        let keyring = JwtKeyring::from_settings(&settings).unwrap();
+        let other_keyring = keyring_type(JwtKeyring::from_settings(&other_settings).unwrap());
+
+        let token = generate_auth_token(&user, "family", &settings, &keyring).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let result = validate_token(
+            &token,
+            None,
+            banned_token_store,
+            user_store().await,
+            other_keyring,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_cookie_uses_configured_name_and_ttl() {
+        let user = user();
+        let settings = JwtSettings {
+            secret: "secret".to_owned(),
+            cookie_name: "session".to_owned(),
+            token_ttl_seconds: 60,
+            ..JwtSettings::default()
+        };
+
+        let keyring = keyring_type(JwtKeyring::from_settings(&settings).unwrap());
+        let cookie = generate_auth_cookie(&user, "family", &settings, keyring.clone())
+            .await
+            .unwrap();
+        assert_eq!(cookie.name(), "session");
+
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let claims = validate_token(
+            cookie.value(),
+            None,
+            banned_token_store,
+            user_store().await,
+            keyring,
+        )
+        .await
+        .unwrap();
+        let max_exp = Utc::now().timestamp() + 60;
+        assert!(claims.exp <= max_exp as usize);
+    }
+
+    #[tokio::test]
+    async fn test_generate_refresh_cookie() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+        let cookie =
+            generate_refresh_cookie(&email, "family", refresh_token_store.clone(), &settings())
+                .await
+                .unwrap();
+        assert_eq!(cookie.name(), "refresh_token");
+        assert_eq!(cookie.path(), Some("/refresh"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(cookie.max_age(), Some(time::Duration::days(30)));
+
+        let token = RefreshToken::parse(cookie.value().to_owned()).unwrap();
+        let record = refresh_token_store
+            .write()
+            .await
+            .consume_token(&token)
+            .await
+            .unwrap();
+        assert_eq!(record.email, email);
+        assert_eq!(record.family_id, "family");
+        assert!(record.expires_at > Utc::now().timestamp());
+    }
+}
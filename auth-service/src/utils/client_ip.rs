@@ -0,0 +1,218 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::http::{header::FORWARDED, HeaderMap};
+use ipnet::IpNet;
+use serde::Deserialize;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+// A trusted proxy is an IP address or a CIDR range, e.g. `10.0.0.0/8`.
+// Parsed once when the settings are loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TrustedProxy(IpNet);
+
+impl TrustedProxy {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(proxy: &str) -> Result<Self, Self::Err> {
+        proxy
+            .parse::<IpNet>()
+            .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+            .map(Self)
+            .map_err(|_| format!("{} is not an IP address or CIDR range", proxy))
+    }
+}
+
+impl TryFrom<String> for TrustedProxy {
+    type Error = String;
+
+    fn try_from(proxy: String) -> Result<Self, Self::Error> {
+        proxy.parse()
+    }
+}
+
+// The IP of the client behind `peer`. Only trusted proxies can say who they
+// forwarded a request for, so the forwarding headers are read from the right,
+// hop by hop, until an address that is not a trusted proxy. Anything before it
+// could have been made up by the client.
+pub fn client_ip(
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    trusted_proxies: &[TrustedProxy],
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    let mut client_ip = peer.ip().to_canonical();
+
+    for hop in forwarded_for(headers).iter().rev() {
+        if !is_trusted(&client_ip) {
+            break;
+        }
+
+        // Unknown or obfuscated hops end the chain at the last proxy
+        match parse_hop(hop) {
+            Some(ip) => client_ip = ip.to_canonical(),
+            None => break,
+        }
+    }
+
+    client_ip
+}
+
+// The addresses a request was forwarded for, oldest first. `Forwarded` is
+// preferred over `X-Forwarded-For` when a proxy sets both.
+fn forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    let forwarded: Vec<String> = header_list(headers, FORWARDED.as_str())
+        .iter()
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .map(|(_, value)| value.trim().to_owned())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    match forwarded.is_empty() {
+        true => header_list(headers, X_FORWARDED_FOR),
+        false => forwarded,
+    }
+}
+
+// Comma separated values across every occurrence of the header
+fn header_list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// e.g. `192.0.2.60`, `"192.0.2.60:4711"` or `"[2001:db8::17]:4711"`
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim_matches('"');
+
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+        .or_else(|| {
+            hop.strip_prefix('[')
+                .and_then(|hop| hop.strip_suffix(']'))
+                .and_then(|hop| hop.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn peer(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 4711)
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn proxies(proxies: &[&str]) -> Vec<TrustedProxy> {
+        proxies.iter().map(|proxy| proxy.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_trusted_proxy() {
+        for proxy in ["10.0.0.1", "10.0.0.0/8", "::1", "fd00::/8"] {
+            assert!(proxy.parse::<TrustedProxy>().is_ok(), "Failed for: {proxy}");
+        }
+
+        for proxy in ["", "proxy.local", "10.0.0.0/33", "10.0.0.1:80"] {
+            assert!(
+                proxy.parse::<TrustedProxy>().is_err(),
+                "Failed for: {proxy}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_forwarding_headers_are_ignored_from_untrusted_peers() {
+        let headers = headers(&[
+            ("x-forwarded-for", "203.0.113.7"),
+            ("forwarded", "for=203.0.113.8"),
+        ]);
+
+        let result = client_ip(peer("198.51.100.1"), &headers, &[]);
+        assert_eq!(result, ip("198.51.100.1"));
+
+        let result = client_ip(peer("198.51.100.1"), &headers, &proxies(&["10.0.0.0/8"]));
+        assert_eq!(result, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn test_client_is_the_last_untrusted_hop() {
+        let trusted_proxies = proxies(&["10.0.0.0/8"]);
+
+        // The client made up the first address, the proxies added the others
+        let headers = headers(&[
+            ("x-forwarded-for", "192.0.2.1, 203.0.113.7"),
+            ("x-forwarded-for", "10.0.0.2"),
+        ]);
+
+        let result = client_ip(peer("10.0.0.1"), &headers, &trusted_proxies);
+        assert_eq!(result, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let trusted_proxies = proxies(&["10.0.0.1", "::1"]);
+
+        let test_cases = [
+            ("for=203.0.113.7;proto=https", "203.0.113.7"),
+            ("for=192.0.2.1, For=\"203.0.113.7:4711\"", "203.0.113.7"),
+            ("for=\"[2001:db8::17]:4711\"", "2001:db8::17"),
+            ("for=\"[2001:db8::17]\";by=10.0.0.1", "2001:db8::17"),
+            // Obfuscated clients can't be told apart, so they share the proxy
+            ("for=_hidden", "10.0.0.1"),
+            ("for=unknown", "10.0.0.1"),
+            ("proto=https", "10.0.0.1"),
+        ];
+
+        for (forwarded, expected) in test_cases {
+            let headers = headers(&[
+                ("forwarded", forwarded),
+                ("x-forwarded-for", "198.51.100.1"),
+            ]);
+
+            let result = client_ip(peer("10.0.0.1"), &headers, &trusted_proxies);
+            assert_eq!(result, ip(expected), "Failed for: {forwarded}");
+        }
+    }
+
+    #[test]
+    fn test_ipv4_mapped_peers_match_ipv4_proxies() {
+        let headers = headers(&[("x-forwarded-for", "203.0.113.7")]);
+
+        let result = client_ip(peer("::ffff:10.0.0.1"), &headers, &proxies(&["10.0.0.1"]));
+        assert_eq!(result, ip("203.0.113.7"));
+    }
+}
@@ -1,25 +1,91 @@
-use dotenvy::dotenv;
-use lazy_static::lazy_static;
-use std::env as std_env;
-
-// Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
-lazy_static! {
-    pub static ref JWT_SECRET: String = set_token();
-}
-
-
-fn set_token() -> String {
-    dotenv().ok(); // Load environment variables
-    let secret = std_env::var(env::JWT_SECRET_ENV_VAR).expect("JWT_SECRET must be set.");
-    if secret.is_empty() {
-        panic!("JWT_SECRET must not be empty.");
-    }
-    secret
-}
-
 pub mod env {
+    pub const CONFIG_FILE_ENV_VAR: &str = "AUTH_SERVICE_CONFIG";
+    pub const BIND_ADDRESS_ENV_VAR: &str = "BIND_ADDRESS";
+    pub const PUBLIC_URL_ENV_VAR: &str = "PUBLIC_URL";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_PRIVATE_KEY_PATH_ENV_VAR: &str = "JWT_PRIVATE_KEY_PATH";
+    pub const JWT_PREVIOUS_SECRET_ENV_VAR: &str = "JWT_PREVIOUS_SECRET";
+    pub const JWT_KEY_ENCRYPTION_KEY_ENV_VAR: &str = "JWT_KEY_ENCRYPTION_KEY";
+    pub const ADMIN_API_TOKEN_ENV_VAR: &str = "ADMIN_API_TOKEN";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const JWT_AUDIENCES_ENV_VAR: &str = "JWT_AUDIENCES";
+    pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
+    pub const JWT_COOKIE_NAME_ENV_VAR: &str = "JWT_COOKIE_NAME";
+    pub const TOKEN_TTL_SECONDS_ENV_VAR: &str = "TOKEN_TTL_SECONDS";
+    pub const REFRESH_COOKIE_NAME_ENV_VAR: &str = "REFRESH_COOKIE_NAME";
+    pub const REFRESH_TOKEN_TTL_SECONDS_ENV_VAR: &str = "REFRESH_TOKEN_TTL_SECONDS";
+    pub const JWT_KEY_SYNC_INTERVAL_SECONDS_ENV_VAR: &str = "JWT_KEY_SYNC_INTERVAL_SECONDS";
+    pub const TWO_FA_CODE_TTL_SECONDS_ENV_VAR: &str = "TWO_FA_CODE_TTL_SECONDS";
+    pub const TWO_FA_MAX_FAILED_ATTEMPTS_ENV_VAR: &str = "TWO_FA_MAX_FAILED_ATTEMPTS";
+    pub const TOTP_ISSUER_ENV_VAR: &str = "TOTP_ISSUER";
+    pub const WEBAUTHN_RP_ID_ENV_VAR: &str = "WEBAUTHN_RP_ID";
+    pub const WEBAUTHN_RP_NAME_ENV_VAR: &str = "WEBAUTHN_RP_NAME";
+    pub const WEBAUTHN_CHALLENGE_TTL_SECONDS_ENV_VAR: &str = "WEBAUTHN_CHALLENGE_TTL_SECONDS";
+    pub const WEBAUTHN_FAKE_CREDENTIAL_KEY_ENV_VAR: &str = "WEBAUTHN_FAKE_CREDENTIAL_KEY";
+    pub const RATE_LIMIT_BURST_ENV_VAR: &str = "RATE_LIMIT_BURST";
+    pub const RATE_LIMIT_PER_MINUTE_ENV_VAR: &str = "RATE_LIMIT_PER_MINUTE";
+    pub const TRUSTED_PROXIES_ENV_VAR: &str = "TRUSTED_PROXIES";
+    pub const LOGIN_MAX_FAILURES_ENV_VAR: &str = "LOGIN_MAX_FAILURES";
+    pub const LOGIN_LOCKOUT_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_SECONDS";
+    pub const EMAIL_VERIFICATION_REQUIRED_ENV_VAR: &str = "EMAIL_VERIFICATION_REQUIRED";
+    pub const EMAIL_VERIFICATION_TOKEN_TTL_SECONDS_ENV_VAR: &str =
+        "EMAIL_VERIFICATION_TOKEN_TTL_SECONDS";
+    pub const PASSWORD_RESET_TOKEN_TTL_SECONDS_ENV_VAR: &str = "PASSWORD_RESET_TOKEN_TTL_SECONDS";
+    pub const PASSWORD_LOGIN_ENABLED_ENV_VAR: &str = "PASSWORD_LOGIN_ENABLED";
+    pub const MAGIC_LINK_TOKEN_TTL_SECONDS_ENV_VAR: &str = "MAGIC_LINK_TOKEN_TTL_SECONDS";
+    pub const OAUTH_AUTHORIZATION_CODE_TTL_SECONDS_ENV_VAR: &str =
+        "OAUTH_AUTHORIZATION_CODE_TTL_SECONDS";
+    pub const ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR: &str =
+        "ACCOUNT_DELETION_GRACE_PERIOD_SECONDS";
+    pub const ACCOUNT_PURGE_INTERVAL_SECONDS_ENV_VAR: &str = "ACCOUNT_PURGE_INTERVAL_SECONDS";
+    pub const EMAIL_SENDER_ENV_VAR: &str = "EMAIL_SENDER";
+    pub const EMAIL_OUTBOX_DIR_ENV_VAR: &str = "EMAIL_OUTBOX_DIR";
+    pub const EMAIL_LOG_ONLY_ENV_VAR: &str = "EMAIL_LOG_ONLY";
+    pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
+    pub const TEST_DATABASE_URL_ENV_VAR: &str = "TEST_DATABASE_URL";
+    pub const REDIS_URL_ENV_VAR: &str = "REDIS_URL";
+    pub const TEST_REDIS_URL_ENV_VAR: &str = "TEST_REDIS_URL";
+    pub const ARGON2_MEMORY_COST_KIB_ENV_VAR: &str = "ARGON2_MEMORY_COST_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
 }
 
-pub const JWT_COOKIE_NAME: &str = "jwt";
-
+// Values used by `Settings` when neither the config file nor the environment set them
+pub mod defaults {
+    pub const CONFIG_FILE: &str = "config.toml";
+    pub const BIND_ADDRESS: &str = "0.0.0.0:3000";
+    pub const PUBLIC_URL: &str = "http://localhost:3000";
+    pub const JWT_COOKIE_NAME: &str = "jwt";
+    pub const JWT_ISSUER: &str = "auth-service";
+    pub const JWT_AUDIENCE: &str = "app-service";
+    // Clock skew tolerated between services when checking `exp` and `nbf`
+    pub const JWT_LEEWAY_SECONDS: u64 = 30;
+    // This value determines how long the JWT auth token is valid for
+    pub const TOKEN_TTL_SECONDS: u64 = 600; // 10 minutes
+    pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
+    // How long a refresh token can be exchanged for a new auth token
+    pub const REFRESH_TOKEN_TTL_SECONDS: u64 = 2_592_000; // 30 days
+                                                          // How often replicas load signing keys rotated on other replicas
+    pub const JWT_KEY_SYNC_INTERVAL_SECONDS: u64 = 60;
+    // How long a 2FA code can be used after it was sent
+    pub const TWO_FA_CODE_TTL_SECONDS: u64 = 600; // 10 minutes
+    pub const TWO_FA_MAX_FAILED_ATTEMPTS: u32 = 5;
+    pub const TOTP_ISSUER: &str = "auth-service";
+    // Shown by browsers and authenticators when creating a passkey
+    pub const WEBAUTHN_RP_NAME: &str = "auth-service";
+    // How long a passkey registration or sign-in can take in the browser
+    pub const WEBAUTHN_CHALLENGE_TTL_SECONDS: u64 = 300; // 5 minutes
+                                                         // How long an email verification link can be used after it was sent
+    pub const EMAIL_VERIFICATION_TOKEN_TTL_SECONDS: u64 = 86_400; // 24 hours
+                                                                  // How long a password reset link can be used after it was sent
+    pub const PASSWORD_RESET_TOKEN_TTL_SECONDS: u64 = 900; // 15 minutes
+                                                           // How long a login link can be used after it was sent
+    pub const MAGIC_LINK_TOKEN_TTL_SECONDS: u64 = 600; // 10 minutes
+                                                       // Clients exchange authorization codes right after the redirect
+    pub const OAUTH_AUTHORIZATION_CODE_TTL_SECONDS: u64 = 60;
+    // Deleted accounts can be restored by an operator until they are purged
+    pub const ACCOUNT_DELETION_GRACE_PERIOD_SECONDS: u64 = 2_592_000; // 30 days
+    pub const ACCOUNT_PURGE_INTERVAL_SECONDS: u64 = 3600; // 1 hour
+    pub const EMAIL_SENDER: &str = "no-reply@auth-service.local";
+}
@@ -0,0 +1,178 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailToken, EmailTokenPurpose, EmailTokenRecord, UserStoreError,
+    },
+};
+
+// Email a single-use link for `purpose` to `email`, valid for `ttl_seconds`
+pub async fn send_email_link(
+    state: &AppState,
+    email: &Email,
+    purpose: EmailTokenPurpose,
+    ttl_seconds: u64,
+) -> Result<(), AuthAPIError> {
+    let ttl_seconds = i64::try_from(ttl_seconds).map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let token = EmailToken::default();
+    let record = EmailTokenRecord {
+        email: email.clone(),
+        purpose,
+        expires_at: Utc::now().timestamp() + ttl_seconds,
+    };
+    let link_token = match purpose {
+        EmailTokenPurpose::MagicLogin => sign_login_link_token(state, &token, &record).await?,
+        _ => token.as_ref().to_owned(),
+    };
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(token.clone(), record)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Verification links go straight to the API. Reset links open the UI,
+    // which asks for the new password and posts it to /reset-password.
+    // Login links open the UI too, so link scanners that fetch every URL in
+    // an email can not use them up.
+    let (path, subject, action) = match purpose {
+        EmailTokenPurpose::VerifyEmail => (
+            "/verify-email?token=",
+            "Verify your email address",
+            "Confirm your email address",
+        ),
+        EmailTokenPurpose::ResetPassword => (
+            "/?reset_token=",
+            "Reset your password",
+            "Choose a new password",
+        ),
+        EmailTokenPurpose::MagicLogin => ("/?magic_token=", "Your login link", "Log in"),
+    };
+    let link = format!(
+        "{}{}{}",
+        state.settings.public_url.trim_end_matches('/'),
+        path,
+        link_token
+    );
+    let content = format!("{} by opening this link: {}", action, link);
+
+    state
+        .email_client
+        .send_email(email, subject, &content)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+// Like `send_email_link`, but only if `email` belongs to an account, and in
+// the background. The caller answers at once either way, so response times
+// do not reveal which addresses have an account. Verification links only go
+// to accounts that are still unverified.
+pub fn send_email_link_to_account(
+    state: &AppState,
+    email: Email,
+    purpose: EmailTokenPurpose,
+    ttl_seconds: u64,
+) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        let user = state.user_store.read().await.get_user(&email).await;
+        let sent = match user {
+            Ok(user) if purpose == EmailTokenPurpose::VerifyEmail && user.email_verified => true,
+            Ok(user) => send_email_link(&state, &user.email, purpose, ttl_seconds)
+                .await
+                .is_ok(),
+            Err(UserStoreError::UserNotFound) => true,
+            Err(_) => false,
+        };
+
+        if !sent {
+            eprintln!("Failed to send {:?} link", purpose);
+        }
+    });
+}
+
+// Use up a token sent by `send_email_link` and return the address it was
+// sent to. Tokens for another purpose or past their expiry are rejected,
+// but still used up. Login link tokens whose signature does not check out
+// are rejected without using up the token inside.
+pub async fn consume_email_token(
+    state: &AppState,
+    token: String,
+    purpose: EmailTokenPurpose,
+) -> Result<Email, AuthAPIError> {
+    let (token, signed_email) = match purpose {
+        EmailTokenPurpose::MagicLogin => {
+            let claims = state
+                .jwt_keyring
+                .read()
+                .await
+                .decode_for_audience::<LoginLinkClaims>(&token, LOGIN_LINK_AUDIENCE)
+                .map_err(|_| AuthAPIError::InvalidToken)?;
+            (claims.jti, Some(claims.sub))
+        }
+        _ => (token, None),
+    };
+    let token = EmailToken::parse(token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let record = state
+        .email_token_store
+        .write()
+        .await
+        .consume_token(&token)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    if record.purpose != purpose || record.expires_at < Utc::now().timestamp() {
+        return Err(AuthAPIError::InvalidToken);
+    }
+    if signed_email.is_some_and(|email| email != record.email.as_ref()) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    Ok(record.email)
+}
+
+// Login links log the user straight in, so rather than the bare random token
+// they carry a JWT signed with the JWT keys. Its audience keeps it from being
+// accepted as a session, and its ID is the single-use token.
+const LOGIN_LINK_AUDIENCE: &str = "login-link";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginLinkClaims {
+    sub: String,
+    exp: i64,
+    iat: i64,
+    nbf: i64,
+    jti: String,
+    iss: String,
+    aud: Vec<String>,
+}
+
+async fn sign_login_link_token(
+    state: &AppState,
+    token: &EmailToken,
+    record: &EmailTokenRecord,
+) -> Result<String, AuthAPIError> {
+    let now = Utc::now().timestamp();
+    let claims = LoginLinkClaims {
+        sub: record.email.as_ref().to_owned(),
+        exp: record.expires_at,
+        iat: now,
+        nbf: now,
+        jti: token.as_ref().to_owned(),
+        iss: state.settings.jwt.issuer.clone(),
+        aud: vec![LOGIN_LINK_AUDIENCE.to_owned()],
+    };
+
+    state
+        .jwt_keyring
+        .read()
+        .await
+        .encode(&claims)
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use tokio::time::{interval_at, Instant};
+
+use crate::{
+    app_state::{JwtKeyStoreType, JwtKeyringType},
+    domain::JwtKeyStoreError,
+};
+
+// Runs forever, loading the keys rotated on any replica and deleting stored
+// keys no token needs anymore. Every replica runs it; deleting is idempotent,
+// so they do not get in each other's way.
+pub async fn sync_jwt_keys(
+    jwt_keyring: JwtKeyringType,
+    jwt_key_store: JwtKeyStoreType,
+    interval_seconds: u64,
+) {
+    // The keys were loaded once before the server started
+    let period = Duration::from_secs(interval_seconds);
+    let mut interval = interval_at(Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = load_jwt_keys(&jwt_keyring, &jwt_key_store).await {
+            eprintln!("Failed to sync signing keys: {:?}", e);
+        }
+    }
+}
+
+pub async fn load_jwt_keys(
+    jwt_keyring: &JwtKeyringType,
+    jwt_key_store: &JwtKeyStoreType,
+) -> Result<(), JwtKeyStoreError> {
+    let records = jwt_key_store.read().await.get_keys().await?;
+
+    let expired_before = {
+        let mut keyring = jwt_keyring.write().await;
+        keyring.sync(&records);
+        keyring.expired_before(&records)
+    };
+
+    if let Some(expired_before) = expired_before {
+        jwt_key_store
+            .write()
+            .await
+            .delete_keys(expired_before)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1005 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use ed25519_dalek::SigningKey;
+use jsonwebtoken::{
+    errors::ErrorKind,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rand::RngCore;
+use rsa::{pkcs1::DecodeRsaPrivateKey, traits::PublicKeyParts, RsaPrivateKey};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::{JwtKeyRecord, KeyEncryptionKey},
+    settings::{JwtAlgorithm, JwtKeySettings, JwtSettings, SettingsError},
+};
+
+// Keys used to sign and verify JWT auth tokens. Exactly one key is active and
+// signs new tokens. Retired keys only verify the tokens they signed, until
+// those tokens have expired. Every token names its key in the `kid` header.
+pub struct JwtKeyring {
+    keys: Vec<JwtKey>,
+    active_kid: String,
+    // How long a retired key stays usable: the token TTL plus the leeway, plus
+    // the key sync interval, as other replicas may sign with a key for that
+    // long after this one has retired it
+    verify_window_seconds: i64,
+    // Claim checks shared by every key. The algorithm is set per key.
+    validation: Validation,
+    // Private keys in the key store are encrypted with this. Without it,
+    // stored keys can't be loaded or added.
+    key_encryption_key: Option<KeyEncryptionKey>,
+}
+
+pub struct JwtKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    // Public key other services can verify tokens with. This is `None` for
+    // HS256, because the shared secret must never be published.
+    public_jwk: Option<Jwk>,
+    retired_at: Option<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JwtKeyringError {
+    KeyAlreadyExists,
+    KeyNotFound,
+    KeyRetired,
+    UnexpectedError,
+}
+
+impl JwtKeyring {
+    pub fn from_settings(settings: &JwtSettings) -> Result<Self, SettingsError> {
+        let active_key = JwtKey::load(
+            settings.algorithm,
+            &settings.secret,
+            settings.private_key_path.as_deref(),
+            "jwt.private_key_path",
+        )?;
+
+        let mut keyring = Self::new(active_key, settings);
+        if let Some(key) = &settings.key_encryption_key {
+            keyring.key_encryption_key = Some(KeyEncryptionKey::parse(key).ok_or(
+                SettingsError::InvalidValue {
+                    setting: "jwt.key_encryption_key",
+                    reason: "must be a base64url encoded 32 byte key",
+                },
+            )?);
+        }
+
+        // HS256 keys get a random kid, so repeated secrets are found by value
+        let mut secrets = Vec::new();
+        if settings.algorithm == JwtAlgorithm::HS256 {
+            secrets.push(settings.secret.as_str());
+        }
+
+        // Configured retired keys may still have signed tokens that were
+        // issued just before this process started
+        for key_settings in &settings.retired_keys {
+            let JwtKeySettings {
+                algorithm,
+                secret,
+                private_key_path,
+            } = key_settings;
+            let repeats_secret = *algorithm == JwtAlgorithm::HS256 && secrets.contains(&&**secret);
+            if *algorithm == JwtAlgorithm::HS256 {
+                secrets.push(secret);
+            }
+
+            let key = JwtKey::load(
+                *algorithm,
+                secret,
+                private_key_path.as_deref(),
+                "jwt.retired_keys",
+            )?;
+
+            let kid = key.kid.clone();
+            if repeats_secret || keyring.add_key(key).is_err() || keyring.retire(&kid).is_err() {
+                return Err(SettingsError::InvalidValue {
+                    setting: "jwt.retired_keys",
+                    reason: "must not repeat the active key or each other",
+                });
+            }
+        }
+
+        Ok(keyring)
+    }
+
+    pub fn new(active_key: JwtKey, settings: &JwtSettings) -> Self {
+        let mut validation = Validation::new(active_key.algorithm);
+        validation.set_issuer(&[&settings.issuer]);
+        validation.set_audience(&settings.audiences);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation.validate_nbf = true;
+        validation.leeway = settings.leeway_seconds;
+
+        let verify_window_seconds = settings
+            .token_ttl_seconds
+            .saturating_add(settings.leeway_seconds)
+            .saturating_add(settings.key_sync_interval_seconds);
+
+        Self {
+            active_kid: active_key.kid.clone(),
+            keys: vec![active_key],
+            verify_window_seconds: verify_window_seconds.try_into().unwrap_or(i64::MAX),
+            validation,
+            key_encryption_key: None,
+        }
+    }
+
+    pub fn key_encryption_key(&self) -> Option<&KeyEncryptionKey> {
+        self.key_encryption_key.as_ref()
+    }
+
+    pub fn active_kid(&self) -> &str {
+        &self.active_kid
+    }
+
+    pub fn active_algorithm(&self) -> Algorithm {
+        self.active_key().algorithm
+    }
+
+    // Add a key that verifies tokens but does not sign them until promoted.
+    // Its public key is published straight away, so other services can
+    // pick it up before any token is signed with it.
+    pub fn add_key(&mut self, key: JwtKey) -> Result<(), JwtKeyringError> {
+        if self.keys.iter().any(|existing| existing.kid == key.kid) {
+            return Err(JwtKeyringError::KeyAlreadyExists);
+        }
+        self.keys.push(key);
+        Ok(())
+    }
+
+    // Make the key with `kid` the signing key and retire the previous one
+    pub fn promote(&mut self, kid: &str) -> Result<(), JwtKeyringError> {
+        let key = self.find(kid).ok_or(JwtKeyringError::KeyNotFound)?;
+        if key.retired_at.is_some() {
+            return Err(JwtKeyringError::KeyRetired);
+        }
+
+        let previous_kid = std::mem::replace(&mut self.active_kid, kid.to_owned());
+        if previous_kid != kid {
+            self.retire(&previous_kid)?;
+        }
+        Ok(())
+    }
+
+    fn retire(&mut self, kid: &str) -> Result<(), JwtKeyringError> {
+        let now = Utc::now().timestamp();
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|key| key.kid == kid)
+            .ok_or(JwtKeyringError::KeyNotFound)?;
+        key.retired_at.get_or_insert(now);
+        Ok(())
+    }
+
+    // Bring in the keys rotated on any replica. Stored keys verify tokens and
+    // are published as soon as they are loaded. Each one signs from its
+    // `activates_at` on, and the keys that were active before it are retired.
+    pub fn sync(&mut self, records: &[JwtKeyRecord]) {
+        let now = Utc::now().timestamp();
+        let expired_before = self.expired_before(records);
+
+        for record in records {
+            let has_expired = expired_before.is_some_and(|before| record.activates_at < before);
+            if has_expired || self.find(&record.kid).is_some() {
+                continue;
+            }
+
+            let key = self
+                .key_encryption_key
+                .as_ref()
+                .and_then(|key_encryption_key| JwtKey::from_record(record, key_encryption_key));
+            match key {
+                Some(key) => self.keys.push(key),
+                None => eprintln!(
+                    "Ignoring invalid signing key {} in the key store",
+                    record.kid
+                ),
+            }
+        }
+
+        let mut due: Vec<&JwtKeyRecord> = records
+            .iter()
+            .filter(|record| record.activates_at <= now && self.find(&record.kid).is_some())
+            .collect();
+        due.sort_by(|a, b| (a.activates_at, &a.kid).cmp(&(b.activates_at, &b.kid)));
+
+        if let Some((latest, earlier)) = due.split_last() {
+            if self.promote(&latest.kid).is_ok() {
+                for record in earlier {
+                    let _ = self.retire(&record.kid);
+                }
+            }
+        }
+
+        self.prune();
+    }
+
+    // Stored keys that became active before this were replaced longer ago
+    // than any token they signed can live, so they can be deleted
+    pub fn expired_before(&self, records: &[JwtKeyRecord]) -> Option<i64> {
+        let now = Utc::now().timestamp();
+        records
+            .iter()
+            .map(|record| record.activates_at)
+            .filter(|activates_at| activates_at.saturating_add(self.verify_window_seconds) < now)
+            .max()
+    }
+
+    // Drop retired keys once every token they signed has expired
+    pub fn prune(&mut self) {
+        let now = Utc::now().timestamp();
+        let verify_window_seconds = self.verify_window_seconds;
+        self.keys
+            .retain(|key| !key.has_expired(now, verify_window_seconds));
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let key = self.active_key();
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        jsonwebtoken::encode(&header, claims, &key.encoding_key)
+    }
+
+    // Verify a token with the key named in its `kid` header. The token must
+    // come from our issuer and name at least one configured audience.
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<T, jsonwebtoken::errors::Error> {
+        self.decode_with(token, self.validation.clone())
+    }
+
+    // Same as `decode`, but the token must be minted for `audience`
+    pub fn decode_for_audience<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &str,
+    ) -> Result<T, jsonwebtoken::errors::Error> {
+        let mut validation = self.validation.clone();
+        validation.set_audience(&[audience]);
+        self.decode_with(token, validation)
+    }
+
+    fn decode_with<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        mut validation: Validation,
+    ) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid.ok_or(ErrorKind::InvalidToken)?;
+
+        if let Some(key) = self.usable_key(&kid) {
+            validation.algorithms = vec![key.algorithm];
+            return jsonwebtoken::decode::<T>(token, &key.decoding_key, &validation)
+                .map(|data| data.claims);
+        }
+
+        // Every replica gives the configured secrets its own random kid, so
+        // HS256 tokens from other replicas are checked against each secret
+        if header.alg != Algorithm::HS256 {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+
+        validation.algorithms = vec![Algorithm::HS256];
+        let now = Utc::now().timestamp();
+        self.keys
+            .iter()
+            .filter(|key| key.algorithm == Algorithm::HS256)
+            .filter(|key| !key.has_expired(now, self.verify_window_seconds))
+            .find_map(|key| jsonwebtoken::decode::<T>(token, &key.decoding_key, &validation).ok())
+            .map(|data| data.claims)
+            .ok_or_else(|| ErrorKind::InvalidToken.into())
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        let now = Utc::now().timestamp();
+        JwkSet {
+            keys: self
+                .keys
+                .iter()
+                .filter(|key| !key.has_expired(now, self.verify_window_seconds))
+                .filter_map(|key| key.public_jwk.clone())
+                .collect(),
+        }
+    }
+
+    fn active_key(&self) -> &JwtKey {
+        self.find(&self.active_kid)
+            .expect("The active key is always in the keyring")
+    }
+
+    fn find(&self, kid: &str) -> Option<&JwtKey> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+
+    fn usable_key(&self, kid: &str) -> Option<&JwtKey> {
+        let now = Utc::now().timestamp();
+        self.find(kid)
+            .filter(|key| !key.has_expired(now, self.verify_window_seconds))
+    }
+}
+
+impl JwtKey {
+    fn load(
+        algorithm: JwtAlgorithm,
+        secret: &str,
+        private_key_path: Option<&str>,
+        setting: &'static str,
+    ) -> Result<Self, SettingsError> {
+        if algorithm == JwtAlgorithm::HS256 {
+            return Ok(Self::from_secret(secret.as_bytes()));
+        }
+
+        let path = private_key_path.ok_or(SettingsError::InvalidValue {
+            setting,
+            reason: "must be set for RS256 and EdDSA",
+        })?;
+
+        let pem = std::fs::read_to_string(path).map_err(|e| SettingsError::ReadFile {
+            path: path.to_owned(),
+            reason: e.to_string(),
+        })?;
+
+        let key = match algorithm {
+            JwtAlgorithm::RS256 => Self::from_rsa_pem(&pem),
+            _ => Self::from_ed_pem(&pem),
+        };
+
+        key.ok_or(SettingsError::InvalidValue {
+            setting,
+            reason: "must contain a private key for the configured algorithm",
+        })
+    }
+
+    // Create a new random key for the given algorithm
+    pub fn generate(algorithm: Algorithm) -> Result<Self, JwtKeyringError> {
+        let (algorithm, private_key) = generate_private_key(algorithm)?;
+        Self::from_private_key(algorithm, &private_key).ok_or(JwtKeyringError::UnexpectedError)
+    }
+
+    // Same as `generate`, but in the form the key is kept in the key store,
+    // with the private key encrypted
+    pub fn generate_record(
+        algorithm: Algorithm,
+        activates_at: i64,
+        key_encryption_key: &KeyEncryptionKey,
+    ) -> Result<JwtKeyRecord, JwtKeyringError> {
+        let (algorithm, private_key) = generate_private_key(algorithm)?;
+        let key = Self::from_private_key(algorithm, &private_key)
+            .ok_or(JwtKeyringError::UnexpectedError)?;
+
+        Ok(JwtKeyRecord {
+            private_key: key_encryption_key
+                .encrypt(&private_key, &key.kid)
+                .ok_or(JwtKeyringError::UnexpectedError)?,
+            kid: key.kid,
+            algorithm: algorithm.as_str().to_owned(),
+            activates_at,
+        })
+    }
+
+    // A key loaded from the key store. An asymmetric key's `kid` must match
+    // the private key, while HS256 keys keep the random one they were given.
+    fn from_record(record: &JwtKeyRecord, key_encryption_key: &KeyEncryptionKey) -> Option<Self> {
+        let algorithm = record.algorithm.parse::<JwtAlgorithm>().ok()?;
+        let private_key = key_encryption_key.decrypt(&record.private_key, &record.kid)?;
+        let key = Self::from_private_key(algorithm, &private_key)?;
+
+        match algorithm {
+            JwtAlgorithm::HS256 => Some(Self {
+                kid: record.kid.clone(),
+                ..key
+            }),
+            JwtAlgorithm::RS256 | JwtAlgorithm::EdDSA => (key.kid == record.kid).then_some(key),
+        }
+    }
+
+    fn from_private_key(algorithm: JwtAlgorithm, private_key: &str) -> Option<Self> {
+        match algorithm {
+            JwtAlgorithm::HS256 => {
+                let secret = URL_SAFE_NO_PAD.decode(private_key).ok()?;
+                Some(Self::from_secret(&secret))
+            }
+            JwtAlgorithm::RS256 => Self::from_rsa_pem(private_key),
+            JwtAlgorithm::EdDSA => Self::from_ed_pem(private_key),
+        }
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    fn from_secret(secret: &[u8]) -> Self {
+        // An ID derived from the secret would give away a hash of it in every
+        // token, so it is random
+        let mut kid = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut kid);
+
+        Self {
+            kid: URL_SAFE_NO_PAD.encode(kid),
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            public_jwk: None,
+            retired_at: None,
+        }
+    }
+
+    // Accepts both PKCS#8 ("BEGIN PRIVATE KEY") and PKCS#1 ("BEGIN RSA PRIVATE KEY") PEM files
+    fn from_rsa_pem(pem: &str) -> Option<Self> {
+        use rsa::pkcs8::DecodePrivateKey;
+
+        let private_key = RsaPrivateKey::from_pkcs8_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+            .ok()?;
+
+        let n = URL_SAFE_NO_PAD.encode(private_key.n().to_bytes_be());
+        let e = URL_SAFE_NO_PAD.encode(private_key.e().to_bytes_be());
+        let kid = thumbprint(&format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n));
+
+        let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n,
+            e,
+        });
+
+        Self::from_private_pem(
+            kid,
+            Algorithm::RS256,
+            EncodingKey::from_rsa_pem(pem.as_bytes()).ok()?,
+            parameters,
+        )
+    }
+
+    // Ed25519 keys must be PKCS#8 encoded, which is what `openssl genpkey` produces
+    fn from_ed_pem(pem: &str) -> Option<Self> {
+        use ed25519_dalek::pkcs8::DecodePrivateKey;
+
+        let signing_key = SigningKey::from_pkcs8_pem(pem).ok()?;
+
+        let x = URL_SAFE_NO_PAD.encode(signing_key.verifying_key().as_bytes());
+        let kid = thumbprint(&format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x));
+
+        let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        });
+
+        Self::from_private_pem(
+            kid,
+            Algorithm::EdDSA,
+            EncodingKey::from_ed_pem(pem.as_bytes()).ok()?,
+            parameters,
+        )
+    }
+
+    // Tokens are verified with the published key, so the JWKS can never
+    // disagree with what this service accepts
+    fn from_private_pem(
+        kid: String,
+        algorithm: Algorithm,
+        encoding_key: EncodingKey,
+        parameters: AlgorithmParameters,
+    ) -> Option<Self> {
+        let key_algorithm = match algorithm {
+            Algorithm::RS256 => KeyAlgorithm::RS256,
+            _ => KeyAlgorithm::EdDSA,
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.clone()),
+                ..CommonParameters::default()
+            },
+            algorithm: parameters,
+        };
+
+        Some(Self {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key: DecodingKey::from_jwk(&jwk).ok()?,
+            public_jwk: Some(jwk),
+            retired_at: None,
+        })
+    }
+
+    fn has_expired(&self, now: i64, verify_window_seconds: i64) -> bool {
+        self.retired_at
+            .is_some_and(|retired_at| retired_at.saturating_add(verify_window_seconds) < now)
+    }
+}
+
+// RFC 7638 JWK thumbprint of the key's required members, in lexicographic order
+fn thumbprint(canonical_jwk: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical_jwk.as_bytes()))
+}
+
+// A new random private key for the given algorithm, encoded the way the key
+// store keeps it
+fn generate_private_key(algorithm: Algorithm) -> Result<(JwtAlgorithm, String), JwtKeyringError> {
+    // ed25519-dalek and rsa share the same `pkcs8` crate
+    use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+
+    let mut rng = rand::thread_rng();
+
+    match algorithm {
+        Algorithm::HS256 => {
+            let mut secret = [0u8; 32];
+            rng.fill_bytes(&mut secret);
+            Ok((JwtAlgorithm::HS256, URL_SAFE_NO_PAD.encode(secret)))
+        }
+        Algorithm::RS256 => {
+            let pem = RsaPrivateKey::new(&mut rng, 2048)
+                .map_err(|_| JwtKeyringError::UnexpectedError)?
+                .to_pkcs8_pem(LineEnding::LF)
+                .map_err(|_| JwtKeyringError::UnexpectedError)?;
+            Ok((JwtAlgorithm::RS256, pem.to_string()))
+        }
+        Algorithm::EdDSA => {
+            let mut secret = [0u8; 32];
+            rng.fill_bytes(&mut secret);
+            let pem = SigningKey::from_bytes(&secret)
+                .to_pkcs8_pem(LineEnding::LF)
+                .map_err(|_| JwtKeyringError::UnexpectedError)?;
+            Ok((JwtAlgorithm::EdDSA, pem.to_string()))
+        }
+        _ => Err(JwtKeyringError::UnexpectedError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    const RSA_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/rsa_private_key.pem");
+    const ED25519_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/ed25519_private_key.pem");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+        nbf: usize,
+        iss: String,
+        aud: Vec<String>,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims {
+            sub: "test@example.com".to_owned(),
+            exp: usize::MAX / 2,
+            nbf: 0,
+            iss: "auth-service".to_owned(),
+            aud: vec!["app-service".to_owned()],
+        }
+    }
+
+    fn settings(algorithm: JwtAlgorithm, private_key_path: &str) -> JwtSettings {
+        JwtSettings {
+            algorithm,
+            secret: "secret".to_owned(),
+            private_key_path: Some(private_key_path.to_owned()),
+            ..JwtSettings::default()
+        }
+    }
+
+    fn keyring(key: JwtKey) -> JwtKeyring {
+        let mut keyring = JwtKeyring::new(key, &JwtSettings::default());
+        keyring.key_encryption_key = Some(key_encryption_key());
+        keyring
+    }
+
+    fn key_encryption_key() -> KeyEncryptionKey {
+        KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap()
+    }
+
+    #[test]
+    fn test_hs256_keys_are_not_published() {
+        let keyring = keyring(JwtKey::from_secret(b"secret"));
+        assert!(keyring.jwks().keys.is_empty());
+
+        let token = keyring.encode(&claims()).unwrap();
+        assert_eq!(keyring.decode::<TestClaims>(&token).unwrap(), claims());
+    }
+
+    #[test]
+    fn test_asymmetric_keys_round_trip_through_jwks() {
+        let cases = [
+            (
+                JwtKey::from_rsa_pem(RSA_PRIVATE_KEY).unwrap(),
+                KeyAlgorithm::RS256,
+            ),
+            (
+                JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap(),
+                KeyAlgorithm::EdDSA,
+            ),
+        ];
+
+        for (key, key_algorithm) in cases {
+            let keyring = keyring(key);
+            let token = keyring.encode(&claims()).unwrap();
+            assert_eq!(keyring.decode::<TestClaims>(&token).unwrap(), claims());
+
+            // A verifier that only has the published key accepts the token
+            let jwks = keyring.jwks();
+            let jwk = &jwks.keys[0];
+            assert_eq!(jwk.common.key_algorithm, Some(key_algorithm));
+            assert_eq!(jwk.common.key_id.as_deref(), Some(keyring.active_kid()));
+            let decoding_key = DecodingKey::from_jwk(jwk).unwrap();
+            let mut validation = Validation::new(keyring.active_algorithm());
+            validation.set_audience(&["app-service"]);
+            let result = jsonwebtoken::decode::<TestClaims>(&token, &decoding_key, &validation);
+            assert!(result.is_ok(), "Failed for algorithm: {:?}", key_algorithm);
+        }
+    }
+
+    #[test]
+    fn test_tokens_carry_the_active_kid() {
+        let keyring = keyring(JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap());
+        let token = keyring.encode(&claims()).unwrap();
+
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.kid.as_deref(), Some(keyring.active_kid()));
+    }
+
+    #[test]
+    fn test_kid_is_derived_from_asymmetric_keys_only() {
+        // HS256 kids must not give away anything about the secret
+        assert_ne!(
+            JwtKey::from_secret(b"secret").kid,
+            JwtKey::from_secret(b"secret").kid
+        );
+        assert_eq!(
+            JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap().kid,
+            JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap().kid
+        );
+    }
+
+    #[test]
+    fn test_tokens_without_known_kid_are_rejected() {
+        let keyring = keyring(JwtKey::from_secret(b"secret"));
+
+        // Same key, but no `kid` header
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(keyring.decode::<TestClaims>(&token).is_err());
+
+        let header = Header {
+            kid: Some("unknown".to_owned()),
+            ..Header::default()
+        };
+        let token = jsonwebtoken::encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_secret(b"other-secret"),
+        )
+        .unwrap();
+        assert!(keyring.decode::<TestClaims>(&token).is_err());
+
+        // Another replica gave the same secret a different kid
+        let token =
+            jsonwebtoken::encode(&header, &claims(), &EncodingKey::from_secret(b"secret")).unwrap();
+        assert!(keyring.decode::<TestClaims>(&token).is_ok());
+    }
+
+    #[test]
+    fn test_tokens_from_other_algorithms_are_rejected() {
+        let rsa_keyring = keyring(JwtKey::from_rsa_pem(RSA_PRIVATE_KEY).unwrap());
+        let mut ed_keyring = keyring(JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap());
+        ed_keyring.add_key(JwtKey::from_secret(b"secret")).unwrap();
+
+        // An HS256 token signed with the RSA public key must not verify
+        let rsa_jwk = &rsa_keyring.jwks().keys[0];
+        let header = Header {
+            kid: Some(rsa_keyring.active_kid().to_owned()),
+            ..Header::default()
+        };
+        let AlgorithmParameters::RSA(parameters) = &rsa_jwk.algorithm else {
+            panic!("Expected an RSA key");
+        };
+        let token = jsonwebtoken::encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_secret(parameters.n.as_bytes()),
+        )
+        .unwrap();
+        assert!(rsa_keyring.decode::<TestClaims>(&token).is_err());
+
+        let token = rsa_keyring.encode(&claims()).unwrap();
+        assert!(ed_keyring.decode::<TestClaims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_retired_key_for_verification() {
+        let mut keyring = keyring(JwtKey::from_secret(b"old-secret"));
+        let old_kid = keyring.active_kid().to_owned();
+        let old_token = keyring.encode(&claims()).unwrap();
+
+        let new_key = JwtKey::generate(Algorithm::EdDSA).unwrap();
+        let new_kid = new_key.kid().to_owned();
+        keyring.add_key(new_key).unwrap();
+
+        // Added keys are published, but do not sign until promoted
+        assert_eq!(keyring.jwks().keys.len(), 1);
+        assert_eq!(keyring.active_kid(), old_kid);
+
+        keyring.promote(&new_kid).unwrap();
+        assert_eq!(keyring.active_kid(), new_kid);
+
+        let new_token = keyring.encode(&claims()).unwrap();
+        assert_eq!(
+            jsonwebtoken::decode_header(&new_token).unwrap().kid,
+            Some(new_kid)
+        );
+        assert!(keyring.decode::<TestClaims>(&new_token).is_ok());
+        assert!(keyring.decode::<TestClaims>(&old_token).is_ok());
+
+        // A retired key can not become active again
+        assert_eq!(keyring.promote(&old_kid), Err(JwtKeyringError::KeyRetired));
+    }
+
+    #[test]
+    fn test_retired_keys_expire_after_token_ttl() {
+        let mut keyring = keyring(JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap());
+        let old_kid = keyring.active_kid().to_owned();
+        let old_token = keyring.encode(&claims()).unwrap();
+
+        let new_key = JwtKey::generate(Algorithm::HS256).unwrap();
+        let new_kid = new_key.kid().to_owned();
+        keyring.add_key(new_key).unwrap();
+        keyring.promote(&new_kid).unwrap();
+
+        // Pretend the key was retired longer ago than a token lives,
+        // including the leeway for clock skew and the key sync interval
+        let key = keyring
+            .keys
+            .iter_mut()
+            .find(|key| key.kid == old_kid)
+            .unwrap();
+        key.retired_at = Some(Utc::now().timestamp() - 631);
+        assert!(keyring.decode::<TestClaims>(&old_token).is_ok());
+
+        let key = keyring
+            .keys
+            .iter_mut()
+            .find(|key| key.kid == old_kid)
+            .unwrap();
+        key.retired_at = Some(Utc::now().timestamp() - 691);
+
+        assert!(keyring.decode::<TestClaims>(&old_token).is_err());
+        assert!(keyring.jwks().keys.is_empty());
+
+        keyring.prune();
+        assert_eq!(keyring.keys.len(), 1);
+    }
+
+    #[test]
+    fn test_sync_publishes_stored_keys_before_they_sign() {
+        let mut keyring = keyring(JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap());
+        let old_kid = keyring.active_kid().to_owned();
+        let old_token = keyring.encode(&claims()).unwrap();
+
+        let now = Utc::now().timestamp();
+        let pending =
+            JwtKey::generate_record(Algorithm::EdDSA, now + 60, &key_encryption_key()).unwrap();
+        keyring.sync(std::slice::from_ref(&pending));
+        assert_eq!(keyring.active_kid(), old_kid);
+        assert!(keyring.jwks().find(&pending.kid).is_some());
+
+        let due = JwtKeyRecord {
+            activates_at: now,
+            ..pending
+        };
+        keyring.sync(std::slice::from_ref(&due));
+        assert_eq!(keyring.active_kid(), due.kid);
+        assert!(keyring.decode::<TestClaims>(&old_token).is_ok());
+    }
+
+    #[test]
+    fn test_sync_makes_replicas_agree() {
+        let now = Utc::now().timestamp();
+        let records = [now - 20, now - 10, now + 60].map(|activates_at| {
+            JwtKey::generate_record(Algorithm::HS256, activates_at, &key_encryption_key()).unwrap()
+        });
+
+        // One replica saw the first key before the others were added
+        let mut replica = keyring(JwtKey::from_secret(b"secret"));
+        replica.sync(&records[..1]);
+        replica.sync(&records);
+        let mut other_replica = keyring(JwtKey::from_secret(b"secret"));
+        other_replica.sync(&records);
+
+        // Both sign with the latest key that is due
+        assert_eq!(replica.active_kid(), records[1].kid);
+        assert_eq!(other_replica.active_kid(), records[1].kid);
+
+        // The key it replaced only verifies the tokens it signed
+        for keyring in [&replica, &other_replica] {
+            assert!(keyring.find(&records[0].kid).unwrap().retired_at.is_some());
+            let old_keyring = JwtKeyring::new(
+                JwtKey::from_record(&records[0], &key_encryption_key()).unwrap(),
+                &JwtSettings::default(),
+            );
+            let old_token = old_keyring.encode(&claims()).unwrap();
+            assert!(keyring.decode::<TestClaims>(&old_token).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_sync_skips_expired_and_invalid_keys() {
+        let now = Utc::now().timestamp();
+        let record = |algorithm, activates_at| {
+            JwtKey::generate_record(algorithm, activates_at, &key_encryption_key()).unwrap()
+        };
+        let expired = record(Algorithm::HS256, now - 2000);
+        let replacement = record(Algorithm::HS256, now - 1000);
+        // The kid does not match the private key
+        let mismatched = JwtKeyRecord {
+            kid: "other".to_owned(),
+            ..record(Algorithm::EdDSA, now - 1)
+        };
+        // Encrypted with the key of another deployment
+        let foreign = JwtKeyRecord {
+            kid: "foreign".to_owned(),
+            ..JwtKey::generate_record(
+                Algorithm::HS256,
+                now - 1,
+                &KeyEncryptionKey::parse(&URL_SAFE_NO_PAD.encode([1u8; 32])).unwrap(),
+            )
+            .unwrap()
+        };
+        let records = [expired.clone(), replacement.clone(), mismatched, foreign];
+
+        let mut keyring = keyring(JwtKey::from_secret(b"secret"));
+        assert_eq!(
+            keyring.expired_before(&records),
+            Some(replacement.activates_at)
+        );
+
+        keyring.sync(&records);
+        assert_eq!(keyring.active_kid(), replacement.kid);
+        assert!(keyring.find(&expired.kid).is_none());
+        assert!(keyring.find("other").is_none());
+        assert!(keyring.find("foreign").is_none());
+    }
+
+    #[test]
+    fn test_issuer_audience_and_not_before_are_enforced() {
+        let keyring = keyring(JwtKey::from_secret(b"secret"));
+        let now = Utc::now().timestamp() as usize;
+
+        type Modify = fn(&mut TestClaims, usize);
+
+        let cases: [(&str, Modify); 4] = [
+            ("issuer", |c, _| c.iss = "other-service".to_owned()),
+            ("audience", |c, _| c.aud = vec!["other-service".to_owned()]),
+            ("not before", |c, now| c.nbf = now + 600),
+            ("expiry", |c, now| c.exp = now - 600),
+        ];
+
+        for (case, modify) in cases {
+            let mut claims = claims();
+            modify(&mut claims, now);
+            let token = keyring.encode(&claims).unwrap();
+            assert!(
+                keyring.decode::<TestClaims>(&token).is_err(),
+                "Failed for input: {:?}",
+                case
+            );
+        }
+
+        // Expiry and not-before tolerate clock skew up to the leeway
+        let mut claims = claims();
+        claims.nbf = now + 10;
+        claims.exp = now - 10;
+        let token = keyring.encode(&claims).unwrap();
+        assert!(keyring.decode::<TestClaims>(&token).is_ok());
+    }
+
+    #[test]
+    fn test_decode_for_audience() {
+        let keyring = keyring(JwtKey::from_secret(b"secret"));
+        let mut claims = claims();
+        claims.aud = vec!["app-service".to_owned(), "billing-service".to_owned()];
+        let token = keyring.encode(&claims).unwrap();
+
+        assert!(keyring
+            .decode_for_audience::<TestClaims>(&token, "billing-service")
+            .is_ok());
+        assert!(keyring
+            .decode_for_audience::<TestClaims>(&token, "other-service")
+            .is_err());
+    }
+
+    #[test]
+    fn test_add_and_promote_errors() {
+        let mut keyring = keyring(JwtKey::from_secret(b"secret"));
+
+        let key = JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap();
+        keyring.add_key(key).unwrap();
+        let result = keyring.add_key(JwtKey::from_ed_pem(ED25519_PRIVATE_KEY).unwrap());
+        assert_eq!(result, Err(JwtKeyringError::KeyAlreadyExists));
+
+        let result = keyring.promote("unknown");
+        assert_eq!(result, Err(JwtKeyringError::KeyNotFound));
+    }
+
+    #[test]
+    fn test_from_settings_loads_retired_keys() {
+        let mut settings = settings(JwtAlgorithm::HS256, "unused.pem");
+        settings.retired_keys = vec![
+            JwtKeySettings {
+                algorithm: JwtAlgorithm::EdDSA,
+                secret: String::new(),
+                private_key_path: Some("tests/fixtures/ed25519_private_key.pem".to_owned()),
+            },
+            JwtKeySettings {
+                algorithm: JwtAlgorithm::HS256,
+                secret: "old-secret".to_owned(),
+                private_key_path: None,
+            },
+        ];
+
+        let keyring = JwtKeyring::from_settings(&settings).unwrap();
+        assert_eq!(keyring.active_algorithm(), Algorithm::HS256);
+        assert_eq!(keyring.jwks().keys.len(), 1);
+
+        let old_keyring = JwtKeyring::new(JwtKey::from_secret(b"old-secret"), &settings);
+        let old_token = old_keyring.encode(&claims()).unwrap();
+        assert!(keyring.decode::<TestClaims>(&old_token).is_ok());
+
+        // The active key can not also be listed as retired
+        settings.retired_keys[1].secret = "secret".to_owned();
+        assert!(matches!(
+            JwtKeyring::from_settings(&settings),
+            Err(SettingsError::InvalidValue {
+                setting: "jwt.retired_keys",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_from_settings_rejects_mismatched_or_missing_keys() {
+        let result = JwtKeyring::from_settings(&settings(
+            JwtAlgorithm::EdDSA,
+            "tests/fixtures/rsa_private_key.pem",
+        ));
+        assert!(matches!(
+            result,
+            Err(SettingsError::InvalidValue {
+                setting: "jwt.private_key_path",
+                ..
+            })
+        ));
+
+        let result =
+            JwtKeyring::from_settings(&settings(JwtAlgorithm::RS256, "/does/not/exist.pem"));
+        assert!(matches!(result, Err(SettingsError::ReadFile { .. })));
+
+        let result = JwtKeyring::from_settings(&settings(
+            JwtAlgorithm::RS256,
+            "tests/fixtures/rsa_private_key.pem",
+        ));
+        assert!(result.is_ok());
+    }
+}
@@ -1,3 +1,11 @@
-pub mod constants;
+pub mod account_purge;
 pub mod auth;
-
+pub mod client_ip;
+pub mod constants;
+pub mod email_links;
+pub mod jwt_key_sync;
+pub mod jwt_keys;
+pub mod rate_limit;
+pub mod reauthentication;
+pub mod two_fa;
+pub mod webauthn;
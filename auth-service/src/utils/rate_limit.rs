@@ -0,0 +1,118 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        verify_user_password, AuthAPIError, Email, Password, RateLimitDecision, RateLimitParams,
+        UserStoreError,
+    },
+    utils::client_ip::client_ip,
+};
+
+// Token bucket per client IP. Buckets live in the rate limit store, so
+// every replica draws from the same bucket. Behind trusted proxies the
+// client IP comes from their forwarding headers.
+pub async fn rate_limit_by_ip(
+    State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthAPIError> {
+    let ip = client_ip(address, request.headers(), &state.settings.trusted_proxies);
+
+    let decision = state
+        .rate_limit_store
+        .write()
+        .await
+        .take_token(&ip.to_string(), state.settings.rate_limit)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    match decision {
+        RateLimitDecision::Allowed => Ok(next.run(request).await),
+        RateLimitDecision::Limited {
+            retry_after_seconds,
+        } => Err(AuthAPIError::TooManyRequests {
+            retry_after_seconds,
+        }),
+    }
+}
+
+// Token bucket per email address for routes that send emails, so nobody can
+// flood someone else's inbox from many IPs
+pub async fn rate_limit_emails_to(
+    state: &AppState,
+    route: &str,
+    email: &Email,
+    params: RateLimitParams,
+) -> Result<(), AuthAPIError> {
+    let key = format!("{}:{}", route, email.as_ref());
+    let decision = state
+        .rate_limit_store
+        .write()
+        .await
+        .take_token(&key, params)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    match decision {
+        RateLimitDecision::Allowed => Ok(()),
+        RateLimitDecision::Limited {
+            retry_after_seconds,
+        } => Err(AuthAPIError::TooManyRequests {
+            retry_after_seconds,
+        }),
+    }
+}
+
+// Check a user's password, under the per-account lockout. Unknown emails
+// count as failures too, so responses do not reveal which accounts exist.
+pub async fn verify_password_with_lockout(
+    state: &AppState,
+    email: &Email,
+    password: &Password,
+) -> Result<(), AuthAPIError> {
+    // The attempt is counted before the password is checked, so parallel
+    // guesses can not all get in before the account is locked. A locked
+    // account rejects even the correct password until the lockout ends.
+    let decision = state
+        .rate_limit_store
+        .write()
+        .await
+        .record_login_attempt(email, state.settings.login_lockout)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    if let RateLimitDecision::Limited {
+        retry_after_seconds,
+    } = decision
+    {
+        return Err(AuthAPIError::TooManyRequests {
+            retry_after_seconds,
+        });
+    }
+
+    // Don't hold the user store lock while hashing the password
+    let user = state.user_store.read().await.get_user(email).await;
+    match verify_user_password(user, password).await {
+        Ok(()) => state
+            .rate_limit_store
+            .write()
+            .await
+            .reset_login_failures(email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError),
+        // Already counted as a failure
+        Err(UserStoreError::UserNotFound | UserStoreError::InvalidCredentials) => {
+            Err(AuthAPIError::IncorrectCredentials)
+        }
+        // The store failing says nothing about the password
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
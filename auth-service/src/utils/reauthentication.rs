@@ -0,0 +1,63 @@
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId, Password, UserStoreError},
+    utils::{
+        rate_limit::verify_password_with_lockout,
+        two_fa::{verify_2fa_code, TwoFactorProof},
+    },
+};
+
+// How a logged in user confirms a sensitive change, so a stolen cookie alone
+// is not enough. That is their password, or with password login turned off,
+// the answer to a challenge from /reauthenticate.
+#[derive(Deserialize, Default)]
+pub struct Reauthentication {
+    pub password: Option<String>,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+pub async fn verify_reauthentication(
+    state: &AppState,
+    email: &Email,
+    reauthentication: Reauthentication,
+) -> Result<(), AuthAPIError> {
+    match (
+        state.settings.password_login_enabled,
+        reauthentication.password,
+        reauthentication.login_attempt_id,
+        reauthentication.two_fa_code,
+    ) {
+        (true, Some(password), None, None) => {
+            let password =
+                Password::parse(password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            verify_password_with_lockout(state, email, &password).await
+        }
+        // The password can't sign in, so it doesn't confirm anything either
+        (false, Some(_), _, _) => Err(AuthAPIError::PasswordLoginDisabled),
+        (false, None, Some(login_attempt_id), Some(two_fa_code)) => {
+            let login_attempt_id = LoginAttemptId::parse(login_attempt_id)
+                .map_err(|_| AuthAPIError::InvalidCredentials)?;
+            let proof =
+                TwoFactorProof::parse(two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+            let user = state
+                .user_store
+                .read()
+                .await
+                .get_user(email)
+                .await
+                .map_err(|e| match e {
+                    UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                    _ => AuthAPIError::UnexpectedError,
+                })?;
+
+            verify_2fa_code(state, &user, login_attempt_id, proof).await
+        }
+        _ => Err(AuthAPIError::InvalidCredentials),
+    }
+}
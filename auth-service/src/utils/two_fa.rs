@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, LoginAttemptId, RecoveryCode, TwoFACode, TwoFACodeStoreError, User,
+        UserStoreError,
+    },
+};
+
+// Where the user gets their 2FA code from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFactorMethod {
+    Email,
+    Totp,
+}
+
+// What a 2FA challenge can be answered with
+#[derive(Debug, Clone, PartialEq)]
+pub enum TwoFactorProof {
+    Code(TwoFACode),
+    // For users who have lost their second factor
+    RecoveryCode(RecoveryCode),
+}
+
+impl TwoFactorProof {
+    pub fn parse(proof: String) -> Result<Self, String> {
+        match TwoFACode::parse(proof.clone()) {
+            Ok(code) => Ok(Self::Code(code)),
+            Err(_) => RecoveryCode::parse(proof).map(Self::RecoveryCode),
+        }
+    }
+}
+
+// Start a 2FA challenge for `user`. Users with an authenticator app take the
+// code from it; everyone else is emailed one, with `content` built from it.
+pub async fn start_2fa_challenge(
+    state: &AppState,
+    user: &User,
+    subject: &str,
+    content: impl FnOnce(&TwoFACode) -> String,
+) -> Result<(LoginAttemptId, TwoFactorMethod), AuthAPIError> {
+    let login_attempt_id = LoginAttemptId::default();
+    // Never sent to users with an authenticator app, but storing it keeps
+    // the login attempt ID single-use for them too
+    let two_fa_code = TwoFACode::default();
+
+    // Store the ID and code so they can be checked by `verify_2fa_code`
+    state
+        .two_fa_code_store
+        .write()
+        .await
+        .add_code(
+            user.email.clone(),
+            login_attempt_id.clone(),
+            two_fa_code.clone(),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    if user.totp_secret.is_some() {
+        return Ok((login_attempt_id, TwoFactorMethod::Totp));
+    }
+
+    state
+        .email_client
+        .send_email(&user.email, subject, &content(&two_fa_code))
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok((login_attempt_id, TwoFactorMethod::Email))
+}
+
+// Check the answer to a challenge from `start_2fa_challenge`, which can only
+// be completed once
+pub async fn verify_2fa_code(
+    state: &AppState,
+    user: &User,
+    login_attempt_id: LoginAttemptId,
+    proof: TwoFactorProof,
+) -> Result<(), AuthAPIError> {
+    // Look up the pending challenge for this email. The store is unlocked
+    // again before the user store is locked, so the two are never held
+    // together.
+    let stored_code = {
+        let two_fa_code_store = state.two_fa_code_store.read().await;
+        let (stored_id, stored_code) = two_fa_code_store
+            .get_code(&user.email)
+            .await
+            .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+        if stored_id != login_attempt_id {
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+        stored_code
+    };
+
+    let verified = match (proof, &user.totp_secret) {
+        (TwoFactorProof::Code(code), Some(totp_secret)) => match totp_secret.verify(&code) {
+            // Codes from the app stay valid for a while, so each one is only
+            // accepted once, or a code seen over the user's shoulder could be
+            // used again
+            Some(time_step) => match state
+                .user_store
+                .write()
+                .await
+                .use_totp_step(&user.email, time_step)
+                .await
+            {
+                Ok(()) => true,
+                Err(UserStoreError::InvalidCredentials) => false,
+                Err(_) => return Err(AuthAPIError::UnexpectedError),
+            },
+            None => false,
+        },
+        (TwoFactorProof::Code(code), None) => code == stored_code,
+        (TwoFactorProof::RecoveryCode(recovery_code), _) => match state
+            .user_store
+            .write()
+            .await
+            .use_recovery_code(&user.email, &recovery_code.hash())
+            .await
+        {
+            Ok(()) => true,
+            Err(UserStoreError::InvalidCredentials) => false,
+            Err(_) => return Err(AuthAPIError::UnexpectedError),
+        },
+    };
+
+    // Another request may have settled the challenge in the meantime, and
+    // only the first one to get here completes it
+    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+    match two_fa_code_store.get_code(&user.email).await {
+        Ok((stored_id, _)) if stored_id == login_attempt_id => {}
+        _ => return Err(AuthAPIError::IncorrectCredentials),
+    }
+
+    if !verified {
+        let failed_attempts = two_fa_code_store
+            .record_failed_attempt(&user.email)
+            .await
+            .map_err(|e| match e {
+                TwoFACodeStoreError::LoginAttemptIdNotFound => AuthAPIError::IncorrectCredentials,
+                TwoFACodeStoreError::UnexpectedError => AuthAPIError::UnexpectedError,
+            })?;
+
+        // A six digit code must not be guessable within its lifetime, so too
+        // many wrong answers end the challenge and the user logs in again
+        if failed_attempts >= state.settings.two_fa_max_failed_attempts {
+            two_fa_code_store
+                .remove_code(&user.email)
+                .await
+                .map_err(|_| AuthAPIError::UnexpectedError)?;
+        }
+
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // Remove the code so it can only be used once
+    two_fa_code_store
+        .remove_code(&user.email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
@@ -0,0 +1,97 @@
+use std::sync::OnceLock;
+
+use rand::RngCore;
+use webauthn_rs::{
+    fake::{FakePasskeyDistribution, WebauthnFakeCredentialGenerator},
+    prelude::{RequestChallengeResponse, Url, Webauthn, WebauthnBuilder, WebauthnError},
+    DEFAULT_AUTHENTICATOR_TIMEOUT,
+};
+use webauthn_rs_proto::{
+    AllowCredentials, AuthenticatorTransport, PublicKeyCredentialRequestOptions,
+    UserVerificationPolicy,
+};
+
+use crate::{domain::Email, settings::Settings};
+
+// Browsers only hand a passkey to pages on the origin it was registered for,
+// which is where users reach this service
+pub fn build_webauthn(settings: &Settings) -> Result<Webauthn, WebauthnError> {
+    let rp_origin = Url::parse(&settings.public_url).map_err(|_| WebauthnError::Configuration)?;
+
+    WebauthnBuilder::new(&rp_id(settings)?, &rp_origin)?
+        .rp_name(&settings.webauthn.rp_name)
+        .build()
+}
+
+// Sign-in options for an address without passkeys, offering made-up
+// credentials in place of real ones, so that the answer doesn't tell whether
+// the address has an account. The same address is always offered the same
+// credentials, and some are offered none, as some users have no passkeys.
+pub fn fake_login_options(
+    settings: &Settings,
+    email: &Email,
+) -> Result<Option<RequestChallengeResponse>, WebauthnError> {
+    let generator = WebauthnFakeCredentialGenerator::<FakePasskeyDistribution>::new(
+        fake_credential_key(settings),
+    )?;
+    let credential_ids = generator.generate(email.as_ref().as_bytes())?;
+    if credential_ids.is_empty() {
+        return Ok(None);
+    }
+
+    let mut challenge = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut challenge);
+
+    // Built like the options of a real sign-in, with the transports platform
+    // authenticators report for synced passkeys
+    let allow_credentials = credential_ids
+        .into_iter()
+        .map(|id| AllowCredentials {
+            type_: "public-key".to_owned(),
+            id: id.into(),
+            transports: Some(vec![
+                AuthenticatorTransport::Hybrid,
+                AuthenticatorTransport::Internal,
+            ]),
+        })
+        .collect();
+
+    Ok(Some(RequestChallengeResponse {
+        public_key: PublicKeyCredentialRequestOptions {
+            challenge: challenge.into(),
+            timeout: Some(DEFAULT_AUTHENTICATOR_TIMEOUT.as_millis() as u32),
+            rp_id: rp_id(settings)?,
+            allow_credentials,
+            user_verification: UserVerificationPolicy::Required,
+            hints: None,
+            extensions: None,
+        },
+        mediation: None,
+    }))
+}
+
+fn rp_id(settings: &Settings) -> Result<String, WebauthnError> {
+    match &settings.webauthn.rp_id {
+        Some(rp_id) => Ok(rp_id.clone()),
+        None => Url::parse(&settings.public_url)
+            .map_err(|_| WebauthnError::Configuration)?
+            .domain()
+            .map(str::to_owned)
+            .ok_or(WebauthnError::Configuration),
+    }
+}
+
+// Without a configured key, made-up credentials change on every restart and
+// differ between replicas
+fn fake_credential_key(settings: &Settings) -> &[u8] {
+    static RANDOM_KEY: OnceLock<[u8; 32]> = OnceLock::new();
+
+    match &settings.webauthn.fake_credential_key {
+        Some(key) => key.as_bytes(),
+        None => RANDOM_KEY.get_or_init(|| {
+            let mut key = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        }),
+    }
+}
@@ -0,0 +1,198 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{routes::ChangePasswordResponse, ErrorResponse};
+
+struct Session {
+    auth_token: String,
+    refresh_token: String,
+}
+
+fn get_cookie(response: &reqwest::Response, name: &str) -> String {
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .expect("Cookie not found");
+    cookie.value().to_owned()
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> Session {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    Session {
+        auth_token: get_cookie(&response, &app.settings.jwt.cookie_name),
+        refresh_token: get_cookie(&response, &app.settings.jwt.refresh_cookie_name),
+    }
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let change_body = serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "newpassword123",
+    });
+    let response = app.post_change_password(&change_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_update_password() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+    let session = login(&app, &random_email, "password123").await;
+
+    let change_body = serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "newpassword123",
+    });
+    let response = app.post_change_password(&change_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The current session gets fresh cookies
+    let new_auth_token = get_cookie(&response, &app.settings.jwt.cookie_name);
+    get_cookie(&response, &app.settings.jwt.refresh_cookie_name);
+    assert_ne!(new_auth_token, session.auth_token);
+    assert_eq!(
+        response
+            .json::<ChangePasswordResponse>()
+            .await
+            .expect("Could not deserialize response body to ChangePasswordResponse")
+            .message,
+        "Password updated".to_owned()
+    );
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": new_auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The token used to change the password has been replaced
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": session.auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    login(&app, &random_email, "newpassword123").await;
+}
+
+#[tokio::test]
+async fn should_invalidate_other_sessions() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    // Each login starts a session; the cookie jar keeps the last one
+    let other_session = login(&app, &random_email, "password123").await;
+    login(&app, &random_email, "password123").await;
+
+    let change_body = serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "newpassword123",
+    });
+    let response = app.post_change_password(&change_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The current session can still be refreshed
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Neither its access token nor its refresh token work any more
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": other_session.auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.set_refresh_token(&other_session.refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_incorrect() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+    login(&app, &random_email, "password123").await;
+
+    let change_body = serde_json::json!({
+        "currentPassword": "wrong-password",
+        "newPassword": "newpassword123",
+    });
+    let response = app.post_change_password(&change_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+
+    // The password was not changed
+    login(&app, &random_email, "password123").await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+    login(&app, &random_email, "password123").await;
+
+    let test_cases = [
+        (
+            serde_json::json!({
+                "currentPassword": "password123",
+                "newPassword": "short",
+            }),
+            "Invalid credentials",
+        ),
+        (
+            serde_json::json!({
+                "currentPassword": "password123",
+                "newPassword": "password123",
+            }),
+            "New password must be different from the current password",
+        ),
+    ];
+
+    for (change_body, error) in test_cases {
+        let response = app.post_change_password(&change_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            change_body
+        );
+        assert_error(response, 400, error).await;
+    }
+}
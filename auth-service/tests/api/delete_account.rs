@@ -0,0 +1,252 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{
+    domain::Email, routes::TwoFactorAuthResponse, settings::AccountDeletionSettings, ErrorResponse,
+};
+
+struct Session {
+    auth_token: String,
+    refresh_token: String,
+}
+
+fn get_cookie(response: &reqwest::Response, name: &str) -> String {
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .expect("Cookie not found");
+    cookie.value().to_owned()
+}
+
+async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn login(app: &TestApp, email: &str) -> Session {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    Session {
+        auth_token: get_cookie(&response, &app.settings.jwt.cookie_name),
+        refresh_token: get_cookie(&response, &app.settings.jwt.refresh_cookie_name),
+    }
+}
+
+// Log in a 2FA user by following the emailed code
+async fn login_with_2fa(app: &TestApp, email: &str) {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+
+    let code = sent_code(app, email).await;
+    let verify_2fa_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn sent_code(app: &TestApp, email: &str) -> String {
+    let email = Email::parse(email.to_owned()).unwrap();
+    let content = app
+        .email_client
+        .last_email_to(&email)
+        .await
+        .unwrap()
+        .content;
+    // The code is the last word of the email
+    content.rsplit(' ').next().unwrap().to_owned()
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let delete_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_delete_account() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email, false).await;
+    let other_session = login(&app, &random_email).await;
+    let session = login(&app, &random_email).await;
+
+    let delete_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert!(get_cookie(&response, &app.settings.jwt.cookie_name).is_empty());
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Every session's tokens are revoked
+    for session in [&session, &other_session] {
+        let verify_token_body = serde_json::json!({ "token": session.auth_token });
+        let response = app.post_verify_token(&verify_token_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        app.set_refresh_token(&session.refresh_token);
+        let response = app.post_refresh().await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The address stays taken during the grace period
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email, false).await;
+    login(&app, &random_email).await;
+
+    let delete_body = serde_json::json!({ "password": "wrong-password" });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+
+    // The account still exists
+    login(&app, &random_email).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email, false).await;
+    login(&app, &random_email).await;
+
+    let test_cases = [
+        serde_json::json!({}),
+        serde_json::json!({ "password": "short" }),
+        // Only users with 2FA confirm with a code
+        serde_json::json!({
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "2FACode": "123456",
+        }),
+    ];
+
+    for delete_body in test_cases {
+        let response = app.post_delete_account(&delete_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            delete_body
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_require_fresh_2fa_code_for_2fa_users() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email, true).await;
+    login_with_2fa(&app, &random_email).await;
+
+    let delete_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code = sent_code(&app, &random_email).await;
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let delete_body = serde_json::json!({
+        "loginAttemptId": login_attempt_id,
+        "2FACode": wrong_code,
+    });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+
+    let delete_body = serde_json::json!({
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_purge_account_after_grace_period() {
+    let mut settings = test_settings();
+    settings.account_deletion = AccountDeletionSettings {
+        grace_period_seconds: 0,
+        purge_interval_seconds: 1,
+    };
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+    signup(&app, &random_email, false).await;
+    login(&app, &random_email).await;
+
+    let delete_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    // Once purged, the address can be used again
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
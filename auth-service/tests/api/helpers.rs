@@ -1,29 +1,146 @@
 use auth_service::Application;
-use auth_service::app_state::AppState;
-use auth_service::services::HashmapUserStore;
+use auth_service::app_state::{
+    AppState, AuthorizationCodeStoreType, BannedTokenStoreType, EmailTokenStoreType,
+    JwtKeyStoreType, OAuthClientStoreType, PasskeyChallengeStoreType, PasskeyStoreType,
+    RateLimitStoreType, RefreshTokenStoreType, TwoFACodeStoreType, UserStoreType,
+};
+use auth_service::domain::{Email, KeyEncryptionKey, PasswordHashingParams, RateLimitParams};
+use auth_service::services::{
+    HashmapAuthorizationCodeStore, HashmapEmailTokenStore, HashmapPasskeyChallengeStore,
+    HashmapRateLimitStore, HashmapRefreshTokenStore, HashmapTwoFACodeStore,
+    HashsetBannedTokenStore, MockEmailClient, PostgresJwtKeyStore, PostgresOAuthClientStore,
+    PostgresPasskeyStore, PostgresUserStore, RedisAuthorizationCodeStore, RedisBannedTokenStore,
+    RedisEmailTokenStore, RedisPasskeyChallengeStore, RedisRateLimitStore, RedisRefreshTokenStore,
+    RedisTwoFACodeStore, SqliteJwtKeyStore, SqliteOAuthClientStore, SqlitePasskeyStore,
+    SqliteUserStore,
+};
+use auth_service::settings::{EmailVerificationSettings, JwtSettings, Settings};
+use auth_service::utils::constants::env::{TEST_DATABASE_URL_ENV_VAR, TEST_REDIS_URL_ENV_VAR};
+use auth_service::utils::{jwt_keys::JwtKeyring, webauthn::build_webauthn};
+use auth_service::{
+    get_redis_connection, get_sqlite_pool, run_postgres_migrations, run_sqlite_migrations,
+};
+use reqwest::cookie::Jar;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 pub struct TestApp {
     pub address: String,
+    pub cookie_jar: Arc<Jar>,
+    pub banned_token_store: BannedTokenStoreType,
+    pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub jwt_key_store: JwtKeyStoreType,
+    pub email_client: Arc<MockEmailClient>,
+    pub settings: Arc<Settings>,
     pub http_client: reqwest::Client,
+    // Only set when running against Postgres
+    db_schema: Option<String>,
+}
+
+// Every test app gets its own JWT secret, so tokens never carry over
+// between apps
+pub fn test_settings() -> Settings {
+    Settings {
+        address: "127.0.0.1:0".to_owned(),
+        jwt: JwtSettings {
+            secret: format!("test-secret-{}", Uuid::new_v4()),
+            ..JwtSettings::default()
+        },
+        // Tests that check rate limiting set their own limits
+        rate_limit: RateLimitParams {
+            burst: 1000,
+            per_minute: 1000,
+        },
+        // Most tests are not about email verification, so their users can
+        // log in straight after signup
+        email_verification: EmailVerificationSettings {
+            required: false,
+            ..EmailVerificationSettings::default()
+        },
+        // Cheap parameters so the tests stay fast
+        password_hashing: PasswordHashingParams {
+            memory_cost_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        },
+        ..Settings::default()
+    }
 }
 
 impl TestApp {
     pub async fn new() -> Self {
-        let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-        let app_state = AppState::new(user_store);
+        Self::with_settings(test_settings()).await
+    }
 
-        let app = Application::build(app_state, "127.0.0.1:0")
+    pub async fn with_settings(settings: Settings) -> Self {
+        settings.validate().expect("Invalid test settings");
+        let jwt_keyring = Arc::new(RwLock::new(
+            JwtKeyring::from_settings(&settings.jwt).expect("Invalid test keys"),
+        ));
+        let webauthn = Arc::new(build_webauthn(&settings).expect("Invalid test WebAuthn settings"));
+        let settings = Arc::new(settings);
+
+        let (user_store, passkey_store, oauth_client_store, jwt_key_store, db_schema) =
+            configure_user_stores().await;
+        let (
+            banned_token_store,
+            two_fa_code_store,
+            refresh_token_store,
+            rate_limit_store,
+            email_token_store,
+            passkey_challenge_store,
+            authorization_code_store,
+        ) = configure_token_stores(&settings).await;
+        let email_client = Arc::new(MockEmailClient::default());
+        let app_state = AppState::new(
+            user_store,
+            passkey_store,
+            oauth_client_store,
+            jwt_key_store.clone(),
+            banned_token_store.clone(),
+            two_fa_code_store.clone(),
+            refresh_token_store.clone(),
+            rate_limit_store,
+            email_token_store,
+            passkey_challenge_store,
+            authorization_code_store,
+            email_client.clone(),
+            jwt_keyring,
+            webauthn,
+            settings.clone(),
+        );
+
+        let app = Application::build(app_state, &settings)
             .await
             .expect("Failed to build test app");
         let address = format!("http://{}", app.address.clone());
 
-        #[allow(clippy::let_underscore_drop)]
+        #[allow(clippy::let_underscore_future)]
         let _ = tokio::spawn(app.run());
-        let http_client = reqwest::Client::new();
-        Self { address, http_client }
+        let cookie_jar = Arc::new(Jar::default());
+        let http_client = reqwest::Client::builder()
+            .cookie_provider(cookie_jar.clone())
+            .build()
+            .unwrap();
+
+        Self {
+            address,
+            cookie_jar,
+            banned_token_store,
+            two_fa_code_store,
+            refresh_token_store,
+            jwt_key_store,
+            email_client,
+            settings,
+            http_client,
+            db_schema,
+        }
     }
 
     pub async fn get_root(&self) -> reqwest::Response {
@@ -51,7 +168,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .json(body)
             .send()
             .await
@@ -66,24 +183,557 @@ impl TestApp {
         .expect("Failed to execute logout")
     }
 
-    pub async fn post_verify_token(&self) -> reqwest::Response {
+    pub async fn post_refresh(&self) -> reqwest::Response {
         self.http_client
-        .post(format!("{}/verify-token", &self.address))
-        .send()
-        .await
-        .expect("Failed to execute verify-token")
+            .post(format!("{}/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute refresh")
+    }
+
+    // Put a refresh token into the cookie jar, replacing the current one
+    pub fn set_refresh_token(&self, token: &str) {
+        let url = reqwest::Url::parse(&self.address).expect("Failed to parse URL");
+        self.cookie_jar.add_cookie_str(
+            &format!(
+                "{}={}; HttpOnly; SameSite=Strict; Path=/refresh",
+                self.settings.jwt.refresh_cookie_name, token
+            ),
+            &url,
+        );
     }
 
-    pub async fn post_verify_2fa(&self) -> reqwest::Response {
+    pub async fn get_jwks(&self) -> reqwest::Response {
         self.http_client
-        .post(format!("{}/verify-2fa", &self.address))
-        .send()
-        .await
-        .expect("Failed to execute verify-2fa")
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute jwks")
+    }
+
+    pub async fn post_rotate_signing_key(
+        &self,
+        admin_api_token: Option<&str>,
+    ) -> reqwest::Response {
+        let mut request = self
+            .http_client
+            .post(format!("{}/admin/rotate-signing-key", &self.address));
+
+        if let Some(admin_api_token) = admin_api_token {
+            request = request.bearer_auth(admin_api_token);
+        }
+
+        request
+            .send()
+            .await
+            .expect("Failed to execute rotate-signing-key")
+    }
+
+    pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-token", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute verify-token")
+    }
+
+    pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute verify-2fa")
+    }
+
+    pub async fn get_verify_email(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/verify-email", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute verify-email")
+    }
+
+    pub async fn post_resend_verification<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/resend-verification", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute resend-verification")
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/forgot-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute forgot-password")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/reset-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute reset-password")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute change-password")
+    }
+
+    pub async fn post_delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/delete-account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute delete-account")
+    }
+
+    pub async fn post_reauthenticate(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/reauthenticate", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute reauthenticate")
+    }
+
+    pub async fn post_totp_enroll<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/totp/enroll", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute totp enroll")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute totp confirm")
+    }
+
+    pub async fn get_recovery_codes(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/recovery-codes", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute get recovery-codes")
+    }
+
+    pub async fn post_recovery_codes<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/recovery-codes", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute post recovery-codes")
     }
 
+    pub async fn post_passkey_register_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkey/register/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute passkey register start")
+    }
+
+    pub async fn post_passkey_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkey/register/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute passkey register finish")
+    }
+
+    pub async fn post_passkey_login_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkey/login/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute passkey login start")
+    }
+
+    pub async fn post_passkey_login_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkey/login/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute passkey login finish")
+    }
+
+    pub async fn post_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute magic link request")
+    }
+
+    pub async fn post_verify_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link/verify", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute magic link verify request")
+    }
+
+    pub async fn post_register_oauth_client<Body>(
+        &self,
+        body: &Body,
+        admin_api_token: &str,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/oauth-clients", &self.address))
+            .bearer_auth(admin_api_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute register oauth client")
+    }
+
+    // Redirects are not followed, so tests can check where users are sent
+    pub async fn get_authorize<Query>(&self, query: &Query) -> reqwest::Response
+    where
+        Query: serde::Serialize,
+    {
+        reqwest::Client::builder()
+            .cookie_provider(self.cookie_jar.clone())
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}/authorize", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute authorize")
+    }
+
+    pub async fn get_consent<Query>(&self, query: &Query) -> reqwest::Response
+    where
+        Query: serde::Serialize,
+    {
+        self.http_client
+            .get(format!("{}/authorize/consent", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute get consent")
+    }
+
+    pub async fn post_consent<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/authorize/consent", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute post consent")
+    }
+
+    pub async fn post_token<Form>(&self, form: &Form) -> reqwest::Response
+    where
+        Form: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/token", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute token")
+    }
+
+    // Reset and login links are sent in the background, so tests wait for
+    // the last email to `email` to have the expected subject
+    pub async fn wait_for_email_to(&self, email: &str, subject: &str) {
+        let email = Email::parse(email.to_owned()).unwrap();
+        for _ in 0..50 {
+            let sent = self.email_client.last_email_to(&email).await;
+            if sent.is_some_and(|sent| sent.subject == subject) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("No email with subject {:?} was sent", subject);
+    }
+
+    // Waits until `count` emails were sent in total, for emails sent in the
+    // background to an address that already got one
+    pub async fn wait_for_sent_emails(&self, count: usize) {
+        for _ in 0..50 {
+            if self.email_client.sent_emails().await.len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "Only {} emails were sent",
+            self.email_client.sent_emails().await.len()
+        );
+    }
+
+    // The token from the last link (verification, password reset or login) sent to `email`
+    pub async fn link_token_sent_to(&self, email: &str) -> String {
+        let email = Email::parse(email.to_owned()).unwrap();
+        let sent = self
+            .email_client
+            .last_email_to(&email)
+            .await
+            .expect("No email was sent");
+        sent.content
+            .split("token=")
+            .nth(1)
+            .expect("No link in email")
+            .to_owned()
+    }
+}
+
+impl Drop for TestApp {
+    // Drop is synchronous, so the schema is removed from a separate thread
+    // with its own runtime
+    fn drop(&mut self) {
+        let Some(db_schema) = self.db_schema.take() else {
+            return;
+        };
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .expect("Failed to create runtime")
+                .block_on(delete_schema(&db_schema));
+        })
+        .join()
+        .expect("Failed to delete test schema");
+    }
 }
 
 pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
-}
\ No newline at end of file
+}
+
+// Tests run against a private in-memory SQLite database by default. Setting
+// TEST_DATABASE_URL runs them against Postgres instead, with a throwaway
+// schema per test app. TOTP secrets are encrypted with a fixed test key.
+async fn configure_user_stores() -> (
+    UserStoreType,
+    PasskeyStoreType,
+    OAuthClientStoreType,
+    JwtKeyStoreType,
+    Option<String>,
+) {
+    let key_encryption_key =
+        KeyEncryptionKey::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8").unwrap();
+
+    match std::env::var(TEST_DATABASE_URL_ENV_VAR) {
+        Ok(url) => {
+            let db_schema = format!("test_{}", Uuid::new_v4().simple());
+            let pg_pool = configure_postgresql(&url, &db_schema).await;
+            let user_store = Arc::new(RwLock::new(PostgresUserStore::new(
+                pg_pool.clone(),
+                key_encryption_key,
+            )));
+            let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+            let oauth_client_store =
+                Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool.clone())));
+            let jwt_key_store = Arc::new(RwLock::new(PostgresJwtKeyStore::new(pg_pool)));
+            (
+                user_store,
+                passkey_store,
+                oauth_client_store,
+                jwt_key_store,
+                Some(db_schema),
+            )
+        }
+        Err(_) => {
+            let sqlite_pool = get_sqlite_pool("sqlite::memory:")
+                .await
+                .expect("Failed to create SQLite connection pool!");
+
+            run_sqlite_migrations(&sqlite_pool)
+                .await
+                .expect("Failed to migrate the database");
+
+            let user_store = Arc::new(RwLock::new(SqliteUserStore::new(
+                sqlite_pool.clone(),
+                key_encryption_key,
+            )));
+            let passkey_store = Arc::new(RwLock::new(SqlitePasskeyStore::new(sqlite_pool.clone())));
+            let oauth_client_store = Arc::new(RwLock::new(SqliteOAuthClientStore::new(
+                sqlite_pool.clone(),
+            )));
+            let jwt_key_store = Arc::new(RwLock::new(SqliteJwtKeyStore::new(sqlite_pool)));
+            (
+                user_store,
+                passkey_store,
+                oauth_client_store,
+                jwt_key_store,
+                None,
+            )
+        }
+    }
+}
+
+// In-memory stores by default, or a local redis-server when TEST_REDIS_URL is
+// set. Keys are unique per test (random emails and fresh tokens), so tests
+// can share one Redis database.
+async fn configure_token_stores(
+    settings: &Settings,
+) -> (
+    BannedTokenStoreType,
+    TwoFACodeStoreType,
+    RefreshTokenStoreType,
+    RateLimitStoreType,
+    EmailTokenStoreType,
+    PasskeyChallengeStoreType,
+    AuthorizationCodeStoreType,
+) {
+    match std::env::var(TEST_REDIS_URL_ENV_VAR) {
+        Ok(url) => {
+            let conn = get_redis_connection(&url)
+                .await
+                .expect("Failed to connect to Redis");
+
+            (
+                Arc::new(RwLock::new(RedisBannedTokenStore::new(
+                    conn.clone(),
+                    settings.jwt.token_ttl_seconds + settings.jwt.leeway_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisTwoFACodeStore::new(
+                    conn.clone(),
+                    settings.two_fa_code_ttl_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisRefreshTokenStore::new(
+                    conn.clone(),
+                    settings.jwt.refresh_token_ttl_seconds,
+                ))),
+                Arc::new(RwLock::new(RedisRateLimitStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisEmailTokenStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisPasskeyChallengeStore::new(conn.clone()))),
+                Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(conn))),
+            )
+        }
+        Err(_) => (
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::new(
+                settings.two_fa_code_ttl_seconds,
+            ))),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapRateLimitStore::default())),
+            Arc::new(RwLock::new(HashmapEmailTokenStore::default())),
+            Arc::new(RwLock::new(HashmapPasskeyChallengeStore::default())),
+            Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default())),
+        ),
+    }
+}
+
+// Create a fresh schema for this test app and migrate it, so tests never
+// see each other's data
+async fn configure_postgresql(url: &str, db_schema: &str) -> PgPool {
+    let mut connection = PgConnection::connect(url)
+        .await
+        .expect("Failed to connect to Postgres");
+
+    connection
+        .execute(format!(r#"CREATE SCHEMA "{}";"#, db_schema).as_str())
+        .await
+        .expect("Failed to create schema");
+
+    let options = PgConnectOptions::from_str(url)
+        .expect("Failed to parse Postgres connection string")
+        .options([("search_path", db_schema)]);
+
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+
+    run_postgres_migrations(&pool)
+        .await
+        .expect("Failed to migrate the schema");
+
+    pool
+}
+
+async fn delete_schema(db_schema: &str) {
+    let url = std::env::var(TEST_DATABASE_URL_ENV_VAR).expect("TEST_DATABASE_URL must be set.");
+    let mut connection = PgConnection::connect(&url)
+        .await
+        .expect("Failed to connect to Postgres");
+
+    connection
+        .execute(format!(r#"DROP SCHEMA "{}" CASCADE;"#, db_schema).as_str())
+        .await
+        .expect("Failed to drop schema");
+}
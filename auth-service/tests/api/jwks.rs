@@ -0,0 +1,127 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::settings::JwtAlgorithm;
+use jsonwebtoken::{jwk::JwkSet, DecodingKey, Validation};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+async fn app_with_algorithm(algorithm: JwtAlgorithm, private_key_path: &str) -> TestApp {
+    let mut settings = test_settings();
+    settings.jwt.algorithm = algorithm;
+    settings.jwt.private_key_path = Some(private_key_path.to_owned());
+    TestApp::with_settings(settings).await
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+#[tokio::test]
+async fn should_not_publish_hs256_secret() {
+    let app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let jwks = response
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    assert!(jwks.keys.is_empty());
+}
+
+#[tokio::test]
+async fn should_verify_tokens_with_published_key() {
+    let test_cases = [
+        (JwtAlgorithm::RS256, "tests/fixtures/rsa_private_key.pem"),
+        (
+            JwtAlgorithm::EdDSA,
+            "tests/fixtures/ed25519_private_key.pem",
+        ),
+    ];
+
+    for (algorithm, private_key_path) in test_cases {
+        let app = app_with_algorithm(algorithm, private_key_path).await;
+
+        let random_email = get_random_email();
+        let token = signup_and_login(&app, &random_email).await;
+
+        let jwks = app
+            .get_jwks()
+            .await
+            .json::<JwkSet>()
+            .await
+            .expect("Could not deserialize response body to JwkSet");
+        assert_eq!(jwks.keys.len(), 1, "Failed for input: {:?}", algorithm);
+
+        let jwk = &jwks.keys[0];
+        let decoding_key = DecodingKey::from_jwk(jwk).expect("Invalid JWK");
+        let algorithm = jwk
+            .common
+            .key_algorithm
+            .and_then(|key_algorithm| key_algorithm.to_string().parse().ok())
+            .expect("JWK has no algorithm");
+
+        // A downstream service checks it is the intended audience
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&app.settings.jwt.issuer]);
+        validation.set_audience(&["app-service"]);
+
+        let claims = jsonwebtoken::decode::<Claims>(&token, &decoding_key, &validation)
+            .expect("Token was not signed with the published key")
+            .claims;
+        assert_eq!(claims.sub, random_email);
+
+        // The auth service itself still accepts the token
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "Failed for input: {:?}",
+            algorithm
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_reject_tokens_signed_with_another_key() {
+    let app = app_with_algorithm(JwtAlgorithm::RS256, "tests/fixtures/rsa_private_key.pem").await;
+    let other_app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let token = signup_and_login(&other_app, &random_email).await;
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
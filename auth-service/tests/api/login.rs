@@ -1,10 +1,10 @@
-use crate::helpers::{get_random_email, TestApp};
-use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{domain::Email, routes::TwoFactorAuthResponse, ErrorResponse};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
     let app = TestApp::new().await;
-    
+
     let random_email = get_random_email();
 
     let test_cases = vec![
@@ -140,9 +140,235 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
 
     let auth_cookie = response
         .cookies()
-        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
 }
 
+#[tokio::test]
+async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert!(response
+        .cookies()
+        .all(|cookie| cookie.name() != app.settings.jwt.cookie_name));
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(json_body.message, "2FA required".to_owned());
+
+    let email = Email::parse(random_email).unwrap();
+
+    let (login_attempt_id, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&email)
+        .await
+        .expect("No 2FA code stored for login attempt");
+
+    assert_eq!(json_body.login_attempt_id, login_attempt_id.as_ref());
+
+    // The code should have been emailed to the user
+    let sent_email = app
+        .email_client
+        .last_email_to(&email)
+        .await
+        .expect("No 2FA email sent");
+
+    assert_eq!(sent_email.content, code.as_ref());
+}
+
+#[tokio::test]
+async fn should_use_configured_cookie_name() {
+    let mut settings = test_settings();
+    settings.jwt.cookie_name = "session".to_owned();
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert!(response.cookies().any(|cookie| cookie.name() == "session"));
+    assert!(response.cookies().all(|cookie| cookie.name() != "jwt"));
+}
+
+#[tokio::test]
+async fn should_return_429_if_account_locked_after_failed_logins() {
+    let mut settings = test_settings();
+    settings.login_lockout.max_failures = 3;
+    settings.login_lockout.lockout_seconds = 60;
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+
+    for _ in 0..3 {
+        let response = app.post_login(&wrong_login_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the correct password is rejected while the account is locked
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many requests".to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_lock_account_if_failed_logins_race() {
+    let mut settings = test_settings();
+    settings.login_lockout.max_failures = 3;
+    settings.login_lockout.lockout_seconds = 60;
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+
+    // Guesses sent at once are all in flight before any of them fails
+    let mut guesses = tokio::task::JoinSet::new();
+    for _ in 0..10 {
+        let request = app
+            .http_client
+            .post(format!("{}/login", &app.address))
+            .json(&wrong_login_body);
+        guesses.spawn(async move { request.send().await.unwrap().status().as_u16() });
+    }
+
+    let mut statuses = Vec::new();
+    while let Some(status) = guesses.join_next().await {
+        statuses.push(status.unwrap());
+    }
+    statuses.sort();
+    assert_eq!(statuses, [401, 401, 401, 429, 429, 429, 429, 429, 429, 429]);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn should_reset_failed_logins_after_successful_login() {
+    let mut settings = test_settings();
+    settings.login_lockout.max_failures = 2;
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    // Failures are only counted while they are consecutive
+    for _ in 0..3 {
+        let response = app.post_login(&wrong_login_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
@@ -0,0 +1,228 @@
+use auth_service::{
+    domain::Email,
+    routes::{MagicLinkResponse, TwoFactorAuthResponse},
+    ErrorResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+
+async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn request_link(app: &TestApp, email: &str) -> MagicLinkResponse {
+    let body = serde_json::json!({ "email": email });
+    let response = app.post_magic_link(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<MagicLinkResponse>()
+        .await
+        .expect("Could not deserialize response body to MagicLinkResponse")
+}
+
+async fn login_link_token(app: &TestApp, email: &str) -> String {
+    app.wait_for_email_to(email, "Your login link").await;
+    app.link_token_sent_to(email).await
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_answer_the_same_for_unknown_email() {
+    let app = TestApp::new().await;
+    let known_email = get_random_email();
+    let unknown_email = get_random_email();
+    signup(&app, &known_email, false).await;
+
+    let known = request_link(&app, &known_email).await;
+    let unknown = request_link(&app, &unknown_email).await;
+    assert_eq!(known, unknown);
+
+    // Links are sent in the background, so wait for the known one first
+    app.wait_for_email_to(&known_email, "Your login link").await;
+    let unknown_email = Email::parse(unknown_email).unwrap();
+    assert!(app
+        .email_client
+        .last_email_to(&unknown_email)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_email() {
+    let app = TestApp::new().await;
+
+    let body = serde_json::json!({ "email": "not-an-email" });
+    let response = app.post_magic_link(&body).await;
+    assert_error(response, 400, "Invalid credentials").await;
+}
+
+#[tokio::test]
+async fn should_log_in_with_link() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    request_link(&app, &email).await;
+
+    let token = login_link_token(&app, &email).await;
+    let response = app
+        .post_verify_magic_link(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == app.settings.jwt.refresh_cookie_name));
+
+    let verify_body = serde_json::json!({ "token": auth_cookie.value() });
+    let response = app.post_verify_token(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_is_reused_or_invalid() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    request_link(&app, &email).await;
+
+    let token = login_link_token(&app, &email).await;
+    let body = serde_json::json!({ "token": token });
+    let response = app.post_verify_magic_link(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let tokens = [token, "not-a-token".to_owned()];
+
+    for token in tokens {
+        let body = serde_json::json!({ "token": token });
+        let response = app.post_verify_magic_link(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            token
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_is_not_signed_by_this_service() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    request_link(&app, &email).await;
+    let token = login_link_token(&app, &email).await;
+
+    let header = jsonwebtoken::decode_header(&token).expect("Failed to read token header");
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_aud = false;
+    let claims = jsonwebtoken::decode::<serde_json::Value>(
+        &token,
+        &DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .expect("Failed to read token claims")
+    .claims;
+
+    // The same claims signed with another key
+    let resigned = jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(b"other"))
+        .expect("Failed to sign token");
+    // Another address with the original signature
+    let mut other_claims = claims.clone();
+    other_claims["sub"] = serde_json::json!(get_random_email());
+    let parts = token.split('.').collect::<Vec<_>>();
+    let payload = URL_SAFE_NO_PAD.encode(other_claims.to_string());
+    let tampered = format!("{}.{}.{}", parts[0], payload, parts[2]);
+    // The single-use token without the signature around it
+    let bare = claims["jti"].as_str().unwrap().to_owned();
+
+    for forged in [resigned, tampered, bare] {
+        let body = serde_json::json!({ "token": forged });
+        let response = app.post_verify_magic_link(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            forged
+        );
+    }
+
+    // None of them used up the link
+    let body = serde_json::json!({ "token": token });
+    let response = app.post_verify_magic_link(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_206_if_2fa_enabled() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+    request_link(&app, &email).await;
+
+    let token = login_link_token(&app, &email).await;
+    let response = app
+        .post_verify_magic_link(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert!(response
+        .cookies()
+        .all(|cookie| cookie.name() != app.settings.jwt.cookie_name));
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(json_body.message, "2FA required".to_owned());
+}
+
+#[tokio::test]
+async fn should_return_403_from_login_if_password_login_disabled() {
+    let mut settings = test_settings();
+    settings.password_login_enabled = false;
+    let app = TestApp::with_settings(settings).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_error(response, 403, "Password login is disabled").await;
+
+    request_link(&app, &email).await;
+    let token = login_link_token(&app, &email).await;
+    let response = app
+        .post_verify_magic_link(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
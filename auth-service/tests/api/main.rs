@@ -1,7 +1,21 @@
+mod change_password;
+mod delete_account;
 mod helpers;
-mod root;
+mod jwks;
 mod login;
 mod logout;
+mod magic_link;
+mod oauth;
+mod passkey;
+mod rate_limit;
+mod reauthenticate;
+mod recovery_codes;
+mod refresh;
+mod reset_password;
+mod root;
+mod rotate_signing_key;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
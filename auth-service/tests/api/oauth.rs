@@ -0,0 +1,456 @@
+use auth_service::{
+    routes::{
+        ConsentDecisionResponse, ConsentResponse, RegisterOAuthClientResponse, TokenResponse,
+        VerifyTokenResponse,
+    },
+    ErrorResponse,
+};
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+
+const ADMIN_API_TOKEN: &str = "test-admin-token-0123456789abcdef";
+const KEY_ENCRYPTION_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+const REDIRECT_URI: &str = "http://localhost:8000/callback";
+// The example from RFC 7636, appendix B
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+async fn app_with_admin_token() -> TestApp {
+    let mut settings = test_settings();
+    settings.admin_api_token = Some(ADMIN_API_TOKEN.to_owned());
+    settings.jwt.key_encryption_key = Some(KEY_ENCRYPTION_KEY.to_owned());
+    TestApp::with_settings(settings).await
+}
+
+async fn register_client(app: &TestApp, public: bool) -> RegisterOAuthClientResponse {
+    let body = serde_json::json!({
+        "name": "Test App",
+        "redirectUris": [REDIRECT_URI],
+        "public": public
+    });
+    let response = app.post_register_oauth_client(&body, ADMIN_API_TOKEN).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<RegisterOAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to RegisterOAuthClientResponse")
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+fn authorize_params(client_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "response_type": "code",
+        "client_id": client_id,
+        "redirect_uri": REDIRECT_URI,
+        "code_challenge": CODE_CHALLENGE,
+        "code_challenge_method": "S256",
+        "state": "xyz"
+    })
+}
+
+fn location(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .expect("No location header")
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn query_param(uri: &str, name: &str) -> Option<String> {
+    Url::parse(uri)
+        .expect("Invalid redirect URI")
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+// Allow the client as the logged in user, and return the code it is sent
+async fn approve(app: &TestApp, client_id: &str) -> String {
+    let mut body = authorize_params(client_id);
+    body["approved"] = serde_json::json!(true);
+    let response = app.post_consent(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let redirect_uri = response
+        .json::<ConsentDecisionResponse>()
+        .await
+        .expect("Could not deserialize response body to ConsentDecisionResponse")
+        .redirect_uri;
+    assert!(redirect_uri.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&redirect_uri, "state").as_deref(), Some("xyz"));
+
+    query_param(&redirect_uri, "code").expect("No code in redirect URI")
+}
+
+fn token_form(client: &RegisterOAuthClientResponse, code: &str) -> Vec<(&'static str, String)> {
+    let mut form = vec![
+        ("grant_type", "authorization_code".to_owned()),
+        ("code", code.to_owned()),
+        ("redirect_uri", REDIRECT_URI.to_owned()),
+        ("code_verifier", CODE_VERIFIER.to_owned()),
+        ("client_id", client.client_id.clone()),
+    ];
+    if let Some(client_secret) = &client.client_secret {
+        form.push(("client_secret", client_secret.clone()));
+    }
+    form
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_register_clients() {
+    let app = app_with_admin_token().await;
+
+    let confidential = register_client(&app, false).await;
+    assert!(confidential.client_secret.is_some());
+
+    // Public clients can't keep a secret, so they don't get one
+    let public = register_client(&app, true).await;
+    assert!(public.client_secret.is_none());
+    assert_ne!(confidential.client_id, public.client_id);
+}
+
+#[tokio::test]
+async fn should_reject_invalid_client_registrations() {
+    let app = app_with_admin_token().await;
+
+    let test_cases = [
+        serde_json::json!({ "name": "", "redirectUris": [REDIRECT_URI] }),
+        serde_json::json!({ "name": "Test App", "redirectUris": [] }),
+        serde_json::json!({ "name": "Test App", "redirectUris": ["/callback"] }),
+        serde_json::json!({ "name": "Test App", "redirectUris": [format!("{}#frag", REDIRECT_URI)] }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app
+            .post_register_oauth_client(test_case, ADMIN_API_TOKEN)
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    let body = serde_json::json!({ "name": "Test App", "redirectUris": [REDIRECT_URI] });
+    let response = app
+        .post_register_oauth_client(&body, "wrong-admin-token")
+        .await;
+    assert_error(response, 401, "Invalid auth token").await;
+}
+
+#[tokio::test]
+async fn should_not_redirect_for_unknown_client_or_redirect_uri() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+
+    let mut unknown_client = authorize_params(&uuid::Uuid::new_v4().to_string());
+    let response = app.get_authorize(&unknown_client).await;
+    assert_error(response, 401, "invalid_client").await;
+
+    unknown_client["client_id"] = serde_json::json!("not-a-client-id");
+    let response = app.get_authorize(&unknown_client).await;
+    assert_error(response, 401, "invalid_client").await;
+
+    let mut wrong_redirect = authorize_params(&client.client_id);
+    wrong_redirect["redirect_uri"] = serde_json::json!("http://evil.example.com/callback");
+    let response = app.get_authorize(&wrong_redirect).await;
+    assert_error(response, 400, "invalid_request").await;
+}
+
+#[tokio::test]
+async fn should_redirect_invalid_requests_back_to_client() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+
+    let test_cases = [
+        (
+            "code_challenge_method",
+            serde_json::json!("plain"),
+            "invalid_request",
+        ),
+        (
+            "code_challenge",
+            serde_json::json!("too-short"),
+            "invalid_request",
+        ),
+        (
+            "response_type",
+            serde_json::json!("token"),
+            "unsupported_response_type",
+        ),
+    ];
+
+    for (field, value, error) in test_cases {
+        let mut params = authorize_params(&client.client_id);
+        params[field] = value;
+
+        let response = app.get_authorize(&params).await;
+        assert_eq!(
+            response.status().as_u16(),
+            303,
+            "Failed for input: {:?}",
+            field
+        );
+
+        let location = location(&response);
+        assert!(
+            location.starts_with(REDIRECT_URI),
+            "Failed for input: {:?}",
+            field
+        );
+        assert_eq!(query_param(&location, "error").as_deref(), Some(error));
+        assert_eq!(query_param(&location, "state").as_deref(), Some("xyz"));
+    }
+}
+
+#[tokio::test]
+async fn should_send_users_who_are_not_logged_in_to_the_ui() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    let params = authorize_params(&client.client_id);
+
+    let response = app.get_authorize(&params).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(location(&response).starts_with("/?authorize="));
+
+    let response = app.get_consent(&params).await;
+    assert_error(response, 401, "login_required").await;
+}
+
+#[tokio::test]
+async fn should_exchange_code_for_access_token_after_consent() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    // Users who haven't allowed the client yet are asked first
+    let response = app
+        .get_authorize(&authorize_params(&client.client_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(location(&response).starts_with("/?authorize="));
+
+    let response = app.get_consent(&authorize_params(&client.client_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<ConsentResponse>()
+            .await
+            .expect("Could not deserialize response body to ConsentResponse")
+            .client_name,
+        "Test App"
+    );
+
+    let code = approve(&app, &client.client_id).await;
+    let response = app.post_token(&token_form(&client, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .headers()
+            .get(reqwest::header::CACHE_CONTROL)
+            .unwrap(),
+        "no-store"
+    );
+
+    let token = response
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+    assert_eq!(token.token_type, "Bearer");
+    assert_eq!(token.expires_in, app.settings.jwt.token_ttl_seconds);
+
+    let verify_token_body = serde_json::json!({
+        "token": token.access_token,
+        "audience": client.client_id,
+    });
+    let response = app.post_verify_token(&verify_token_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<VerifyTokenResponse>()
+            .await
+            .expect("Could not deserialize response body to VerifyTokenResponse")
+            .email,
+        email
+    );
+
+    // Access tokens are only for the client, not for the other services
+    let verify_token_body = serde_json::json!({ "token": token.access_token });
+    let response = app.post_verify_token(&verify_token_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_skip_consent_once_allowed() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    signup_and_login(&app, &get_random_email()).await;
+    approve(&app, &client.client_id).await;
+
+    let response = app
+        .get_authorize(&authorize_params(&client.client_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = location(&response);
+    assert!(location.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&location, "state").as_deref(), Some("xyz"));
+
+    let code = query_param(&location, "code").expect("No code in redirect URI");
+    let response = app.post_token(&token_form(&client, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_send_access_denied_when_user_denies() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let mut body = authorize_params(&client.client_id);
+    body["approved"] = serde_json::json!(false);
+    let response = app.post_consent(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let redirect_uri = response
+        .json::<ConsentDecisionResponse>()
+        .await
+        .expect("Could not deserialize response body to ConsentDecisionResponse")
+        .redirect_uri;
+    assert_eq!(
+        query_param(&redirect_uri, "error").as_deref(),
+        Some("access_denied")
+    );
+    assert!(query_param(&redirect_uri, "code").is_none());
+
+    // Denying is not remembered
+    let response = app
+        .get_authorize(&authorize_params(&client.client_id))
+        .await;
+    assert!(location(&response).starts_with("/?authorize="));
+}
+
+#[tokio::test]
+async fn should_only_accept_code_once() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = approve(&app, &client.client_id).await;
+    let response = app.post_token(&token_form(&client, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_token(&token_form(&client, &code)).await;
+    assert_error(response, 400, "invalid_grant").await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_token_requests() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    let other_client = register_client(&app, false).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let test_cases = [
+        (
+            "code_verifier",
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXl".to_owned(),
+            400,
+            "invalid_grant",
+        ),
+        (
+            "redirect_uri",
+            format!("{}/other", REDIRECT_URI),
+            400,
+            "invalid_grant",
+        ),
+        (
+            "grant_type",
+            "client_credentials".to_owned(),
+            400,
+            "unsupported_grant_type",
+        ),
+        (
+            "client_secret",
+            other_client.client_secret.clone().unwrap(),
+            401,
+            "invalid_client",
+        ),
+    ];
+
+    for (field, value, status, error) in test_cases {
+        let code = approve(&app, &client.client_id).await;
+        let mut form = token_form(&client, &code);
+        form.retain(|(name, _)| *name != field);
+        form.push((field, value));
+
+        let response = app.post_token(&form).await;
+        assert_error(response, status, error).await;
+    }
+
+    // Codes are only for the client they were issued to
+    let code = approve(&app, &client.client_id).await;
+    let response = app.post_token(&token_form(&other_client, &code)).await;
+    assert_error(response, 400, "invalid_grant").await;
+}
+
+#[tokio::test]
+async fn should_accept_basic_auth_and_public_clients() {
+    let app = app_with_admin_token().await;
+    let client = register_client(&app, false).await;
+    let public = register_client(&app, true).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = approve(&app, &client.client_id).await;
+    let mut form = token_form(&client, &code);
+    form.retain(|(name, _)| *name != "client_id" && *name != "client_secret");
+    let response = app
+        .http_client
+        .post(format!("{}/token", &app.address))
+        .basic_auth(&client.client_id, client.client_secret.as_ref())
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute token");
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Public clients only prove themselves with the code verifier
+    let code = approve(&app, &public.client_id).await;
+    let response = app.post_token(&token_form(&public, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,296 @@
+use auth_service::{
+    routes::{
+        FinishPasskeyRegistrationResponse, StartPasskeyLoginResponse,
+        StartPasskeyRegistrationResponse,
+    },
+    ErrorResponse,
+};
+use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+use webauthn_rs::prelude::{PublicKeyCredential, Url};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// A software authenticator that claims to have verified the user, as a
+// platform authenticator would after a fingerprint or PIN
+fn authenticator() -> WebauthnAuthenticator<SoftPasskey> {
+    WebauthnAuthenticator::new(SoftPasskey::new(true))
+}
+
+// The page the browser would run the ceremony on
+fn origin(app: &TestApp) -> Url {
+    Url::parse(&app.settings.public_url).unwrap()
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn register_passkey(app: &TestApp, authenticator: &mut WebauthnAuthenticator<SoftPasskey>) {
+    let start_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_passkey_register_start(&start_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let start = response
+        .json::<StartPasskeyRegistrationResponse>()
+        .await
+        .expect("Could not deserialize response body to StartPasskeyRegistrationResponse");
+    let credential = authenticator
+        .do_registration(origin(app), start.options)
+        .expect("Authenticator failed to register");
+
+    let finish_body = serde_json::json!({
+        "challengeId": start.challenge_id,
+        "credential": credential,
+    });
+    let response = app.post_passkey_register_finish(&finish_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<FinishPasskeyRegistrationResponse>()
+            .await
+            .expect("Could not deserialize response body to FinishPasskeyRegistrationResponse")
+            .message,
+        "Passkey registered"
+    );
+}
+
+async fn start_login(app: &TestApp, email: &str) -> StartPasskeyLoginResponse {
+    let start_body = serde_json::json!({ "email": email });
+    let response = app.post_passkey_login_start(&start_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<StartPasskeyLoginResponse>()
+        .await
+        .expect("Could not deserialize response body to StartPasskeyLoginResponse")
+}
+
+fn sign_in(
+    app: &TestApp,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+    start: StartPasskeyLoginResponse,
+) -> PublicKeyCredential {
+    authenticator
+        .do_authentication(origin(app), start.options)
+        .expect("Authenticator failed to sign in")
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let start_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_passkey_register_start(&start_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let start_body = serde_json::json!({ "password": "wrong-password" });
+    let response = app.post_passkey_register_start(&start_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_log_in_with_registered_passkey() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let mut authenticator = authenticator();
+
+    signup_and_login(&app, &email).await;
+    register_passkey(&app, &mut authenticator).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let start = start_login(&app, &email).await;
+    let challenge_id = start.challenge_id.clone();
+    let credential = sign_in(&app, &mut authenticator, start);
+
+    let finish_body = serde_json::json!({
+        "challengeId": challenge_id,
+        "credential": credential,
+    });
+    let response = app.post_passkey_login_finish(&finish_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.refresh_cookie_name)
+        .expect("No refresh cookie found");
+    assert!(!refresh_cookie.value().is_empty());
+
+    let verify_body = serde_json::json!({ "token": auth_cookie.value() });
+    let response = app.post_verify_token(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_challenge_is_reused() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let mut authenticator = authenticator();
+
+    signup_and_login(&app, &email).await;
+    register_passkey(&app, &mut authenticator).await;
+
+    let start = start_login(&app, &email).await;
+    let challenge_id = start.challenge_id.clone();
+    let credential = sign_in(&app, &mut authenticator, start);
+
+    let finish_body = serde_json::json!({
+        "challengeId": challenge_id,
+        "credential": credential,
+    });
+    let response = app.post_passkey_login_finish(&finish_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_passkey_login_finish(&finish_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_credential_answers_another_challenge() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let mut authenticator = authenticator();
+
+    signup_and_login(&app, &email).await;
+    register_passkey(&app, &mut authenticator).await;
+
+    let first = start_login(&app, &email).await;
+    let second = start_login(&app, &email).await;
+    let credential = sign_in(&app, &mut authenticator, first);
+
+    let finish_body = serde_json::json!({
+        "challengeId": second.challenge_id,
+        "credential": credential,
+    });
+    let response = app.post_passkey_login_finish(&finish_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_not_tell_whether_address_has_account() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let mut authenticator = authenticator();
+
+    signup_and_login(&app, &email).await;
+    register_passkey(&app, &mut authenticator).await;
+    let credential = sign_in(&app, &mut authenticator, start_login(&app, &email).await);
+
+    let user_without_passkeys = get_random_email();
+    signup_and_login(&app, &user_without_passkeys).await;
+
+    for email in [user_without_passkeys, get_random_email()] {
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            let start_body = serde_json::json!({ "email": email });
+            responses.push(app.post_passkey_login_start(&start_body).await);
+        }
+        let status = responses[0].status().as_u16();
+        assert_eq!(
+            responses[1].status().as_u16(),
+            status,
+            "Failed for input: {:?}",
+            email
+        );
+
+        // Some addresses look like users without passkeys
+        if status == 401 {
+            continue;
+        }
+        assert_eq!(status, 200, "Failed for input: {:?}", email);
+
+        let mut starts = Vec::new();
+        for response in responses {
+            starts.push(
+                response
+                    .json::<StartPasskeyLoginResponse>()
+                    .await
+                    .expect("Could not deserialize response body to StartPasskeyLoginResponse"),
+            );
+        }
+
+        // The same made-up credentials are offered every time
+        let credential_ids = |start: &StartPasskeyLoginResponse| {
+            start
+                .options
+                .public_key
+                .allow_credentials
+                .iter()
+                .map(|allowed| allowed.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(!credential_ids(&starts[0]).is_empty());
+        assert_eq!(credential_ids(&starts[0]), credential_ids(&starts[1]));
+
+        // The challenge can't be answered
+        let finish_body = serde_json::json!({
+            "challengeId": starts[0].challenge_id,
+            "credential": credential,
+        });
+        let response = app.post_passkey_login_finish(&finish_body).await;
+        assert_error(response, 401, "Incorrect credentials").await;
+    }
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let mut authenticator = authenticator();
+
+    signup_and_login(&app, &email).await;
+    register_passkey(&app, &mut authenticator).await;
+
+    let start_body = serde_json::json!({ "email": "not-an-email" });
+    let response = app.post_passkey_login_start(&start_body).await;
+    assert_error(response, 400, "Invalid credentials").await;
+
+    let start = start_login(&app, &email).await;
+    let credential = sign_in(&app, &mut authenticator, start);
+
+    let finish_body = serde_json::json!({
+        "challengeId": "not-a-uuid",
+        "credential": credential,
+    });
+    let response = app.post_passkey_login_finish(&finish_body).await;
+    assert_error(response, 400, "Invalid credentials").await;
+}
@@ -0,0 +1,142 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::domain::RateLimitParams;
+
+#[tokio::test]
+async fn should_return_429_if_client_exceeds_rate_limit() {
+    let mut settings = test_settings();
+    settings.rate_limit = RateLimitParams {
+        burst: 3,
+        per_minute: 1,
+    };
+    let app = TestApp::with_settings(settings).await;
+
+    let login_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+    });
+
+    // The limit is shared between /signup, /login and /verify-2fa
+    let signup_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    for _ in 0..2 {
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let verify_2fa_body = serde_json::json!({
+        "email": get_random_email(),
+        "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+        "2FACode": "123456",
+    });
+
+    let test_cases = [
+        app.post_signup(&signup_body).await,
+        app.post_login(&login_body).await,
+        app.post_verify_2fa(&verify_2fa_body).await,
+    ];
+
+    for response in test_cases {
+        assert_eq!(
+            response.status().as_u16(),
+            429,
+            "Failed for input: {:?}",
+            response.url().path()
+        );
+
+        // One request is refilled per minute
+        let retry_after: u64 = response
+            .headers()
+            .get("retry-after")
+            .expect("No Retry-After header")
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 60);
+    }
+
+    // Other routes are not limited
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_limit_clients_behind_trusted_proxies_separately() {
+    let mut settings = test_settings();
+    settings.rate_limit = RateLimitParams {
+        burst: 1,
+        per_minute: 1,
+    };
+    settings.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    let app = TestApp::with_settings(settings).await;
+
+    let login_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+    });
+
+    let test_cases = [
+        ("203.0.113.1", 401),
+        ("203.0.113.2", 401),
+        ("203.0.113.1", 429),
+    ];
+
+    for (client_ip, status) in test_cases {
+        let response = app
+            .http_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", client_ip)
+            .json(&login_body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "Failed for input: {:?}",
+            client_ip
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_ignore_forwarded_ips_from_untrusted_peers() {
+    let mut settings = test_settings();
+    settings.rate_limit = RateLimitParams {
+        burst: 1,
+        per_minute: 1,
+    };
+    let app = TestApp::with_settings(settings).await;
+
+    let login_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+    });
+
+    // A client can't get a fresh bucket by making up an address
+    let test_cases = [("203.0.113.1", 401), ("203.0.113.2", 429)];
+
+    for (client_ip, status) in test_cases {
+        let response = app
+            .http_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", client_ip)
+            .json(&login_body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "Failed for input: {:?}",
+            client_ip
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use auth_service::{
+    domain::Email, routes::TwoFactorAuthResponse, utils::two_fa::TwoFactorMethod, ErrorResponse,
+};
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+
+async fn app_without_password_login() -> TestApp {
+    let mut settings = test_settings();
+    settings.password_login_enabled = false;
+    TestApp::with_settings(settings).await
+}
+
+// Sign up without a password and log in with an emailed link
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.wait_for_email_to(email, "Your login link").await;
+    let token = app.link_token_sent_to(email).await;
+    let response = app
+        .post_verify_magic_link(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+// Start a challenge and answer it with the emailed code
+async fn confirmation(app: &TestApp, email: &str) -> serde_json::Value {
+    let response = app.post_reauthenticate().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(json_body.two_factor_method, TwoFactorMethod::Email);
+
+    serde_json::json!({
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": sent_code(app, email).await,
+    })
+}
+
+async fn sent_code(app: &TestApp, email: &str) -> String {
+    let email = Email::parse(email.to_owned()).unwrap();
+    let content = app
+        .email_client
+        .last_email_to(&email)
+        .await
+        .unwrap()
+        .content;
+    // The code is the last word of the email
+    content.rsplit(' ').next().unwrap().to_owned()
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_password_login_enabled() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_reauthenticate().await;
+    assert_error(response, 400, "Invalid credentials").await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = app_without_password_login().await;
+
+    let response = app.post_reauthenticate().await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_confirm_changes_with_emailed_code() {
+    let app = app_without_password_login().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let body = confirmation(&app, &email).await;
+    let response = app.post_recovery_codes(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Each code confirms a single change
+    let response = app.post_recovery_codes(&body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+
+    let body = confirmation(&app, &email).await;
+    let response = app.post_totp_enroll(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = confirmation(&app, &email).await;
+    let response = app.post_passkey_register_start(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut body = confirmation(&app, &email).await;
+    body["newPassword"] = serde_json::json!("password123");
+    let response = app.post_change_password(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_unchanged() {
+    let app = app_without_password_login().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut body = confirmation(&app, &email).await;
+    body["newPassword"] = serde_json::json!("password123");
+    let response = app.post_change_password(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Without a current password, the new one is compared with the stored one
+    let mut body = confirmation(&app, &email).await;
+    body["newPassword"] = serde_json::json!("password123");
+    let response = app.post_change_password(&body).await;
+    assert_error(
+        response,
+        400,
+        "New password must be different from the current password",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_password_sent() {
+    let app = app_without_password_login().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_error(response, 403, "Password login is disabled").await;
+
+    let change_password_body = serde_json::json!({
+        "currentPassword": "password123",
+        "newPassword": "password456",
+    });
+    let response = app.post_change_password(&change_password_body).await;
+    assert_error(response, 403, "Password login is disabled").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_code_incorrect() {
+    let app = app_without_password_login().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut body = confirmation(&app, &email).await;
+    let code = body["2FACode"].as_str().unwrap().to_owned();
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    body["2FACode"] = serde_json::json!(wrong_code);
+
+    let response = app.post_totp_enroll(&body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_delete_account_with_emailed_code() {
+    let app = app_without_password_login().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    // Without a password, deletion starts its own challenge
+    let response = app.post_delete_account(&serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let delete_body = serde_json::json!({
+        "loginAttemptId": login_attempt_id,
+        "2FACode": sent_code(&app, &email).await,
+    });
+    let response = app.post_delete_account(&delete_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
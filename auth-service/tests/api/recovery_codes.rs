@@ -0,0 +1,182 @@
+use auth_service::{
+    domain::{Email, RECOVERY_CODE_COUNT},
+    routes::{signup::SignupResponse, RecoveryCodesResponse, TwoFactorAuthResponse},
+    ErrorResponse,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up with 2FA and log in, returning the recovery codes from signup
+async fn signup_and_login(app: &TestApp, email: &str) -> Vec<String> {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .unwrap()
+        .recovery_codes
+        .expect("No recovery codes");
+
+    let login_attempt_id = start_login(app, email).await;
+    let email = Email::parse(email.to_owned()).unwrap();
+    let code = app
+        .email_client
+        .last_email_to(&email)
+        .await
+        .unwrap()
+        .content;
+    let verify_2fa_body = serde_json::json!({
+        "email": email.as_ref(),
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    recovery_codes
+}
+
+// Log in with the password, returning the login attempt ID
+async fn start_login(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id
+}
+
+async fn login_with_recovery_code(app: &TestApp, email: &str, recovery_code: &str) -> u16 {
+    let login_attempt_id = start_login(app, email).await;
+    let verify_2fa_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": recovery_code,
+    });
+    app.post_verify_2fa(&verify_2fa_body)
+        .await
+        .status()
+        .as_u16()
+}
+
+async fn remaining(app: &TestApp) -> usize {
+    let response = app.get_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse");
+    assert_eq!(body.recovery_codes, None);
+    body.remaining
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let response = app.get_recovery_codes().await;
+    assert_error(response, 400, "Missing auth token").await;
+
+    let regenerate_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_recovery_codes(&regenerate_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let regenerate_body = serde_json::json!({ "password": "wrong-password" });
+    let response = app.post_recovery_codes(&regenerate_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_accept_each_recovery_code_once() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let recovery_codes = signup_and_login(&app, &random_email).await;
+    assert_eq!(remaining(&app).await, RECOVERY_CODE_COUNT);
+
+    let status = login_with_recovery_code(&app, &random_email, &recovery_codes[0]).await;
+    assert_eq!(status, 200);
+    assert_eq!(remaining(&app).await, RECOVERY_CODE_COUNT - 1);
+
+    let status = login_with_recovery_code(&app, &random_email, &recovery_codes[0]).await;
+    assert_eq!(status, 401);
+
+    // Codes can be typed in upper case and without the dash
+    let typed_code = recovery_codes[1].replace('-', "").to_uppercase();
+    let status = login_with_recovery_code(&app, &random_email, &typed_code).await;
+    assert_eq!(status, 200);
+    assert_eq!(remaining(&app).await, RECOVERY_CODE_COUNT - 2);
+}
+
+#[tokio::test]
+async fn should_return_401_if_login_attempt_id_is_incorrect() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let recovery_codes = signup_and_login(&app, &random_email).await;
+
+    start_login(&app, &random_email).await;
+    let verify_2fa_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+        "2FACode": recovery_codes[0],
+    });
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The code was not used up
+    assert_eq!(remaining(&app).await, RECOVERY_CODE_COUNT);
+}
+
+#[tokio::test]
+async fn should_replace_recovery_codes_when_regenerated() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let old_codes = signup_and_login(&app, &random_email).await;
+    let status = login_with_recovery_code(&app, &random_email, &old_codes[0]).await;
+    assert_eq!(status, 200);
+
+    let regenerate_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_recovery_codes(&regenerate_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<RecoveryCodesResponse>().await.unwrap();
+    assert_eq!(body.remaining, RECOVERY_CODE_COUNT);
+    let new_codes = body.recovery_codes.expect("No recovery codes");
+    assert_eq!(new_codes.len(), RECOVERY_CODE_COUNT);
+    assert_eq!(remaining(&app).await, RECOVERY_CODE_COUNT);
+
+    let status = login_with_recovery_code(&app, &random_email, &old_codes[1]).await;
+    assert_eq!(status, 401);
+
+    let status = login_with_recovery_code(&app, &random_email, &new_codes[0]).await;
+    assert_eq!(status, 200);
+}
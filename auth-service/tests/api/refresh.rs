@@ -0,0 +1,255 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::{Email, RefreshToken, RefreshTokenRecord},
+    ErrorResponse,
+};
+
+// Sign up and log in a user without 2FA, returning the refresh token
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    get_refresh_token(app, &response)
+}
+
+fn get_refresh_token(app: &TestApp, response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.refresh_cookie_name)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned()
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_set_refresh_cookie_on_login() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    assert_eq!(refresh_cookie.path(), Some("/refresh"));
+    assert!(refresh_cookie.http_only());
+    assert!(refresh_cookie.same_site_strict());
+    assert!(refresh_cookie.max_age().is_some());
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_token_if_valid_refresh_cookie() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let refresh_token = signup_and_login(&app, &random_email).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    let new_refresh_token = get_refresh_token(&app, &response);
+    assert_ne!(new_refresh_token, refresh_token);
+
+    // The rotated token can be used in turn
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let verify_token_body = serde_json::json!({
+        "token": auth_cookie.value(),
+    });
+
+    let response = app.post_verify_token(&verify_token_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let response = app.post_refresh().await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_refresh_token() {
+    let app = TestApp::new().await;
+
+    // Malformed, and well-formed but never issued
+    let test_cases = [
+        "invalid".to_owned(),
+        RefreshToken::default().as_ref().to_owned(),
+    ];
+
+    for test_case in test_cases {
+        app.set_refresh_token(&test_case);
+
+        let response = app.post_refresh().await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_refresh_token_expired() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let token = RefreshToken::default();
+    let record = RefreshTokenRecord {
+        email: Email::parse(random_email).unwrap(),
+        family_id: "expired-family".to_owned(),
+        expires_at: chrono::Utc::now().timestamp() - 1,
+    };
+
+    app.refresh_token_store
+        .write()
+        .await
+        .add_token(token.clone(), record)
+        .await
+        .unwrap();
+
+    app.set_refresh_token(token.as_ref());
+    let response = app.post_refresh().await;
+    assert_error(response, 401, "Invalid auth token").await;
+}
+
+#[tokio::test]
+async fn should_revoke_token_family_if_refresh_token_reused() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let stolen_token = signup_and_login(&app, &random_email).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let current_token = get_refresh_token(&app, &response);
+
+    // Replay the token that was already rotated
+    app.set_refresh_token(&stolen_token);
+    let response = app.post_refresh().await;
+    assert_error(response, 401, "Invalid auth token").await;
+
+    // The legitimate client's token was revoked along with it
+    app.set_refresh_token(&current_token);
+    let response = app.post_refresh().await;
+    assert_error(response, 401, "Invalid auth token").await;
+}
+
+#[tokio::test]
+async fn should_keep_other_sessions_if_refresh_token_reused() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let stolen_token = signup_and_login(&app, &random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    let other_session_token = get_refresh_token(&app, &response);
+
+    app.set_refresh_token(&stolen_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.set_refresh_token(&stolen_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.set_refresh_token(&other_session_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_refresh_tokens_on_logout() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let refresh_token = signup_and_login(&app, &random_email).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.set_refresh_token(&refresh_token);
+    let response = app.post_refresh().await;
+    assert_error(response, 401, "Invalid auth token").await;
+}
+
+#[tokio::test]
+async fn should_keep_other_sessions_on_logout() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let other_session_token = signup_and_login(&app, &random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let logged_out_token = get_refresh_token(&app, &response);
+
+    // Logs out the second session, whose auth cookie is now in the jar
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.set_refresh_token(&logged_out_token);
+    let response = app.post_refresh().await;
+    assert_error(response, 401, "Invalid auth token").await;
+
+    app.set_refresh_token(&other_session_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,262 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{
+    domain::{EmailToken, LockoutParams, RateLimitParams},
+    routes::ResetPasswordResponse,
+};
+
+// Sign up and log in a user without 2FA, returning the auth and refresh tokens
+async fn signup_and_login(app: &TestApp, email: &str) -> (String, String) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie = |name: &str| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .expect("Cookie not found")
+            .value()
+            .to_owned()
+    };
+    (
+        cookie(&app.settings.jwt.cookie_name),
+        cookie(&app.settings.jwt.refresh_cookie_name),
+    )
+}
+
+async fn request_reset_token(app: &TestApp, email: &str) -> String {
+    let forgot_body = serde_json::json!({ "email": email });
+    let response = app.post_forgot_password(&forgot_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.wait_for_email_to(email, "Reset your password").await;
+    app.link_token_sent_to(email).await
+}
+
+#[tokio::test]
+async fn should_return_200_and_update_password() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let token = request_reset_token(&app, &random_email).await;
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<ResetPasswordResponse>()
+            .await
+            .expect("Could not deserialize response body to ResetPasswordResponse")
+            .message,
+        "Password updated".to_owned()
+    );
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "newpassword123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_existing_sessions() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let (auth_token, refresh_token) = signup_and_login(&app, &random_email).await;
+
+    let token = request_reset_token(&app, &random_email).await;
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.set_refresh_token(&refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_without_sending_if_account_does_not_exist() {
+    let app = TestApp::new().await;
+
+    let forgot_body = serde_json::json!({ "email": get_random_email() });
+    let response = app.post_forgot_password(&forgot_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(app.email_client.sent_emails().await.is_empty());
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_password() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let token = request_reset_token(&app, &random_email).await;
+
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "short",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The link can still be used with a valid password
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    // A verification link is not a reset link
+    let verification_token = app.link_token_sent_to(&random_email).await;
+    let unknown_token = EmailToken::default();
+
+    let test_cases = [
+        "invalid",
+        unknown_token.as_ref(),
+        verification_token.as_str(),
+    ];
+
+    for token in test_cases {
+        let reset_body = serde_json::json!({
+            "token": token,
+            "password": "newpassword123",
+        });
+        let response = app.post_reset_password(&reset_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            reset_body
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_is_reused() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let token = request_reset_token(&app, &random_email).await;
+
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_expired() {
+    let mut settings = test_settings();
+    settings.password_reset.token_ttl_seconds = 1;
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let token = request_reset_token(&app, &random_email).await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_unlock_account_after_reset() {
+    let mut settings = test_settings();
+    settings.login_lockout = LockoutParams {
+        max_failures: 2,
+        lockout_seconds: 60,
+    };
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+    for _ in 0..2 {
+        app.post_login(&login_body).await;
+    }
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let token = request_reset_token(&app, &random_email).await;
+    let reset_body = serde_json::json!({
+        "token": token,
+        "password": "newpassword123",
+    });
+    let response = app.post_reset_password(&reset_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "newpassword123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_429_if_reset_requests_are_throttled() {
+    let mut settings = test_settings();
+    settings.password_reset.request_limit = RateLimitParams {
+        burst: 1,
+        per_minute: 1,
+    };
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+
+    let forgot_body = serde_json::json!({ "email": random_email });
+    let response = app.post_forgot_password(&forgot_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_forgot_password(&forgot_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{
+    domain::KeyEncryptionKey, routes::RotateSigningKeyResponse, settings::JwtAlgorithm,
+    utils::jwt_keys::JwtKey, ErrorResponse,
+};
+use chrono::Utc;
+use jsonwebtoken::{jwk::JwkSet, Algorithm};
+
+const ADMIN_API_TOKEN: &str = "test-admin-token-0123456789abcdef";
+const KEY_ENCRYPTION_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+
+async fn app_with_admin_token(algorithm: JwtAlgorithm) -> TestApp {
+    let mut settings = test_settings();
+    settings.admin_api_token = Some(ADMIN_API_TOKEN.to_owned());
+    settings.jwt.key_encryption_key = Some(KEY_ENCRYPTION_KEY.to_owned());
+    settings.jwt.algorithm = algorithm;
+    settings.jwt.private_key_path = Some("tests/fixtures/ed25519_private_key.pem".to_owned());
+    // Rotated keys sign one sync interval after they are added
+    settings.jwt.key_sync_interval_seconds = 1;
+    TestApp::with_settings(settings).await
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    login(app, email).await
+}
+
+async fn login(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+// Log in until the token is signed with `kid`, which takes a key sync or two
+async fn login_until_kid(app: &TestApp, email: &str, kid: &str) -> String {
+    for _ in 0..20 {
+        let token = login(app, email).await;
+        if get_kid(&token) == kid {
+            return token;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    panic!("Tokens were never signed with {}", kid);
+}
+
+fn get_kid(token: &str) -> String {
+    jsonwebtoken::decode_header(token)
+        .expect("Invalid token header")
+        .kid
+        .expect("Token has no kid")
+}
+
+async fn rotate(app: &TestApp) -> RotateSigningKeyResponse {
+    let response = app.post_rotate_signing_key(Some(ADMIN_API_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<RotateSigningKeyResponse>()
+        .await
+        .expect("Could not deserialize response body to RotateSigningKeyResponse")
+}
+
+#[tokio::test]
+async fn should_return_400_if_admin_token_missing() {
+    let app = app_with_admin_token(JwtAlgorithm::HS256).await;
+
+    let response = app.post_rotate_signing_key(None).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing auth token".to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_token_incorrect() {
+    let app = app_with_admin_token(JwtAlgorithm::HS256).await;
+    let app_without_admin_token = TestApp::new().await;
+
+    let test_cases = [
+        (&app, "wrong-token"),
+        (&app, "test-admin-token-0123456789abcdeF"),
+        (&app_without_admin_token, ADMIN_API_TOKEN),
+    ];
+
+    for (app, token) in test_cases {
+        let response = app.post_rotate_signing_key(Some(token)).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            token
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_sign_new_tokens_with_new_key_and_accept_old_tokens() {
+    let app = app_with_admin_token(JwtAlgorithm::HS256).await;
+
+    let random_email = get_random_email();
+    let old_token = signup_and_login(&app, &random_email).await;
+    let old_kid = get_kid(&old_token);
+
+    let response = rotate(&app).await;
+    assert_ne!(response.kid, old_kid);
+    assert!(response.activates_at > Utc::now().timestamp() - 1);
+
+    let new_token = login_until_kid(&app, &random_email, &response.kid).await;
+
+    for token in [old_token, new_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "Failed for input: {:?}",
+            token
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_publish_rotated_keys_before_they_sign() {
+    let app = app_with_admin_token(JwtAlgorithm::EdDSA).await;
+
+    let random_email = get_random_email();
+    let old_kid = get_kid(&signup_and_login(&app, &random_email).await);
+
+    let first = rotate(&app).await;
+    let second = rotate(&app).await;
+
+    let jwks = app
+        .get_jwks()
+        .await
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    // Nothing has expired yet, so every key is published, including the
+    // ones that do not sign yet
+    for kid in [&old_kid, &first.kid, &second.kid] {
+        assert!(jwks.find(kid).is_some(), "Failed for input: {:?}", kid);
+    }
+
+    // The latest key wins once both are active
+    login_until_kid(&app, &random_email, &second.kid).await;
+}
+
+#[tokio::test]
+async fn should_load_keys_rotated_on_other_replicas() {
+    let app = app_with_admin_token(JwtAlgorithm::EdDSA).await;
+
+    let random_email = get_random_email();
+    let old_token = signup_and_login(&app, &random_email).await;
+
+    // Another replica stores a key, without telling this one
+    let key_encryption_key = KeyEncryptionKey::parse(KEY_ENCRYPTION_KEY).unwrap();
+    let record = JwtKey::generate_record(
+        Algorithm::EdDSA,
+        Utc::now().timestamp(),
+        &key_encryption_key,
+    )
+    .expect("Failed to generate key");
+    let kid = record.kid.clone();
+    app.jwt_key_store
+        .write()
+        .await
+        .add_key(record)
+        .await
+        .expect("Failed to store key");
+
+    let new_token = login_until_kid(&app, &random_email, &kid).await;
+
+    for token in [old_token, new_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "Failed for input: {:?}",
+            token
+        );
+    }
+}
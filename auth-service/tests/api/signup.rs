@@ -1,5 +1,5 @@
-use auth_service::{routes::signup::SignupResponse, ErrorResponse};
-use crate::helpers::{get_random_email, TestApp};
+use auth_service::{domain::RECOVERY_CODE_COUNT, routes::signup::SignupResponse, ErrorResponse};
+use crate::helpers::{get_random_email, test_settings, TestApp};
 
 #[tokio::test]
 async fn signup_test() {
@@ -40,18 +40,16 @@ async fn should_return_201_if_valid_input() {
         "Failed to create user with valid input"
     );
 
-    let expected_response = SignupResponse {
-        message: "User created successfully!".to_owned(),
-    };
-
     // Assert that we are getting the correct response body!
-    assert_eq!(
-        response
-            .json::<SignupResponse>()
-            .await
-            .expect("Could not deserialize response body to UserBody"),
-        expected_response
-    );
+    let response_body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
+    assert_eq!(response_body.message, "User created successfully!");
+
+    // Users with 2FA get their recovery codes
+    let recovery_codes = response_body.recovery_codes.expect("No recovery codes");
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
 }
 
 #[tokio::test]
@@ -86,6 +84,11 @@ async fn should_return_400_if_invalid_input() {
             "password": "",
             "requires2FA": true
         }),
+        // Only optional with password login turned off
+        serde_json::json!({
+            "email": "test@example.com",
+            "requires2FA": true
+        }),
     ];
 
     for test_case in test_cases.iter() {
@@ -145,3 +148,18 @@ async fn should_return_409_if_email_already_exists() {
         "User already exists".to_owned()
     );
 }
+
+#[tokio::test]
+async fn should_return_201_without_password_if_password_login_disabled() {
+    let mut settings = test_settings();
+    settings.password_login_enabled = false;
+    let app = TestApp::with_settings(settings).await;
+
+    let request_body = serde_json::json!({
+        "email": get_random_email(),
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&request_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
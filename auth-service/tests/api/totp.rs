@@ -0,0 +1,332 @@
+use auth_service::{
+    domain::{Email, RECOVERY_CODE_COUNT},
+    routes::{ConfirmTotpResponse, EnrollTotpResponse, TwoFactorAuthResponse},
+    utils::two_fa::TwoFactorMethod,
+    ErrorResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use totp_rs::TOTP;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn enroll(app: &TestApp) -> TOTP {
+    let enroll_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_totp_enroll(&enroll_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<EnrollTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+    TOTP::from_url(body.otpauth_uri).expect("Invalid otpauth URI")
+}
+
+// The authenticator app's code for the current time
+fn current_code(totp: &TOTP) -> String {
+    totp.generate_current().unwrap()
+}
+
+// The code the app shows next. Each time step's code is only accepted once,
+// so this one still works after the current one was used.
+fn next_code(totp: &TOTP) -> String {
+    totp.generate(chrono::Utc::now().timestamp() as u64 + totp.step)
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let enroll_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_totp_enroll(&enroll_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+
+    let confirm_body = serde_json::json!({ "code": "123456" });
+    let response = app.post_totp_confirm(&confirm_body).await;
+    assert_error(response, 400, "Missing auth token").await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let enroll_body = serde_json::json!({ "password": "wrong-password" });
+    let response = app.post_totp_enroll(&enroll_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_return_otpauth_uri_and_qr_code() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+
+    let enroll_body = serde_json::json!({ "password": "password123" });
+    let response = app.post_totp_enroll(&enroll_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<EnrollTotpResponse>().await.unwrap();
+
+    let totp = TOTP::from_url(&body.otpauth_uri).unwrap();
+    assert_eq!(
+        totp.issuer.as_deref(),
+        Some(app.settings.totp_issuer.as_str())
+    );
+    assert_eq!(totp.account_name, random_email);
+    assert_eq!(totp.get_secret_base32(), body.secret);
+
+    let qr_code = STANDARD
+        .decode(body.qr_code)
+        .expect("QR code is not base64");
+    assert!(qr_code.starts_with(b"\x89PNG"));
+}
+
+#[tokio::test]
+async fn should_return_400_if_nothing_to_confirm() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let test_cases = [
+        // Not enrolled yet
+        serde_json::json!({ "code": "123456" }),
+        serde_json::json!({ "code": "12345" }),
+        serde_json::json!({ "code": "abcdef" }),
+    ];
+
+    for confirm_body in test_cases {
+        let response = app.post_totp_confirm(&confirm_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            confirm_body
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_code_is_incorrect() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+    let totp = enroll(&app).await;
+
+    let code = current_code(&totp);
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let confirm_body = serde_json::json!({ "code": wrong_code });
+    let response = app.post_totp_confirm(&confirm_body).await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[tokio::test]
+async fn should_require_authenticator_code_at_login_once_confirmed() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let totp = enroll(&app).await;
+
+    // Enrolling alone changes nothing
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let confirm_body = serde_json::json!({ "code": current_code(&totp) });
+    let response = app.post_totp_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<ConfirmTotpResponse>().await.unwrap();
+    assert_eq!(body.message, "Authenticator app enabled");
+    // Confirming turned 2FA on
+    assert_eq!(
+        body.recovery_codes.map(|codes| codes.len()),
+        Some(RECOVERY_CODE_COUNT)
+    );
+
+    let emails_sent = app.email_client.sent_emails().await.len();
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    assert_eq!(body.two_factor_method, TwoFactorMethod::Totp);
+    // The code comes from the app, so nothing is emailed
+    assert_eq!(app.email_client.sent_emails().await.len(), emails_sent);
+
+    let verify_2fa_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": body.login_attempt_id,
+        "2FACode": next_code(&totp),
+    });
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_authenticator_code_reused() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let totp = enroll(&app).await;
+    let confirm_code = current_code(&totp);
+    let confirm_body = serde_json::json!({ "code": confirm_code });
+    let response = app.post_totp_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+
+    // Neither the code used to confirm the app, nor one used to log in,
+    // nor a code from before them is accepted again
+    let login_code = next_code(&totp);
+    let now = chrono::Utc::now().timestamp() as u64;
+    let test_cases = [
+        (confirm_code.clone(), 401),
+        (totp.generate(now - totp.step), 401),
+        (login_code.clone(), 200),
+        (login_code, 401),
+        (confirm_code, 401),
+    ];
+
+    let mut login_attempt_id = body.login_attempt_id;
+    for (code, status) in test_cases {
+        let verify_2fa_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        });
+        let response = app.post_verify_2fa(&verify_2fa_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "Failed for input: {:?}",
+            code
+        );
+
+        // A successful login ends the challenge, so start another one
+        if status == 200 {
+            let response = app.post_login(&login_body).await;
+            assert_eq!(response.status().as_u16(), 206);
+            let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+            login_attempt_id = body.login_attempt_id;
+        }
+    }
+}
+
+#[tokio::test]
+async fn should_accept_codes_one_step_off() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    signup_and_login(&app, &random_email).await;
+    let totp = enroll(&app).await;
+    let now = chrono::Utc::now().timestamp() as u64;
+    let confirm_body = serde_json::json!({ "code": totp.generate(now - totp.step) });
+    let response = app.post_totp_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Codes from before the one used to confirm are not accepted any more
+    let test_cases = [
+        (now - 3 * totp.step, 401),
+        (now + 3 * totp.step, 401),
+        (now + totp.step, 200),
+    ];
+
+    for (time, status) in test_cases {
+        let login_body = serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        });
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 206);
+        let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+
+        let verify_2fa_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": body.login_attempt_id,
+            "2FACode": totp.generate(time),
+        });
+        let response = app.post_verify_2fa(&verify_2fa_body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "Failed for input: {:?}",
+            time
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_keep_emailing_codes_to_2fa_users_until_confirmed() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    assert_eq!(body.two_factor_method, TwoFactorMethod::Email);
+
+    let email = Email::parse(random_email.clone()).unwrap();
+    let code = app
+        .email_client
+        .last_email_to(&email)
+        .await
+        .unwrap()
+        .content;
+    let verify_2fa_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": body.login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    enroll(&app).await;
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    assert_eq!(body.two_factor_method, TwoFactorMethod::Email);
+}
@@ -1,9 +1,315 @@
-use crate::helpers::TestApp;
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{
+    domain::{Email, LoginAttemptId, TwoFACode},
+    routes::TwoFactorAuthResponse,
+    ErrorResponse,
+};
+
+// Sign up a user with 2FA enabled and start a login attempt for them
+async fn signup_and_login_with_2fa(app: &TestApp, email: &str) -> TwoFactorAuthResponse {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    login_with_2fa(app, email).await
+}
+
+async fn login_with_2fa(app: &TestApp, email: &str) -> TwoFactorAuthResponse {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+}
+
+async fn get_stored_code(app: &TestApp, email: &str) -> (LoginAttemptId, TwoFACode) {
+    app.two_fa_code_store
+        .read()
+        .await
+        .get_code(&Email::parse(email.to_owned()).unwrap())
+        .await
+        .expect("No 2FA code stored for login attempt")
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let two_fa_code = TwoFACode::default();
+
+    let test_cases = [
+        serde_json::json!({
+            "loginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": two_fa_code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "2FACode": two_fa_code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id.as_ref(),
+        }),
+        serde_json::json!({}),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            422,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let two_fa_code = TwoFACode::default();
+
+    let test_cases = [
+        serde_json::json!({
+            "email": "invalid-email",
+            "loginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": two_fa_code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": "invalid-login-attempt-id",
+            "2FACode": two_fa_code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": "12345",
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid credentials".to_owned()
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_credentials() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, code) = get_stored_code(&app, &random_email).await;
+
+    // Pick a code that is guaranteed to differ from the stored one
+    let wrong_code = if code.as_ref() == "000000" {
+        "111111"
+    } else {
+        "000000"
+    };
+
+    let test_cases = [
+        serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": login_response.login_attempt_id,
+            "2FACode": code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": LoginAttemptId::default().as_ref(),
+            "2FACode": code.as_ref(),
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_response.login_attempt_id,
+            "2FACode": wrong_code,
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            test_case
+        );
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Incorrect credentials".to_owned()
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_old_code() {
+    // Call login twice. Then, attempt to call verify-2fa with the 2FA code from the first login request. This should fail.
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let first_login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, first_code) = get_stored_code(&app, &random_email).await;
+
+    let second_login_response = login_with_2fa(&app, &random_email).await;
+    assert_ne!(
+        first_login_response.login_attempt_id,
+        second_login_response.login_attempt_id
+    );
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": first_login_response.login_attempt_id,
+        "2FACode": first_code.as_ref(),
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_if_correct_code() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, code) = get_stored_code(&app, &random_email).await;
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_response.login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.cookie_name)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.jwt.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    assert_eq!(refresh_cookie.path(), Some("/refresh"));
+}
 
 #[tokio::test]
-async fn verify_f2a_test() {
+async fn should_return_401_if_same_code_twice() {
     let app = TestApp::new().await;
-    let response = app.post_verify_2fa().await;
+
+    let random_email = get_random_email();
+    let login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, code) = get_stored_code(&app, &random_email).await;
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_response.login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
     assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_2fa(&request_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_after_too_many_wrong_codes() {
+    let mut settings = test_settings();
+    settings.two_fa_max_failed_attempts = 3;
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+    let login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, code) = get_stored_code(&app, &random_email).await;
+    let wrong_code = if code.as_ref() == "000000" {
+        "111111"
+    } else {
+        "000000"
+    };
+
+    for _ in 0..3 {
+        let request_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_response.login_attempt_id,
+            "2FACode": wrong_code,
+        });
+
+        let response = app.post_verify_2fa(&request_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The right code comes too late, the login has to start again
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_response.login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
+    assert_eq!(response.status().as_u16(), 401);
 }
 
+#[tokio::test]
+async fn should_return_401_if_code_expired() {
+    let mut settings = test_settings();
+    settings.two_fa_code_ttl_seconds = 1;
+    let app = TestApp::with_settings(settings).await;
+
+    let random_email = get_random_email();
+    let login_response = signup_and_login_with_2fa(&app, &random_email).await;
+    let (_, code) = get_stored_code(&app, &random_email).await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_response.login_attempt_id,
+        "2FACode": code.as_ref(),
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,189 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::{
+    domain::{EmailToken, RateLimitParams},
+    routes::VerifyEmailResponse,
+    ErrorResponse,
+};
+
+// Email verification is turned off for most tests
+async fn app_requiring_verification() -> TestApp {
+    let mut settings = test_settings();
+    settings.email_verification.required = true;
+    TestApp::with_settings(settings).await
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn should_return_403_if_email_not_verified() {
+    let app = app_requiring_verification().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Email address not verified".to_owned()
+    );
+
+    // A wrong password still gets the usual answer
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_and_allow_login_after_following_link() {
+    let app = app_requiring_verification().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    let token = app.link_token_sent_to(&random_email).await;
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<VerifyEmailResponse>()
+            .await
+            .expect("Could not deserialize response body to VerifyEmailResponse")
+            .message,
+        "Email address verified".to_owned()
+    );
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_is_reused() {
+    let app = app_requiring_verification().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    let token = app.link_token_sent_to(&random_email).await;
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let app = app_requiring_verification().await;
+
+    let unknown_token = EmailToken::default();
+    let test_cases = ["", "invalid", unknown_token.as_ref()];
+
+    for token in test_cases {
+        let response = app.get_verify_email(token).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            token
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_expired() {
+    let mut settings = test_settings();
+    settings.email_verification.token_ttl_seconds = 1;
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    let token = app.link_token_sent_to(&random_email).await;
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_send_new_link_on_resend() {
+    let app = app_requiring_verification().await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+    let first_token = app.link_token_sent_to(&random_email).await;
+
+    let emails_sent = app.email_client.sent_emails().await.len();
+    let resend_body = serde_json::json!({ "email": random_email });
+    let response = app.post_resend_verification(&resend_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.wait_for_sent_emails(emails_sent + 1).await;
+    let second_token = app.link_token_sent_to(&random_email).await;
+    assert_ne!(first_token, second_token);
+
+    let response = app.get_verify_email(&second_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Verified accounts do not get another email
+    let emails_sent = app.email_client.sent_emails().await.len();
+    let response = app.post_resend_verification(&resend_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.email_client.sent_emails().await.len(), emails_sent);
+}
+
+#[tokio::test]
+async fn should_return_200_without_sending_if_account_does_not_exist() {
+    let app = app_requiring_verification().await;
+
+    let resend_body = serde_json::json!({ "email": get_random_email() });
+    let response = app.post_resend_verification(&resend_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(app.email_client.sent_emails().await.is_empty());
+}
+
+#[tokio::test]
+async fn should_return_429_if_resend_is_throttled() {
+    let mut settings = test_settings();
+    settings.email_verification.resend_limit = RateLimitParams {
+        burst: 2,
+        per_minute: 1,
+    };
+    let app = TestApp::with_settings(settings).await;
+    let random_email = get_random_email();
+    signup(&app, &random_email).await;
+
+    let resend_body = serde_json::json!({ "email": random_email });
+    for _ in 0..2 {
+        let response = app.post_resend_verification(&resend_body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app.post_resend_verification(&resend_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+
+    // The limit is per address
+    let resend_body = serde_json::json!({ "email": get_random_email() });
+    let response = app.post_resend_verification(&resend_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}